@@ -10,11 +10,10 @@ use sdmmc_protocol::{
     sdmmc::{
         HostInfo, MmcData, MmcDataFlag, MmcIos, MmcSignalVoltage, SdmmcCmd, SdmmcError,
         capability::{
-            MMC_CAP_4_BIT_DATA, MMC_TIMING_LEGACY, MMC_TIMING_SD_HS, MMC_TIMING_UHS, MMC_VDD_31_32,
-            MMC_VDD_32_33, MMC_VDD_33_34,
+            MMC_CAP_4_BIT_DATA, MMC_TIMING_LEGACY, MMC_TIMING_MMC_DDR52, MMC_TIMING_MMC_HS,
+            MMC_TIMING_SD_HS, MMC_TIMING_UHS, MMC_VDD_31_32, MMC_VDD_32_33, MMC_VDD_33_34,
         },
-        mmc_struct::{MmcBusWidth, MmcTiming},
-        sd::Sdcard,
+        mmc_struct::{MmcBusWidth, MmcTiming, MmcTuning},
     },
     sdmmc_os::{Sleep, process_wait_unreliable},
     sdmmc_traits::{SdmmcHardware, SdmmcOps},
@@ -329,7 +328,7 @@ impl SdmmcMesonHardware {
     fn meson_frequency(timing: MmcTiming) -> Result<u32, SdmmcError> {
         let freq: u32 = match timing {
             MmcTiming::Legacy => 25000000,
-            MmcTiming::MmcHs => 26000000,
+            MmcTiming::MmcHs => 52000000,
             MmcTiming::SdHs => 50000000,
             MmcTiming::UhsSdr12 => 25000000,
             MmcTiming::UhsSdr25 => 50000000,
@@ -415,7 +414,7 @@ impl SdmmcOps for SdmmcMesonHardware {
 
     fn sdmmc_execute_tuning(
         &mut self,
-        memory: *mut [u8; 64],
+        tuning: &MmcTuning,
         sleep: &mut dyn Sleep,
     ) -> Result<(), SdmmcError> {
         let mut current_delay: u32 = 0;
@@ -460,7 +459,7 @@ impl SdmmcOps for SdmmcMesonHardware {
                 tried_lowest_delay,
                 tried_highest_delay
             );
-            let res: Result<(), SdmmcError> = Sdcard::sdcard_test_tuning(self, sleep, memory);
+            let res: Result<(), SdmmcError> = tuning.send_tuning_block(self, sleep);
 
             match res {
                 Ok(_) => {
@@ -573,7 +572,10 @@ impl SdmmcOps for SdmmcMesonHardware {
         }
 
         // If the pervious timing mode is using ddr, then disable ddr
-        if self.timing == MmcTiming::UhsDdr50 || self.timing == MmcTiming::MmcDdr52 {
+        if self.timing == MmcTiming::UhsDdr50
+            || self.timing == MmcTiming::MmcDdr52
+            || self.timing == MmcTiming::MmcHs400
+        {
             self.meson_enable_ddr(false);
         }
 
@@ -594,6 +596,7 @@ impl SdmmcOps for SdmmcMesonHardware {
         unsafe {
             meson_mmc_cfg = ptr::read_volatile(&self.register.cfg);
         }
+        meson_mmc_cfg &= !CFG_BUS_WIDTH_MASK;
         match bus_width {
             MmcBusWidth::Width1 => meson_mmc_cfg |= CFG_BUS_WIDTH_1,
             MmcBusWidth::Width4 => meson_mmc_cfg |= CFG_BUS_WIDTH_4,
@@ -605,6 +608,13 @@ impl SdmmcOps for SdmmcMesonHardware {
         Ok(())
     }
 
+    /// The Meson host does not have a dedicated data strobe input,
+    /// so HS400 is backed by sampling on both clock edges in DDR mode
+    fn sdmmc_config_strobe(&mut self, enable: bool) -> Result<(), SdmmcError> {
+        self.meson_enable_ddr(enable);
+        Ok(())
+    }
+
     fn sdmmc_read_datalanes(&self) -> Result<u8, SdmmcError> {
         unsafe {
             // Read the status register
@@ -756,6 +766,22 @@ impl SdmmcOps for SdmmcMesonHardware {
         return Ok(());
     }
 
+    fn sdmmc_abort_data(&mut self) -> Result<(), SdmmcError> {
+        // Stop the descriptor chain like meson_mmc_irq() in Linux does on errors
+        unsafe {
+            ptr::write_volatile(&mut self.register.start, 0);
+        }
+
+        self.meson_wait_desc_stop()?;
+
+        // Clear the status left by the aborted request
+        unsafe {
+            ptr::write_volatile(&mut self.register.status, STATUS_MASK);
+        }
+
+        Ok(())
+    }
+
     fn sdmmc_host_reset(&mut self) -> Result<MmcIos, SdmmcError> {
         Self::meson_reset(self);
 
@@ -779,7 +805,12 @@ impl SdmmcHardware for SdmmcMesonHardware {
         max_block_per_req: MAX_BLOCK_PER_TRANSFER,
         // On odroid c4, the operating voltage is default to 3.3V
         vdd: (MMC_VDD_33_34 | MMC_VDD_32_33 | MMC_VDD_31_32),
-        host_capability: MMC_TIMING_LEGACY | MMC_TIMING_SD_HS | MMC_TIMING_UHS | MMC_CAP_4_BIT_DATA,
+        host_capability: MMC_TIMING_LEGACY
+            | MMC_TIMING_SD_HS
+            | MMC_TIMING_UHS
+            | MMC_TIMING_MMC_HS
+            | MMC_TIMING_MMC_DDR52
+            | MMC_CAP_4_BIT_DATA,
     };
 
     /// This function should be CONST!!! It is just Rust does not support it yet
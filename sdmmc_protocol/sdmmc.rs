@@ -2,7 +2,10 @@
 // SPDX-License-Identifier: BSD-2-Clause
 
 pub mod capability;
+pub mod cmdq;
+pub mod emmc;
 pub mod mmc_struct;
+pub mod rpmb;
 pub mod sd;
 
 mod constant;
@@ -10,40 +13,84 @@ mod constant;
 use core::{
     future::Future,
     pin::Pin,
-    sync::atomic::Ordering,
+    sync::atomic::{AtomicBool, Ordering},
     task::{Context, Poll, Waker},
 };
 
 use capability::{
-    MMC_CAP_4_BIT_DATA, MMC_EMPTY_CAP, MMC_TIMING_LEGACY, MMC_TIMING_SD_HS, MMC_TIMING_UHS_DDR50,
-    MMC_TIMING_UHS_SDR12, MMC_TIMING_UHS_SDR25, MMC_TIMING_UHS_SDR50, MMC_TIMING_UHS_SDR104,
-    SdcardCapability,
+    EMmcCapability, MMC_CAP_4_BIT_DATA, MMC_CAP_8_BIT_DATA, MMC_CAP_BUS_WIDTH_TEST, MMC_EMPTY_CAP,
+    MMC_TIMING_LEGACY, MMC_TIMING_MMC_DDR52, MMC_TIMING_MMC_HS, MMC_TIMING_MMC_HS200,
+    MMC_TIMING_MMC_HS400, MMC_TIMING_SD_HS, MMC_TIMING_UHS_DDR50, MMC_TIMING_UHS_SDR12,
+    MMC_TIMING_UHS_SDR25, MMC_TIMING_UHS_SDR50, MMC_TIMING_UHS_SDR104, SdcardCapability,
 };
+use cmdq::{CMDQ_QUEUE_STATUS_TIMEOUT_MS, CmdqQueue, CmdqRequest};
 use constant::{
-    MMC_CMD_ALL_SEND_CID, MMC_CMD_APP_CMD, MMC_CMD_ERASE, MMC_CMD_GO_IDLE_STATE,
+    EXT_CSD_BKOPS_AUTO_EN, EXT_CSD_BKOPS_EN, EXT_CSD_BKOPS_MANUAL_EN, EXT_CSD_BKOPS_START,
+    EXT_CSD_BOOT_ACK, EXT_CSD_BOOT_BUS_CONDITIONS, EXT_CSD_BOOT_PART_ENABLE_SHIFT, EXT_CSD_BOOT_WP,
+    EXT_CSD_BOOT_WP_B_PERM_WP_DIS, EXT_CSD_BOOT_WP_B_PERM_WP_EN, EXT_CSD_BOOT_WP_B_PERM_WP_SEC_SEL,
+    EXT_CSD_BOOT_WP_B_PWR_WP_DIS, EXT_CSD_BOOT_WP_B_PWR_WP_EN, EXT_CSD_BOOT_WP_B_PWR_WP_SEC_SEL,
+    EXT_CSD_BOOT_WP_B_SEC_WP_SEL, EXT_CSD_BUS_WIDTH, EXT_CSD_BUS_WIDTH_1, EXT_CSD_BUS_WIDTH_4,
+    EXT_CSD_BUS_WIDTH_8, EXT_CSD_BUS_WIDTH_STROBE, EXT_CSD_CACHE_CTRL, EXT_CSD_CMDQ_MODE_EN,
+    EXT_CSD_DDR_BUS_WIDTH_4, EXT_CSD_DDR_BUS_WIDTH_8, EXT_CSD_DRV_STR_SHIFT,
+    EXT_CSD_ERASE_GROUP_DEF, EXT_CSD_FFU_INSTALL, EXT_CSD_FLUSH_CACHE, EXT_CSD_HPI_IMPL_CMD12,
+    EXT_CSD_HPI_MGMT, EXT_CSD_HPI_SUPPORT, EXT_CSD_HS_TIMING, EXT_CSD_MODE_CONFIG,
+    EXT_CSD_MODE_CONFIG_FFU, EXT_CSD_MODE_CONFIG_NORMAL, EXT_CSD_MODE_OPERATION_CODES,
+    EXT_CSD_PART_CONFIG, EXT_CSD_PART_CONFIG_ACC_MASK, EXT_CSD_PARTITION_SETTING_COMPLETE,
+    EXT_CSD_PARTITION_SETTING_COMPLETED, EXT_CSD_POWER_OFF_LONG, EXT_CSD_POWER_OFF_NOTIFICATION,
+    EXT_CSD_POWER_OFF_SHORT, EXT_CSD_POWER_ON, EXT_CSD_SANITIZE_START, EXT_CSD_SEC_SANITIZE,
+    EXT_CSD_SIZE, EXT_CSD_TIMING_BC, EXT_CSD_TIMING_HS, EXT_CSD_TIMING_HS200, EXT_CSD_TIMING_HS400,
+    EXT_CSD_US_PERM_WP_DIS, EXT_CSD_US_PERM_WP_EN, EXT_CSD_US_PWR_WP_DIS, EXT_CSD_US_PWR_WP_EN,
+    EXT_CSD_USER_WP, MMC_CMD_ALL_SEND_CID, MMC_CMD_APP_CMD, MMC_CMD_ARG_SQS,
+    MMC_CMD_CLR_WRITE_PROT, MMC_CMD_CMDQ_TASK_MGMT, MMC_CMD_ERASE, MMC_CMD_ERASE_GROUP_END,
+    MMC_CMD_ERASE_GROUP_START, MMC_CMD_EXECUTE_READ_TASK, MMC_CMD_EXECUTE_WRITE_TASK,
+    MMC_CMD_GO_IDLE_STATE, MMC_CMD_QUEUED_TASK_ADDRESS, MMC_CMD_QUEUED_TASK_PARAMS,
     MMC_CMD_READ_MULTIPLE_BLOCK, MMC_CMD_READ_SINGLE_BLOCK, MMC_CMD_SELECT_CARD, MMC_CMD_SEND_CSD,
-    MMC_CMD_SET_BLOCK_COUNT, MMC_CMD_STOP_TRANSMISSION, MMC_CMD_WRITE_MULTIPLE_BLOCK,
-    MMC_CMD_WRITE_SINGLE_BLOCK, OCR_BUSY, OCR_HCS, OCR_S18R, SD_CMD_APP_SEND_OP_COND,
-    SD_CMD_APP_SET_BUS_WIDTH, SD_CMD_ERASE_WR_BLK_END, SD_CMD_ERASE_WR_BLK_START,
-    SD_CMD_SEND_IF_COND, SD_CMD_SEND_RELATIVE_ADDR, SD_CMD_SWITCH_FUNC, SD_CMD_SWITCH_UHS18V,
-    SD_ERASE_ARG, SD_SWITCH_FUNCTION_GROUP_ONE, SD_SWITCH_FUNCTION_GROUP_ONE_CHECK_SDHS,
-    SD_SWITCH_FUNCTION_GROUP_ONE_CHECK_UHS_DDR50, SD_SWITCH_FUNCTION_GROUP_ONE_CHECK_UHS_SDR12,
-    SD_SWITCH_FUNCTION_GROUP_ONE_CHECK_UHS_SDR25, SD_SWITCH_FUNCTION_GROUP_ONE_CHECK_UHS_SDR50,
-    SD_SWITCH_FUNCTION_GROUP_ONE_CHECK_UHS_SDR104, SD_SWITCH_FUNCTION_GROUP_ONE_SET_LEGACY,
-    SD_SWITCH_FUNCTION_GROUP_ONE_SET_SDHS, SD_SWITCH_FUNCTION_GROUP_ONE_SET_UHS_DDR50,
-    SD_SWITCH_FUNCTION_GROUP_ONE_SET_UHS_SDR12, SD_SWITCH_FUNCTION_GROUP_ONE_SET_UHS_SDR25,
-    SD_SWITCH_FUNCTION_GROUP_ONE_SET_UHS_SDR50, SD_SWITCH_FUNCTION_GROUP_ONE_SET_UHS_SDR104,
-    SD_SWITCH_FUNCTION_SELECTION_GROUP_ONE,
+    MMC_CMD_SEND_OP_COND, MMC_CMD_SEND_STATUS, MMC_CMD_SEND_WRITE_PROT,
+    MMC_CMD_SEND_WRITE_PROT_TYPE, MMC_CMD_SET_BLOCK_COUNT, MMC_CMD_SET_BLOCKLEN,
+    MMC_CMD_SET_RELATIVE_ADDR, MMC_CMD_SET_WRITE_PROT, MMC_CMD_STOP_TRANSMISSION,
+    MMC_CMD_WRITE_MULTIPLE_BLOCK, MMC_CMD_WRITE_SINGLE_BLOCK, MMC_CMD0_ARG_BOOT_INITIATION,
+    MMC_CMD0_ARG_GO_PRE_IDLE, MMC_CMD23_ARG_PACKED, MMC_CMD23_ARG_REL_WR, MMC_CMDQ_DISCARD_QUEUE,
+    MMC_DISCARD_ARG, MMC_ERASE_ARG, MMC_SECURE_ERASE_ARG, MMC_SECURE_TRIM1_ARG,
+    MMC_SECURE_TRIM2_ARG, MMC_STATE_PRG, MMC_STATE_RCV, MMC_STATUS_CURR_STATE,
+    MMC_STATUS_ERASE_ERRORS, MMC_STATUS_RDY_FOR_DATA, MMC_STATUS_SWITCH_ERROR,
+    MMC_STATUS_WP_ERRORS, MMC_STATUS_WP_VIOLATION, MMC_TRIM_ARG, OCR_2T, OCR_ACCESS_MODE, OCR_BUSY,
+    OCR_HCS, OCR_S18R, OCR_SECTOR_MODE, OCR_VOLTAGE_MASK, SD_CMD_ADDRESS_EXTENSION,
+    SD_CMD_APP_SEND_OP_COND, SD_CMD_APP_SET_BUS_WIDTH, SD_CMD_ERASE_WR_BLK_END,
+    SD_CMD_ERASE_WR_BLK_START, SD_CMD_SEND_IF_COND, SD_CMD_SEND_RELATIVE_ADDR, SD_CMD_SWITCH_FUNC,
+    SD_CMD_SWITCH_UHS18V, SD_ERASE_ARG, SD_SWITCH_FUNCTION_GROUP_ONE,
+    SD_SWITCH_FUNCTION_GROUP_ONE_CHECK_SDHS, SD_SWITCH_FUNCTION_GROUP_ONE_CHECK_UHS_DDR50,
+    SD_SWITCH_FUNCTION_GROUP_ONE_CHECK_UHS_SDR12, SD_SWITCH_FUNCTION_GROUP_ONE_CHECK_UHS_SDR25,
+    SD_SWITCH_FUNCTION_GROUP_ONE_CHECK_UHS_SDR50, SD_SWITCH_FUNCTION_GROUP_ONE_CHECK_UHS_SDR104,
+    SD_SWITCH_FUNCTION_GROUP_ONE_SET_LEGACY, SD_SWITCH_FUNCTION_GROUP_ONE_SET_SDHS,
+    SD_SWITCH_FUNCTION_GROUP_ONE_SET_UHS_DDR50, SD_SWITCH_FUNCTION_GROUP_ONE_SET_UHS_SDR12,
+    SD_SWITCH_FUNCTION_GROUP_ONE_SET_UHS_SDR25, SD_SWITCH_FUNCTION_GROUP_ONE_SET_UHS_SDR50,
+    SD_SWITCH_FUNCTION_GROUP_ONE_SET_UHS_SDR104, SD_SWITCH_FUNCTION_SELECTION_GROUP_ONE,
 };
-use mmc_struct::{BlockTransmissionMode, MmcBusWidth, MmcDevice, MmcState, MmcTiming};
-use sd::{Cid, Csd, Scr, Sdcard};
+use emmc::{
+    BkopsMode, BkopsStatus, EMMC_CACHE_FLUSH_TIMEOUT_MS, EMMC_DEFAULT_CMD6_TIMEOUT_MS,
+    EMMC_DEFAULT_RELATIVE_ADDR, EMMC_SANITIZE_TIMEOUT_MS, EMMC_SPEC_VERSION_CMD23, EMmc,
+    EmmcBootBusConditions, EmmcBootPartition, EmmcBootWriteProtect, EmmcCid, EmmcCsd,
+    EmmcEraseType, EmmcPartition, EmmcPartitionInfo, EmmcPartitionLayout, EmmcPartitionPlan,
+    ExtCsd, FFU_STATUS_DOWNLOAD_ERROR, FFU_STATUS_SUCCESS, FfuInstallation, HealthReport,
+    PACKED_HEADER_SIZE, PackedWriteEntry, WriteProtectType,
+};
+use mmc_struct::{
+    BlockTransmissionMode, MmcBusWidth, MmcDevice, MmcState, MmcTiming, MmcTuning, WriteFlags,
+};
+use rpmb::{
+    RPMB_DATA_SIZE, RPMB_FRAME_SIZE, RPMB_KEY_MAC_SIZE, RPMB_REQ_AUTH_READ, RPMB_REQ_AUTH_WRITE,
+    RPMB_REQ_KEY_PROGRAM, RPMB_REQ_READ_COUNTER, RPMB_REQ_RESULT_READ, RPMB_RESP_AUTH_READ,
+    RPMB_RESP_AUTH_WRITE, RPMB_RESP_KEY_PROGRAM, RPMB_RESP_READ_COUNTER, RPMB_RESULT_AUTH_FAILURE,
+    RpmbFrame, RpmbFrameBuffer,
+};
+use sd::{Cid, Csd, Scr, SdCapacityClass, Sdcard};
 
 pub const SDCARD_DEFAULT_SECTOR_SIZE: u32 = 512;
 
 use crate::{
-    dev_log,
+    dev_log, info,
     sdmmc::mmc_struct::CardInfo,
-    sdmmc_os::{Sleep, VoltageOps},
+    sdmmc_os::{BkopsPolicy, RpmbAuthenticator, Sleep, VoltageOps},
     sdmmc_traits::{SdmmcHardware, SdmmcOps},
 };
 
@@ -62,6 +109,7 @@ pub struct MmcData {
     pub addr: u64,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MmcDataFlag {
     SdmmcDataRead,
     SdmmcDataWrite,
@@ -84,6 +132,16 @@ pub enum SdmmcError {
     ESTOPCMD,
     ENOCARD,
     ECARDINACTIVE,
+    // The RPMB operation result reported by the eMMC, check RPMB_RESULT_* in rpmb.rs
+    ERPMB(u16),
+    // The request is interrupted through HPI, the data written may be incomplete, check emmc_interrupted_write_progress
+    EINTERRUPTED,
+    // Field firmware update is not supported or disabled by FW_CONFIG
+    EFFUUNSUPPORTED,
+    // The field firmware update failed, the value is FFU_STATUS, check FFU_STATUS_* in emmc.rs
+    EFFU(u8),
+    // The blocks being written are write protected
+    EWRITEPROTECTED,
 }
 
 // Define the MMC response flags
@@ -263,7 +321,17 @@ pub struct SdmmcProtocol<T: SdmmcHardware, S: Sleep, V: VoltageOps> {
     /// This mmc device is optional because there may not always be a card in the slot!
     mmc_device: Option<MmcDevice>,
 
-    private_memory: Option<*mut [u8; 64]>,
+    /// How the tuning block is checked when retuning is needed, set by tune_performance
+    tuning: Option<MmcTuning>,
+
+    /// The power of the card is cut by suspend, the card needs to be set up again in resume
+    powered_off: bool,
+
+    /// Tasks submitted through cmdq_submit
+    cmdq: CmdqQueue,
+
+    /// Interrupts the write being executed through HPI once set, check emmc_set_hpi_trigger
+    hpi_trigger: Option<&'static AtomicBool>,
 }
 
 impl<T, S, V> Unpin for SdmmcProtocol<T, S, V>
@@ -284,11 +352,15 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
             voltage_ops,
             mmc_ios: ios,
             mmc_device: None,
-            private_memory: None,
+            tuning: None,
+            powered_off: false,
+            cmdq: CmdqQueue::new(1),
+            hpi_trigger: None,
         })
     }
 
-    fn sdcard_init(&mut self, voltage_switch: bool) -> Result<(), SdmmcError> {
+    /// Return the capacity class of the card reported in the OCR
+    fn sdcard_init(&mut self, voltage_switch: bool) -> Result<SdCapacityClass, SdmmcError> {
         let mut resp: [u32; 4] = [0; 4];
 
         let mut cmd = SdmmcCmd {
@@ -317,13 +389,13 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
             self.hardware
                 .sdmmc_do_request(&mut self.sleep, &cmd, None, &mut resp, 1);
 
-        // If the result is OK and the resp is 0x1AA, the card we are initializing is a SDHC/SDXC
+        // If the result is OK and the resp is 0x1AA, the card we are initializing follows SD version 2.0 or later
         // If the result is error, it is either the voltage not being set up correctly, which mean a bug in hardware layer
-        // or the card is eMMC or legacy sdcard
-        // For now, we only deal with the situation it is a sdcard
+        // or the card is eMMC or a SD version 1.x card, the ACMD41 below tells them apart
         if res.is_ok() && resp[0] != 0x1AA {
             return Err(SdmmcError::EUNSUPPORTEDCARD);
         }
+        let sd_version_2: bool = res.is_ok();
 
         // Uboot define this value to 1000...
         let mut retry: u16 = 1000;
@@ -345,7 +417,7 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
             match res {
                 Ok(_) => {}
                 Err(SdmmcError::ETIMEDOUT) => return Err(SdmmcError::EUNSUPPORTEDCARD),
-                Err(_) => return res,
+                Err(e) => return Err(e),
             }
 
             cmd = SdmmcCmd {
@@ -354,24 +426,37 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
                 cmdarg: 0,
             };
 
-            // Set the HCS bit if version is SD Version 2
-            // Since we are only support SDHC card, the OCR_HCS bit should be supported by the card
-            cmd.cmdarg |= OCR_HCS;
+            // Set the HCS bit if version is SD Version 2, version 1.x cards only support standard capacity
+            // SDUC cards only leave the idle state if the host reports supporting more than 2TB as well
+            if sd_version_2 {
+                cmd.cmdarg |= OCR_HCS | OCR_2T;
+            }
 
             // Right now we deliberately not set XPC bit for maximum compatibility
 
-            // Change this when we decide to support spi or SDSC as well
+            // Change this when we decide to support spi as well
             cmd.cmdarg |= T::HOST_INFO.vdd & 0xff8000;
 
-            if voltage_switch == true && T::HOST_INFO.has_capability(MMC_TIMING_UHS_SDR12) {
+            if voltage_switch == true
+                && sd_version_2
+                && T::HOST_INFO.has_capability(MMC_TIMING_UHS_SDR12)
+            {
                 cmd.cmdarg |= OCR_S18R;
                 // It seems that cards will not respond to commands that have MMC_VDD_165_195 bit set, even if the card supports UHS-I
                 // cmd.cmdarg |= MMC_VDD_165_195;
             }
 
             // Send ACMD41
-            self.hardware
-                .sdmmc_do_request(&mut self.sleep, &cmd, None, &mut resp, 0)?;
+            // eMMC accepts CMD55 but never answers ACMD41, so a timeout here means it is not a sdcard
+            let res = self
+                .hardware
+                .sdmmc_do_request(&mut self.sleep, &cmd, None, &mut resp, 0);
+
+            match res {
+                Ok(_) => {}
+                Err(SdmmcError::ETIMEDOUT) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+                Err(e) => return Err(e),
+            }
 
             dev_log!("OCR: {:08x}\n", resp[0]);
 
@@ -399,7 +484,14 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
             self.mmc_ios.signal_voltage = MmcSignalVoltage::Voltage180;
         }
 
-        Ok(())
+        // CCS and CO2T share the bits with HCS and HO2T, they are only valid after the card is ready
+        if !sd_version_2 || resp[0] & OCR_HCS != OCR_HCS {
+            Ok(SdCapacityClass::Standard)
+        } else if resp[0] & OCR_2T == OCR_2T {
+            Ok(SdCapacityClass::Ultra)
+        } else {
+            Ok(SdCapacityClass::High)
+        }
     }
 
     // Function that is not completed
@@ -419,12 +511,12 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
         // Use labeled block here for better clarification
         // For card initialization, we retry 2 times for each card
         // There could be more complex retry logic implemented in the future
-        let res: Result<(), SdmmcError> = 'sdcard_init: {
+        let res: Result<SdCapacityClass, SdmmcError> = 'sdcard_init: {
             let mut voltage_switch_init: bool = T::HOST_INFO.has_capability(MMC_TIMING_UHS_SDR12);
             let mut init_error: SdmmcError = SdmmcError::EUNSUPPORTEDCARD;
             for _ in 0..CARD_INIT_RETRY {
                 match self.sdcard_init(voltage_switch_init) {
-                    Ok(_) => break 'sdcard_init Ok(()),
+                    Ok(capacity_class) => break 'sdcard_init Ok(capacity_class),
                     Err(SdmmcError::EUNSUPPORTEDCARD) => {
                         break 'sdcard_init Err(SdmmcError::EUNSUPPORTEDCARD);
                     }
@@ -448,25 +540,138 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
             Err(init_error)
         };
 
-        if res.is_ok() {
-            let card: Sdcard = self.setup_sdcard_cont()?;
+        if let Ok(capacity_class) = res {
+            let card: Sdcard = self.setup_sdcard_cont(capacity_class)?;
             self.mmc_device = Some(MmcDevice::Sdcard(card));
             return Ok(());
         }
 
-        // Unsupported card
-        {
-            // If the result is error, it is either the voltage not being set up correctly, which mean a bug in hardware layer
-            // or the card is eMMC or legacy sdcard
-            // For now, we only deal with the situation it is a sdcard
-            // TODO: Implement setup for eMMC and legacy sdcard(SDSC) here
-            dev_log!(
-                "Driver right now only support SDHC/SDXC card, please check if you are running this driver on SDIO/SDSC/EMMC card!\n"
-            );
-            res
+        if let Err(SdmmcError::EUNSUPPORTEDCARD) = res {
+            dev_log!("Card does not respond to sdcard init sequence, trying eMMC\n");
+        } else {
+            return res.map(|_| ());
+        }
+
+        let res: Result<bool, SdmmcError> = 'emmc_init: {
+            let mut init_error: SdmmcError = SdmmcError::EUNSUPPORTEDCARD;
+            for _ in 0..CARD_INIT_RETRY {
+                match self.emmc_init() {
+                    Ok(sector_mode) => break 'emmc_init Ok(sector_mode),
+                    Err(SdmmcError::EUNSUPPORTEDCARD) => {
+                        break 'emmc_init Err(SdmmcError::EUNSUPPORTEDCARD);
+                    }
+                    Err(e) => {
+                        init_error = e;
+                        if let Some(ref mut voltage_ops) = self.voltage_ops {
+                            voltage_ops.card_power_cycling()?;
+                        }
+                        self.mmc_ios = self.hardware.sdmmc_host_reset()?;
+                    }
+                }
+            }
+            Err(init_error)
+        };
+
+        match res {
+            Ok(sector_mode) => {
+                let emmc: EMmc = self.setup_emmc_cont(sector_mode)?;
+                self.mmc_device = Some(MmcDevice::EMmc(emmc));
+                Ok(())
+            }
+            // Unsupported card
+            Err(e) => {
+                // If the result is error, it is either the voltage not being set up correctly, which mean a bug in hardware layer
+                // or the card is SDIO
+                dev_log!(
+                    "Driver right now only support SDSC/SDHC/SDXC/SDUC/eMMC card, please check if you are running this driver on SDIO card!\n"
+                );
+                Err(e)
+            }
         }
     }
 
+    /// Read the boot partition of the eMMC through the alternative boot operation mode, check JESD84-B51 section 6.3.3
+    /// This does not need the card to be identified, so a loader can read its next stage right after SdmmcProtocol::new
+    /// The boot partition and BOOT_ACK are chosen by PARTITION_CONFIG, `boot_ack` must match it.
+    /// `bus_width` must match BOOT_BUS_WIDTH in BOOT_BUS_CONDITIONS, and BOOT_MODE must be the backward compatible timing.
+    /// The host needs to implement sdmmc_config_boot, otherwise ENOTIMPLEMENTED is returned and neither the card
+    /// nor the driver state is touched. After the boot read the card is left in idle state, setup_card is needed
+    /// before any other operation
+    pub fn emmc_boot_read(
+        &mut self,
+        blockcnt: u32,
+        destination: u64,
+        bus_width: MmcBusWidth,
+        boot_ack: bool,
+    ) -> Result<(), SdmmcError> {
+        // Ask the host first, so a host without boot support returns before the card or the state is touched
+        self.hardware.sdmmc_config_boot(true, boot_ack)?;
+
+        let res: Result<(), SdmmcError> =
+            self.emmc_boot_read_inner(blockcnt, destination, bus_width);
+
+        let end_res: Result<(), SdmmcError> = self.hardware.sdmmc_config_boot(false, false);
+
+        res.and(end_res)
+    }
+
+    fn emmc_boot_read_inner(
+        &mut self,
+        blockcnt: u32,
+        destination: u64,
+        bus_width: MmcBusWidth,
+    ) -> Result<(), SdmmcError> {
+        // Whatever is known about the card is gone once it goes through pre-idle state
+        self.mmc_device = None;
+        self.tuning = None;
+        self.cmdq = CmdqQueue::new(1);
+
+        self.mmc_ios.clock = self.hardware.sdmmc_config_timing(MmcTiming::Legacy)?;
+        self.hardware.sdmmc_config_bus_width(bus_width)?;
+        self.mmc_ios.bus_width = bus_width;
+
+        let mut cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_GO_IDLE_STATE,
+            resp_type: MMC_RSP_NONE,
+            cmdarg: MMC_CMD0_ARG_GO_PRE_IDLE,
+        };
+
+        self.hardware.sdmmc_send_command(&cmd, None)?;
+
+        // The eMMC needs at least 74 clock cycles in pre-idle state before the boot initiation
+        self.sleep.usleep(2_000);
+
+        cmd = SdmmcCmd {
+            cmdidx: MMC_CMD_GO_IDLE_STATE,
+            resp_type: MMC_RSP_NONE,
+            cmdarg: MMC_CMD0_ARG_BOOT_INITIATION,
+        };
+
+        let data: MmcData = MmcData {
+            blocksize: SDCARD_DEFAULT_SECTOR_SIZE,
+            blockcnt,
+            flags: MmcDataFlag::SdmmcDataRead,
+            addr: destination,
+        };
+        let mut resp: [u32; 4] = [0; 4];
+
+        let res: Result<(), SdmmcError> =
+            self.hardware
+                .sdmmc_do_request(&mut self.sleep, &cmd, Some(&data), &mut resp, 0);
+
+        // CMD0 with the reset argument ends the boot mode, regardless of how much data has been read
+        cmd = SdmmcCmd {
+            cmdidx: MMC_CMD_GO_IDLE_STATE,
+            resp_type: MMC_RSP_NONE,
+            cmdarg: 0,
+        };
+
+        let end_res: Result<(), SdmmcError> = self.hardware.sdmmc_send_command(&cmd, None);
+        self.sleep.usleep(1_000);
+
+        res.and(end_res)
+    }
+
     /// From uboot
     /// Most cards do not answer if some reserved bits
     /// in the ocr are set. However, Some controller
@@ -474,7 +679,7 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
     /// how to manage low voltages SD card is not yet
     /// specified.
     /// Check mmc_sd_get_cid() in Linux for card init process
-    fn setup_sdcard_cont(&mut self) -> Result<Sdcard, SdmmcError> {
+    fn setup_sdcard_cont(&mut self, capacity_class: SdCapacityClass) -> Result<Sdcard, SdmmcError> {
         let mut resp: [u32; 4] = [0; 4];
 
         // Send CMD2 to get the CID register
@@ -548,6 +753,17 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
             .sdmmc_do_request(&mut self.sleep, &cmd, None, &mut resp, 1)?;
 
         // SDHC/SDXC default to 512 bytes sector size so I did not manually set it here
+        // SDSC block length follows READ_BL_LEN, which can be 1024 or 2048 bytes on 1GB and 2GB cards
+        if capacity_class == SdCapacityClass::Standard {
+            cmd = SdmmcCmd {
+                cmdidx: MMC_CMD_SET_BLOCKLEN,
+                resp_type: MMC_RSP_R1,
+                cmdarg: SDCARD_DEFAULT_SECTOR_SIZE,
+            };
+
+            self.hardware
+                .sdmmc_do_request(&mut self.sleep, &cmd, None, &mut resp, 1)?;
+        }
 
         self.mmc_ios.clock = self.hardware.sdmmc_config_timing(MmcTiming::Legacy)?;
 
@@ -562,6 +778,7 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
             manufacture_info: cid,
             card_specific_data: csd,
             card_version,
+            capacity_class,
             relative_card_addr: rca,
             card_state,
             card_cap: capability::SdcardCapability(MMC_EMPTY_CAP),
@@ -570,11 +787,181 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
         })
     }
 
+    /// eMMC counterpart of sdcard_init, check mmc_attach_mmc() in Linux for card init process
+    /// Return whether the device is working in sector mode (high capacity) or byte mode
+    fn emmc_init(&mut self) -> Result<bool, SdmmcError> {
+        let mut resp: [u32; 4] = [0; 4];
+
+        let mut cmd = SdmmcCmd {
+            cmdidx: MMC_CMD_GO_IDLE_STATE,
+            resp_type: MMC_RSP_NONE,
+            cmdarg: 0,
+        };
+
+        dev_log!("Try to send go idle cmd to eMMC\n");
+
+        // The failed sdcard init sequence may have left the eMMC in an odd state
+        self.hardware.sdmmc_send_command(&cmd, None)?;
+
+        self.sleep.usleep(2_000);
+
+        // eMMC specification requires the device to finish power up within 1 second
+        let mut retry: u16 = 1000;
+
+        loop {
+            dev_log!("Sending MMC_CMD_SEND_OP_COND!\n");
+
+            // We always ask for sector mode, byte mode device would just ignore this bit
+            cmd = SdmmcCmd {
+                cmdidx: MMC_CMD_SEND_OP_COND,
+                resp_type: MMC_RSP_R3,
+                cmdarg: OCR_SECTOR_MODE | (T::HOST_INFO.vdd & OCR_VOLTAGE_MASK),
+            };
+
+            let res = self
+                .hardware
+                .sdmmc_do_request(&mut self.sleep, &cmd, None, &mut resp, 0);
+
+            match res {
+                Ok(_) => {}
+                // The card does not respond to CMD1, so it is neither a sdcard nor an eMMC
+                Err(SdmmcError::ETIMEDOUT) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+                Err(e) => return Err(e),
+            }
+
+            dev_log!("OCR: {:08x}\n", resp[0]);
+
+            if (resp[0] & OCR_BUSY) != 0 {
+                break;
+            }
+
+            if retry == 0 {
+                dev_log!("SDMMC: MMC_CMD_SEND_OP_COND failed, eMMC not supported!\n");
+                return Err(SdmmcError::EUNSUPPORTEDCARD);
+            }
+            retry -= 1;
+
+            self.sleep.usleep(1_000);
+        }
+
+        Ok(resp[0] & OCR_ACCESS_MODE == OCR_SECTOR_MODE)
+    }
+
+    /// Identification process after the eMMC leaves the ready state
+    /// The main difference with sdcard is that the host assigns the RCA to the eMMC
+    fn setup_emmc_cont(&mut self, sector_mode: bool) -> Result<EMmc, SdmmcError> {
+        let mut resp: [u32; 4] = [0; 4];
+
+        // Send CMD2 to get the CID register
+        let mut cmd = SdmmcCmd {
+            cmdidx: MMC_CMD_ALL_SEND_CID,
+            resp_type: MMC_RSP_R2,
+            cmdarg: 0,
+        };
+        self.hardware
+            .sdmmc_do_request(&mut self.sleep, &cmd, None, &mut resp, 1)?;
+
+        let cid: EmmcCid = EmmcCid::new(resp);
+
+        let card_id = ((resp[0] as u128) << 96)
+            | ((resp[1] as u128) << 64)
+            | ((resp[2] as u128) << 32)
+            | (resp[3] as u128);
+
+        dev_log!(
+            "CID: {:08x} {:08x} {:08x} {:08x}\n",
+            resp[0],
+            resp[1],
+            resp[2],
+            resp[3]
+        );
+
+        // Send CMD3 to assign the RCA
+        let rca: u16 = EMMC_DEFAULT_RELATIVE_ADDR;
+        cmd = SdmmcCmd {
+            cmdidx: MMC_CMD_SET_RELATIVE_ADDR,
+            resp_type: MMC_RSP_R1,
+            cmdarg: (rca as u32) << 16,
+        };
+
+        self.hardware
+            .sdmmc_do_request(&mut self.sleep, &cmd, None, &mut resp, 1)?;
+
+        dev_log!("RCA: {:04x}\n", rca);
+
+        // Send CMD9 to get the CSD register
+        cmd = SdmmcCmd {
+            cmdidx: MMC_CMD_SEND_CSD,
+            resp_type: MMC_RSP_R2,
+            cmdarg: (rca as u32) << 16,
+        };
+
+        self.hardware
+            .sdmmc_do_request(&mut self.sleep, &cmd, None, &mut resp, 1)?;
+
+        dev_log!(
+            "CSD: {:08x} {:08x} {:08x} {:08x}\n",
+            resp[0],
+            resp[1],
+            resp[2],
+            resp[3]
+        );
+
+        let csd: EmmcCsd = EmmcCsd::new(resp);
+
+        // Send CMD7 to select the card
+        cmd = SdmmcCmd {
+            cmdidx: MMC_CMD_SELECT_CARD,
+            resp_type: MMC_RSP_R1,
+            cmdarg: (rca as u32) << 16,
+        };
+
+        self.hardware
+            .sdmmc_do_request(&mut self.sleep, &cmd, None, &mut resp, 1)?;
+
+        // eMMC default to 512 bytes sector size in sector mode
+
+        self.mmc_ios.clock = self.hardware.sdmmc_config_timing(MmcTiming::Legacy)?;
+
+        let card_state: MmcState = MmcState {
+            timing: MmcTiming::Legacy,
+            bus_width: MmcBusWidth::Width1,
+        };
+
+        // CMD23 is mandatory since MMC 3.1, older devices need stop transmission cmd
+        let method: BlockTransmissionMode = if csd.spec_version >= EMMC_SPEC_VERSION_CMD23 {
+            BlockTransmissionMode::SetBlockCount
+        } else {
+            BlockTransmissionMode::StopTransmission
+        };
+
+        Ok(EMmc {
+            card_id,
+            manufacture_info: cid,
+            card_specific_data: csd,
+            relative_card_addr: rca,
+            card_state,
+            method,
+            sector_mode,
+            ext_csd: None,
+            current_partition: EmmcPartition::UserData,
+            cache_enabled: false,
+            hpi_enabled: false,
+            bkops_running: false,
+            cmdq_enabled: false,
+            power_off_notify: false,
+            sleeping: false,
+        })
+    }
+
     /// A function that tune the card speed
-    /// This function does not do roll back so if this function return an error, reset up the card
+    /// SD cards step down to the next slower speed mode if switching to or tuning a speed mode fails,
+    /// the speed mode finally used is logged and reported by card_info.
+    /// Otherwise this function does not do roll back so if this function return an error, reset up the card
     /// Do NOT call this function again if your card is already tuned as this function is not that cheap!
     /// But you should call this function the card being turned into power saving mode
-    /// `stolen_memory` is a specific memory region used to read data from the SD card through CMD6.
+    /// `stolen_memory` is a specific memory region used to read data from the SD card through CMD6
+    /// and the EXT_CSD register from eMMC through CMD8.
     /// To use this function safely, the memory pointer passed in must be from a valid memory address
     /// And must match the physical memory address that is being DMA into
     ///
@@ -583,8 +970,8 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
     /// 1. **Memory Region Constraints**:
     ///    - `stolen_memory` is used as a buffer to hold the 64-byte response from the SD card
     ///      when executing CMD6. This response contains the function switch status and other
-    ///      function-related information.
-    ///    - The memory region from `stolen_memory` to `stolen_memory + 64 bytes` must not overlap
+    ///      function-related information. For eMMC, it holds the 512-byte EXT_CSD register.
+    ///    - The memory region from `stolen_memory` to `stolen_memory + 512 bytes` must not overlap
     ///      with any other data structures, device registers, or memory-mapped peripherals to
     ///      avoid conflicts or unintended behavior.
     ///
@@ -595,6 +982,8 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
     ///      after writing data to ensure consistency between the memory and the SD card.
     ///      For example, you might need to use cache control instructions or APIs specific to your
     ///      platform to manage this.
+    ///    - For eMMC, the bus test procedure writes a pattern into the memory before the host reads it,
+    ///      so the cache function should clean the cache as well (e.g. clean and invalidate).
     ///
     /// 3. **Alignment and Access Requirements**:
     ///    - `stolen_memory` should be aligned to at least 4 bytes (or preferably 8 bytes) to avoid
@@ -604,21 +993,22 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
     ///
     /// # Parameters
     /// - `addr_and_invalidate_cache_fn`: An optional tuple containing:
-    ///     - `*mut [u8; 64]`: A memory address used as a buffer for certain commands (e.g., CMD6). The memory
+    ///     - `*mut [u8; 512]`: A memory address used as a buffer for certain commands (e.g., CMD6). The memory
     ///       should be suitable for DMA into(e.g. aligned to 8 bytes memory border and not conflict
     ///       with other structure or device registers).
     ///     - `fn()`: A function pointer that, when called, invalidates the cache for the range
-    ///       `addr` to `addr + 64 bytes`. This function should ensure cache consistency for
+    ///       `addr` to `addr + 512 bytes`. This function should ensure cache consistency for
     ///       that specific memory range. If `None`, no buffer is used, and the tune performance function
     ///       will not attempt to change the card speed class. The fn should not take any variables and would
     ///       not be stored. By this way, the protocol layer only has the minimal privilege it required for cache invalidation.
+    ///       The only exception is tuning, the memory and the fn are kept to check the tuning block when retuning.
     ///
     /// # Returns
     /// - `Result<(), SdmmcError>`: `Ok(())` if tuning was successful, or an error otherwise.
     /// UB here waiting to be fixed: memory being a mutable reference is also accessed by DMA
     pub unsafe fn tune_performance(
         &mut self,
-        memory: *mut [u8; 64],
+        memory: *mut [u8; EXT_CSD_SIZE],
         cache_invalidate_function: fn(),
         physical_memory_addr: u64,
     ) -> Result<(), SdmmcError> {
@@ -628,11 +1018,8 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
         // Turn down the clock frequency
         self.mmc_ios.clock = self.hardware.sdmmc_config_timing(MmcTiming::CardSetup)?;
 
-        // The private memory should be the physically memory
-        // However any attempt to dereference the pointer
-        // could crash the program if the driver don't have access
-        // to the phyical memory at the proper address
-        self.private_memory = Some(physical_memory_addr as *mut [u8; 64]);
+        // Only set when the card is switched to a speed mode that needs tuning
+        self.tuning = None;
 
         match mmc_device {
             MmcDevice::Sdcard(sdcard) => {
@@ -643,7 +1030,10 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
                     physical_memory_addr,
                 )
             }
-            MmcDevice::EMmc(_emmc) => Err(SdmmcError::ENOTIMPLEMENTED),
+            MmcDevice::EMmc(emmc) => {
+                emmc.card_state.timing = MmcTiming::CardSetup;
+                self.tune_emmc_performance(memory, cache_invalidate_function, physical_memory_addr)
+            }
             MmcDevice::Unknown => Err(SdmmcError::ENOTIMPLEMENTED),
         }
     }
@@ -875,12 +1265,15 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
     /// I should change the process of access raw memory to using volatile read
     fn tune_sdcard_performance(
         &mut self,
-        memory: *mut [u8; 64],
+        memory: *mut [u8; EXT_CSD_SIZE],
         cache_invalidate_function: fn(),
         physical_memory_addr: u64,
     ) -> Result<(), SdmmcError> {
         let mut resp: [u32; 4] = [0; 4];
 
+        // Sdcard registers read by CMD6 and ACMD51 all fit in the first 64 bytes
+        let memory: *mut [u8; 64] = memory as *mut [u8; 64];
+
         if let Some(MmcDevice::Sdcard(sdcard)) = &mut self.mmc_device {
             let scr: Scr = unsafe {
                 Sdcard::sdcard_get_configuration_register(
@@ -976,76 +1369,630 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
             return Err(SdmmcError::EUNDEFINED);
         }
 
-        let mut target_timing: MmcTiming;
-        let sdcard_cap: SdcardCapability;
-        if let Some(MmcDevice::Sdcard(ref sdcard)) = self.mmc_device {
-            dev_log!("Switch to higher speed class\n");
-            sdcard_cap = sdcard.card_cap.clone();
-            target_timing = sdcard.card_state.timing;
-            // This 'tune_speed thing is a feature called labeled block in Rust
-            'tune_speed: {
-                match self.mmc_ios.signal_voltage {
-                    MmcSignalVoltage::Voltage330 => {
-                        if sdcard_cap.contains(SdcardCapability(MMC_TIMING_SD_HS))
-                            && T::HOST_INFO.has_capability(MMC_TIMING_SD_HS)
-                        {
-                            target_timing = MmcTiming::SdHs;
-                        }
-                    }
-                    MmcSignalVoltage::Voltage180 => {
-                        if sdcard_cap.contains(SdcardCapability(MMC_TIMING_UHS_SDR104))
-                            && T::HOST_INFO.has_capability(MMC_TIMING_UHS_SDR104)
-                        {
-                            target_timing = MmcTiming::UhsSdr104;
-                            // If the switch speed succeed, terminate the block
-                            break 'tune_speed;
-                        }
-                        if !sdcard_cap.contains(SdcardCapability(MMC_TIMING_UHS_DDR50))
-                            && T::HOST_INFO.has_capability(MMC_TIMING_UHS_DDR50)
-                        {
-                            target_timing = MmcTiming::UhsDdr50;
-                            break 'tune_speed;
-                        }
-                        if !sdcard_cap.contains(SdcardCapability(MMC_TIMING_UHS_SDR50))
-                            && T::HOST_INFO.has_capability(MMC_TIMING_UHS_SDR50)
-                        {
-                            target_timing = MmcTiming::UhsSdr50;
-                            break 'tune_speed;
-                        }
-                        if !sdcard_cap.contains(SdcardCapability(MMC_TIMING_UHS_SDR25))
-                            && T::HOST_INFO.has_capability(MMC_TIMING_UHS_SDR25)
-                        {
-                            target_timing = MmcTiming::UhsSdr25;
-                            break 'tune_speed;
-                        }
-                    }
-                    MmcSignalVoltage::Voltage120 => return Err(SdmmcError::EUNDEFINED),
-                }
-            };
-            unsafe {
-                self.sdcard_switch_speed(
+        let sdcard_cap: SdcardCapability = match self.mmc_device {
+            Some(MmcDevice::Sdcard(ref sdcard)) => sdcard.card_cap.clone(),
+            _ => return Err(SdmmcError::EUNDEFINED),
+        };
+
+        // The speed modes are tried from the fastest one, the last one of each ladder is
+        // the mode the card is already in after the init, so it is always supported
+        let ladder: &[(MmcTiming, u128)] = match self.mmc_ios.signal_voltage {
+            MmcSignalVoltage::Voltage330 => &[
+                (MmcTiming::SdHs, MMC_TIMING_SD_HS),
+                (MmcTiming::Legacy, MMC_TIMING_LEGACY),
+            ],
+            MmcSignalVoltage::Voltage180 => &[
+                (MmcTiming::UhsSdr104, MMC_TIMING_UHS_SDR104),
+                (MmcTiming::UhsDdr50, MMC_TIMING_UHS_DDR50),
+                (MmcTiming::UhsSdr50, MMC_TIMING_UHS_SDR50),
+                (MmcTiming::UhsSdr25, MMC_TIMING_UHS_SDR25),
+                (MmcTiming::UhsSdr12, MMC_TIMING_UHS_SDR12),
+            ],
+            MmcSignalVoltage::Voltage120 => return Err(SdmmcError::EUNDEFINED),
+        };
+        let (base_timing, _) = ladder[ladder.len() - 1];
+
+        dev_log!("Switch to higher speed class\n");
+
+        let mut res: Result<(), SdmmcError> = Err(SdmmcError::EUNDEFINED);
+
+        for &(target_timing, capability) in ladder {
+            if target_timing != base_timing
+                && !(sdcard_cap.contains(SdcardCapability(capability))
+                    && T::HOST_INFO.has_capability(capability))
+            {
+                continue;
+            }
+
+            dev_log!("Trying to switch sdcard to {:?}\n", target_timing);
+
+            res = unsafe {
+                self.sdcard_select_timing(
                     target_timing,
+                    base_timing,
                     memory,
                     cache_invalidate_function,
                     physical_memory_addr,
-                )?;
-            }
-            self.mmc_ios.clock = self.hardware.sdmmc_config_timing(target_timing)?;
-
-            self.hardware
-                .sdmmc_execute_tuning(physical_memory_addr as *mut [u8; 64], &mut self.sleep)?;
+                )
+            };
+
+            match res {
+                Ok(()) => {
+                    if let Some(MmcDevice::Sdcard(ref mut sdcard)) = self.mmc_device {
+                        sdcard.card_state.timing = target_timing;
+                    }
+                    break;
+                }
+                Err(ref _err) => {
+                    dev_log!("Switch to {:?} failed: {:?}\n", target_timing, _err);
+                }
+            }
+        }
+
+        if res.is_ok() {
+            info!(
+                "SD card is running in {:?} at {}Hz\n",
+                self.sdcard_timing(),
+                self.mmc_ios.clock
+            );
+        }
+
+        res
+    }
+
+    /// One step of the speed mode ladder of tune_sdcard_performance
+    /// The clock is brought down to `base_timing` before CMD6 is issued, so a failed step does not
+    /// leave the bus at a clock the card cannot follow. Tuning is done for SDR50, DDR50 and SDR104,
+    /// but only SDR50 and SDR104 require it to succeed, like Linux a DDR50 card is kept untuned otherwise
+    unsafe fn sdcard_select_timing(
+        &mut self,
+        target_timing: MmcTiming,
+        base_timing: MmcTiming,
+        memory: *mut [u8; 64],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<(), SdmmcError> {
+        self.tuning = None;
+
+        self.mmc_ios.clock = self.hardware.sdmmc_config_timing(base_timing)?;
+
+        unsafe {
+            self.sdcard_switch_speed(
+                target_timing,
+                memory,
+                cache_invalidate_function,
+                physical_memory_addr,
+            )?;
+        }
+
+        self.mmc_ios.clock = self.hardware.sdmmc_config_timing(target_timing)?;
+
+        if matches!(
+            target_timing,
+            MmcTiming::UhsSdr104 | MmcTiming::UhsDdr50 | MmcTiming::UhsSdr50
+        ) {
+            let tuning: MmcTuning = unsafe {
+                MmcTuning::sdcard(
+                    physical_memory_addr,
+                    memory as *const u8,
+                    cache_invalidate_function,
+                )
+            };
 
-            dev_log!("Current frequency: {}Hz\n", self.mmc_ios.clock);
+            match self.hardware.sdmmc_execute_tuning(&tuning, &mut self.sleep) {
+                Ok(()) => self.tuning = Some(tuning),
+                Err(_err) if target_timing == MmcTiming::UhsDdr50 => {
+                    dev_log!(
+                        "SDMMC: DDR50 tuning failed: {:?}, continuing untuned\n",
+                        _err
+                    );
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn sdcard_timing(&self) -> MmcTiming {
+        match self.mmc_device {
+            Some(MmcDevice::Sdcard(ref sdcard)) => sdcard.card_state.timing,
+            _ => MmcTiming::Legacy,
+        }
+    }
+
+    /// eMMC counterpart of tune_sdcard_performance
+    /// Read the EXT_CSD, switch to the widest bus width and then to the fastest timing both sides support
+    /// The order is HS400ES, HS400 through HS200, HS200, DDR52 through HS, then HS
+    fn tune_emmc_performance(
+        &mut self,
+        memory: *mut [u8; EXT_CSD_SIZE],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<(), SdmmcError> {
+        let ext_csd: ExtCsd = unsafe {
+            EMmc::emmc_get_ext_csd(
+                &mut self.hardware,
+                &mut self.sleep,
+                physical_memory_addr,
+                memory,
+                cache_invalidate_function,
+            )?
+        };
+
+        self.mmc_ios.clock = self.hardware.sdmmc_config_timing(MmcTiming::Legacy)?;
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.update_ext_csd(ext_csd);
+            emmc.card_state.timing = MmcTiming::Legacy;
         } else {
             return Err(SdmmcError::EUNDEFINED);
         }
-        if let Some(MmcDevice::Sdcard(ref mut sdcard)) = self.mmc_device {
-            sdcard.card_state.timing = target_timing;
+
+        // Not fatal, the eMMC is put into sleep state before losing power instead
+        if self.emmc_enable_power_off_notify().is_err() {
+            dev_log!("Failed to enable eMMC power off notification\n");
+        }
+
+        if self.mmc_ios.bus_width == MmcBusWidth::Width1
+            && T::HOST_INFO.has_capability(MMC_CAP_4_BIT_DATA)
+        {
+            unsafe {
+                self.emmc_select_bus_width(
+                    memory,
+                    cache_invalidate_function,
+                    physical_memory_addr,
+                )?;
+            }
+        }
+
+        let (device_type, strobe_support) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                (ext_csd.device_type.clone(), ext_csd.strobe_support)
+            }
+            _ => return Err(SdmmcError::EUNDEFINED),
+        };
+
+        let enhanced_strobe: bool = match self.mmc_ios.emmc {
+            Some(ref settings) => settings.enhanced_strobe,
+            None => false,
+        };
+
+        // HS400 only works with 8 bit bus width
+        let hs400_supported: bool = self.mmc_ios.bus_width == MmcBusWidth::Width8
+            && T::HOST_INFO.has_capability(MMC_TIMING_MMC_HS400)
+            && device_type.contains(EMmcCapability(MMC_TIMING_MMC_HS400));
+
+        // HS200 only works with 4 or 8 bit bus width
+        let hs200_supported: bool = self.mmc_ios.bus_width != MmcBusWidth::Width1
+            && T::HOST_INFO.has_capability(MMC_TIMING_MMC_HS200)
+            && device_type.contains(EMmcCapability(MMC_TIMING_MMC_HS200));
+
+        let hs_supported: bool = T::HOST_INFO.has_capability(MMC_TIMING_MMC_HS)
+            && device_type.contains(EMmcCapability(MMC_TIMING_MMC_HS));
+
+        // DDR52 only works with 4 or 8 bit bus width
+        let ddr52_supported: bool = hs_supported
+            && self.mmc_ios.bus_width != MmcBusWidth::Width1
+            && T::HOST_INFO.has_capability(MMC_TIMING_MMC_DDR52)
+            && device_type.contains(EMmcCapability(MMC_TIMING_MMC_DDR52));
+
+        'select_timing: {
+            if hs400_supported && enhanced_strobe && strobe_support {
+                match self.emmc_select_hs400es() {
+                    Ok(()) => break 'select_timing,
+                    Err(_err) => {
+                        dev_log!(
+                            "Switch to HS400ES failed: {:?}, fall back to legacy\n",
+                            _err
+                        );
+                        self.emmc_select_legacy_timing()?;
+                    }
+                }
+            }
+
+            if hs200_supported {
+                let mut res: Result<(), SdmmcError> = unsafe {
+                    self.emmc_select_hs200(memory, cache_invalidate_function, physical_memory_addr)
+                };
+
+                if res.is_ok() && hs400_supported {
+                    res = self.emmc_select_hs400();
+                }
+
+                match res {
+                    Ok(()) => break 'select_timing,
+                    Err(_err) => {
+                        dev_log!(
+                            "Switch to HS200/HS400 failed: {:?}, fall back to legacy\n",
+                            _err
+                        );
+                        self.emmc_select_legacy_timing()?;
+                    }
+                }
+            }
+
+            if hs_supported {
+                if let Err(_err) = self.emmc_select_hs() {
+                    dev_log!("Switch to HS failed: {:?}, fall back to legacy\n", _err);
+                    self.emmc_select_legacy_timing()?;
+                    break 'select_timing;
+                }
+
+                if ddr52_supported && let Err(_err) = self.emmc_select_ddr52() {
+                    dev_log!("Switch to DDR52 failed: {:?}, fall back to HS\n", _err);
+                    self.emmc_select_legacy_timing()?;
+                    self.emmc_select_hs()?;
+                }
+            }
+        }
+
+        dev_log!("Current frequency: {}Hz\n", self.mmc_ios.clock);
+
+        Ok(())
+    }
+
+    /// Switch the eMMC to HS200 through EXT_CSD HS_TIMING and tune the sampling point with CMD21
+    /// The bus width should already be switched to 4 or 8 bit, check mmc_select_hs200() in Linux
+    /// Unsafe because dereference raw pointer
+    unsafe fn emmc_select_hs200(
+        &mut self,
+        memory: *mut [u8; EXT_CSD_SIZE],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms) = self.emmc_switch_params()?;
+
+        self.emmc_switch_signal_voltage_180()?;
+
+        self.emmc_switch_timing(
+            rca,
+            EXT_CSD_TIMING_HS200 | self.emmc_driver_strength(),
+            MmcTiming::MmcHs200,
+            generic_cmd6_time_ms,
+        )?;
+
+        let tuning: MmcTuning = unsafe {
+            MmcTuning::emmc_hs200(
+                self.mmc_ios.bus_width,
+                physical_memory_addr,
+                memory as *const u8,
+                cache_invalidate_function,
+            )?
+        };
+
+        self.hardware
+            .sdmmc_execute_tuning(&tuning, &mut self.sleep)?;
+
+        self.tuning = Some(tuning);
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.card_state.timing = MmcTiming::MmcHs200;
+        }
+
+        Ok(())
+    }
+
+    /// Switch the eMMC from tuned HS200 to HS400, the sequence is HS200 -> HS -> HS400
+    /// The sampling point tuned in HS200 is reused in HS400, check mmc_select_hs400() in Linux
+    fn emmc_select_hs400(&mut self) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms) = self.emmc_switch_params()?;
+
+        // The bus width can only be switched to DDR in HS timing
+        self.emmc_switch_timing(
+            rca,
+            EXT_CSD_TIMING_HS,
+            MmcTiming::MmcHs,
+            generic_cmd6_time_ms,
+        )?;
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_BUS_WIDTH,
+            EXT_CSD_DDR_BUS_WIDTH_8,
+            generic_cmd6_time_ms,
+        )?;
+
+        self.hardware.sdmmc_config_strobe(true)?;
+
+        self.emmc_switch_timing(
+            rca,
+            EXT_CSD_TIMING_HS400 | self.emmc_driver_strength(),
+            MmcTiming::MmcHs400,
+            generic_cmd6_time_ms,
+        )?;
+
+        // CMD21 is not allowed in HS400, retuning would need to go through HS200 again
+        self.tuning = None;
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.card_state.timing = MmcTiming::MmcHs400;
+        }
+
+        Ok(())
+    }
+
+    /// Switch the eMMC to HS timing at 52MHz through EXT_CSD HS_TIMING, check mmc_select_hs() in Linux
+    fn emmc_select_hs(&mut self) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms) = self.emmc_switch_params()?;
+
+        self.emmc_switch_timing(
+            rca,
+            EXT_CSD_TIMING_HS,
+            MmcTiming::MmcHs,
+            generic_cmd6_time_ms,
+        )?;
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.card_state.timing = MmcTiming::MmcHs;
+        }
+
+        Ok(())
+    }
+
+    /// Switch the eMMC from HS to DDR52 by writing the DDR value of the current bus width to BUS_WIDTH
+    /// HS_TIMING stays in HS, the host switches to DDR once the card has taken the new bus width,
+    /// check mmc_select_hs_ddr() in Linux
+    fn emmc_select_ddr52(&mut self) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms) = self.emmc_switch_params()?;
+
+        let bus_width: u8 = match self.mmc_ios.bus_width {
+            MmcBusWidth::Width4 => EXT_CSD_DDR_BUS_WIDTH_4,
+            MmcBusWidth::Width8 => EXT_CSD_DDR_BUS_WIDTH_8,
+            MmcBusWidth::Width1 => return Err(SdmmcError::EINVAL),
+        };
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_BUS_WIDTH,
+            bus_width,
+            generic_cmd6_time_ms,
+        )?;
+
+        self.mmc_ios.clock = self.hardware.sdmmc_config_timing(MmcTiming::MmcDdr52)?;
+
+        // Make sure the card still responds in DDR before any data transfer is done
+        EMmc::emmc_send_status(&mut self.hardware, &mut self.sleep, rca)?;
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.card_state.timing = MmcTiming::MmcDdr52;
+        }
+
+        Ok(())
+    }
+
+    /// Switch the eMMC to HS400 Enhanced Strobe, the sequence is Legacy -> HS -> HS400ES
+    /// No tuning is needed as the response is latched with the data strobe signal as well,
+    /// check mmc_select_hs400es() in Linux
+    fn emmc_select_hs400es(&mut self) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms) = self.emmc_switch_params()?;
+
+        self.emmc_switch_signal_voltage_180()?;
+
+        self.emmc_switch_timing(
+            rca,
+            EXT_CSD_TIMING_HS,
+            MmcTiming::MmcHs,
+            generic_cmd6_time_ms,
+        )?;
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_BUS_WIDTH,
+            EXT_CSD_DDR_BUS_WIDTH_8 | EXT_CSD_BUS_WIDTH_STROBE,
+            generic_cmd6_time_ms,
+        )?;
+
+        self.hardware.sdmmc_config_strobe(true)?;
+
+        self.emmc_switch_timing(
+            rca,
+            EXT_CSD_TIMING_HS400 | self.emmc_driver_strength(),
+            MmcTiming::MmcHs400,
+            generic_cmd6_time_ms,
+        )?;
+
+        self.tuning = None;
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.card_state.timing = MmcTiming::MmcHs400;
+        }
+
+        Ok(())
+    }
+
+    /// Bring the eMMC back to the backwards compatible timing and SDR bus after a failed speed switch
+    /// If this fails as well, the card should be completely reinit
+    fn emmc_select_legacy_timing(&mut self) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms) = self.emmc_switch_params()?;
+
+        self.tuning = None;
+
+        match self.hardware.sdmmc_config_strobe(false) {
+            Ok(()) | Err(SdmmcError::ENOTIMPLEMENTED) => {}
+            Err(e) => return Err(e),
+        }
+
+        self.emmc_switch_timing(
+            rca,
+            EXT_CSD_TIMING_BC,
+            MmcTiming::Legacy,
+            generic_cmd6_time_ms,
+        )?;
+
+        // HS400 switches the bus to DDR, so the bus width is written again
+        let bus_width: u8 = match self.mmc_ios.bus_width {
+            MmcBusWidth::Width1 => EXT_CSD_BUS_WIDTH_1,
+            MmcBusWidth::Width4 => EXT_CSD_BUS_WIDTH_4,
+            MmcBusWidth::Width8 => EXT_CSD_BUS_WIDTH_8,
+        };
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_BUS_WIDTH,
+            bus_width,
+            generic_cmd6_time_ms,
+        )?;
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.card_state.timing = MmcTiming::Legacy;
+        }
+
+        Ok(())
+    }
+
+    /// Write HS_TIMING and change the host timing accordingly
+    /// The card switches to the new timing right after CMD6,
+    /// so the host timing must be changed before the card status is checked
+    fn emmc_switch_timing(
+        &mut self,
+        rca: u16,
+        hs_timing: u8,
+        timing: MmcTiming,
+        timeout_ms: u32,
+    ) -> Result<(), SdmmcError> {
+        EMmc::emmc_send_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            EXT_CSD_HS_TIMING,
+            hs_timing,
+        )?;
+
+        self.mmc_ios.clock = self.hardware.sdmmc_config_timing(timing)?;
+
+        EMmc::emmc_wait_switch_done(&mut self.hardware, &mut self.sleep, rca, timeout_ms)
+    }
+
+    /// HS200 and HS400 require 1.8V signal voltage, eMMC does not need CMD11 for this
+    fn emmc_switch_signal_voltage_180(&mut self) -> Result<(), SdmmcError> {
+        if self.mmc_ios.signal_voltage == MmcSignalVoltage::Voltage180 {
+            return Ok(());
+        }
+
+        if let Some(ref mut voltage_ops) = self.voltage_ops {
+            voltage_ops.card_voltage_switch(MmcSignalVoltage::Voltage180)?;
+        } else {
+            self.hardware
+                .sdmmc_voltage_switch(MmcSignalVoltage::Voltage180)?
         }
+        self.mmc_ios.signal_voltage = MmcSignalVoltage::Voltage180;
 
         Ok(())
     }
 
+    /// Driver strength placed in the upper nibble of HS_TIMING
+    fn emmc_driver_strength(&self) -> u8 {
+        let driver_type: MmcDriverType = match self.mmc_ios.emmc {
+            Some(ref settings) => settings.drv_type,
+            None => MmcDriverType::TypeB,
+        };
+        (driver_type as u8) << EXT_CSD_DRV_STR_SHIFT
+    }
+
+    /// Relative card address and GENERIC_CMD6_TIME needed by every CMD6 sent to the eMMC
+    fn emmc_switch_params(&self) -> Result<(u16, u32), SdmmcError> {
+        if let Some(MmcDevice::EMmc(ref emmc)) = self.mmc_device {
+            let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+            Ok((emmc.relative_card_addr, ext_csd.generic_cmd6_time_ms))
+        } else {
+            Err(SdmmcError::EUNDEFINED)
+        }
+    }
+
+    /// Switch the eMMC to the widest bus the host supports through EXT_CSD BUS_WIDTH
+    /// Each bus width is verified either by the bus test procedure if the host supports it,
+    /// or by reading back the EXT_CSD in the new bus width, check mmc_select_bus_width() in Linux
+    /// Unsafe because dereference raw pointer
+    unsafe fn emmc_select_bus_width(
+        &mut self,
+        memory: *mut [u8; EXT_CSD_SIZE],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms) = self.emmc_switch_params()?;
+
+        let candidates: &[(MmcBusWidth, u8)] = if T::HOST_INFO.has_capability(MMC_CAP_8_BIT_DATA) {
+            &[
+                (MmcBusWidth::Width8, EXT_CSD_BUS_WIDTH_8),
+                (MmcBusWidth::Width4, EXT_CSD_BUS_WIDTH_4),
+            ]
+        } else {
+            &[(MmcBusWidth::Width4, EXT_CSD_BUS_WIDTH_4)]
+        };
+
+        let mut res: Result<(), SdmmcError> = Err(SdmmcError::EUNDEFINED);
+
+        for &(bus_width, ext_csd_bus_width) in candidates {
+            dev_log!("Trying to switch eMMC to bus width {:?}\n", bus_width);
+
+            res = EMmc::emmc_switch(
+                &mut self.hardware,
+                &mut self.sleep,
+                rca,
+                EXT_CSD_BUS_WIDTH,
+                ext_csd_bus_width,
+                generic_cmd6_time_ms,
+            );
+            if res.is_err() {
+                continue;
+            }
+
+            self.hardware.sdmmc_config_bus_width(bus_width)?;
+
+            res = if T::HOST_INFO.has_capability(MMC_CAP_BUS_WIDTH_TEST) {
+                unsafe {
+                    EMmc::emmc_bus_test(
+                        &mut self.hardware,
+                        &mut self.sleep,
+                        bus_width,
+                        physical_memory_addr,
+                        memory,
+                        cache_invalidate_function,
+                    )
+                }
+            } else {
+                let ext_csd: Result<ExtCsd, SdmmcError> = unsafe {
+                    EMmc::emmc_get_ext_csd(
+                        &mut self.hardware,
+                        &mut self.sleep,
+                        physical_memory_addr,
+                        memory,
+                        cache_invalidate_function,
+                    )
+                };
+                match (ext_csd, &self.mmc_device) {
+                    (Ok(ext_csd), Some(MmcDevice::EMmc(emmc))) => match emmc.ext_csd {
+                        Some(ref old) if old.read_only_fields_match(&ext_csd) => Ok(()),
+                        _ => Err(SdmmcError::EIO),
+                    },
+                    (Err(e), _) => Err(e),
+                    _ => Err(SdmmcError::EUNDEFINED),
+                }
+            };
+
+            if res.is_ok() {
+                self.mmc_ios.bus_width = bus_width;
+                if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+                    emmc.card_state.bus_width = bus_width;
+                }
+                dev_log!("Tuning datalanes succeed!\n");
+                return Ok(());
+            }
+        }
+
+        // If any of the cmd above fail, the card should be completely reinit
+        res
+    }
+
     pub fn config_interrupt(
         &mut self,
         enable_irq: bool,
@@ -1066,11 +2013,20 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
         start_idx: u64,
         destination: u64,
     ) -> (Result<(), SdmmcError>, SdmmcProtocol<T, S, V>) {
+        if let Err(err) = self.emmc_stop_bkops() {
+            return (Err(err), self);
+        }
+
         let trans_meth: BlockTransmissionMode = {
             if let Some(ref device) = self.mmc_device {
                 match device {
                     MmcDevice::Sdcard(sdcard) => sdcard.method.clone(),
-                    MmcDevice::EMmc(emmc) => emmc.method.clone(),
+                    MmcDevice::EMmc(emmc) => {
+                        if let Err(err) = emmc.check_block_range(start_idx, blockcnt) {
+                            return (Err(err), self);
+                        }
+                        emmc.method.clone()
+                    }
                     MmcDevice::Unknown => return (Err(SdmmcError::EUNSUPPORTEDCARD), self),
                 }
             } else {
@@ -1078,6 +2034,19 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
             }
         };
 
+        // Legacy read cmds are not accepted while the command queue is on
+        if self.emmc_cmdq_enabled() {
+            let request: CmdqRequest = CmdqRequest {
+                flags: MmcDataFlag::SdmmcDataRead,
+                start_idx,
+                blockcnt,
+                addr: destination,
+                write_flags: WriteFlags::default(),
+            };
+            let res: Result<(), SdmmcError> = self.cmdq_run_single(request).await;
+            return (res, self);
+        }
+
         let mut cmd: SdmmcCmd;
         let mut res: Result<(), SdmmcError>;
         let mut turing: bool = false;
@@ -1093,21 +2062,26 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
         // TODO: Add more validation check in the future
         // Like sdmmc card usually cannot transfer arbitrary number of blocks at once
 
-        // The cmd arg for read operation is different between some card variation as showed by uboot code below
-        /*
-            if (mmc->high_capacity)
-                cmd.cmdarg = start;
-            else
-                cmd.cmdarg = start * mmc->read_bl_len;
-        */
-        // For now we default to assume the card is high_capacity
-        // TODO: If we boot the card by ourself or reset the card, remember to send block len cmd
+        // Standard capacity cards are addressed in bytes, check MmcDevice::card_address
+        let (card_address, address_extension) = match self.mmc_device {
+            Some(ref device) => match device.card_address(start_idx) {
+                Ok(address) => address,
+                Err(err) => return (Err(err), self),
+            },
+            None => return (Err(SdmmcError::ENOCARD), self),
+        };
         loop {
+            if let Err(err) =
+                Self::sdmmc_address_extension(&mut self.hardware, address_extension).await
+            {
+                res = Err(err);
+                break;
+            }
             if blockcnt == 1 {
                 cmd = SdmmcCmd {
                     cmdidx: MMC_CMD_READ_SINGLE_BLOCK,
                     resp_type: MMC_RSP_R1,
-                    cmdarg: start_idx as u32,
+                    cmdarg: card_address,
                 };
                 res = Self::sdmmc_async_request(&mut self.hardware, &cmd, Some(&data), &mut resp)
                     .await;
@@ -1118,7 +2092,7 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
                 cmd = SdmmcCmd {
                     cmdidx: MMC_CMD_READ_MULTIPLE_BLOCK,
                     resp_type: MMC_RSP_R1,
-                    cmdarg: start_idx as u32,
+                    cmdarg: card_address,
                 };
 
                 res = Self::sdmmc_multi_blocks_io(
@@ -1127,6 +2101,7 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
                     &data,
                     &mut resp,
                     trans_meth.clone(),
+                    0,
                 )
                 .await;
             }
@@ -1144,11 +2119,11 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
                             .await;
 
                     if let SdmmcError::EIO = err {
-                        if let Some(memory) = self.private_memory {
+                        if let Some(tuning) = self.tuning {
                             if turing == false {
                                 turing = true;
                                 if let Ok(()) =
-                                    self.hardware.sdmmc_execute_tuning(memory, &mut self.sleep)
+                                    self.hardware.sdmmc_execute_tuning(&tuning, &mut self.sleep)
                                 {
                                     continue;
                                 }
@@ -1166,16 +2141,62 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
     // For any future code add to read_block/write_block, remember to change both
     // Should read_block/write_block be the same function?
     pub async fn write_block(
+        self,
+        blockcnt: u32,
+        start_idx: u64,
+        source: u64,
+    ) -> (Result<(), SdmmcError>, SdmmcProtocol<T, S, V>) {
+        self.write_block_with_flags(blockcnt, start_idx, source, WriteFlags::default())
+            .await
+    }
+
+    /// write_block with extra semantics like force unit access
+    /// Requests that need eMMC request bits in CMD23 are always sent as CMD23 followed by CMD25
+    pub async fn write_block_with_flags(
         mut self,
         blockcnt: u32,
         start_idx: u64,
         source: u64,
+        flags: WriteFlags,
     ) -> (Result<(), SdmmcError>, SdmmcProtocol<T, S, V>) {
+        if let Err(err) = self.emmc_stop_bkops() {
+            return (Err(err), self);
+        }
+
+        let mut cmd23_flags: u32 = 0;
+        let (card_address, address_extension) = match self.mmc_device {
+            Some(ref device) => match device.card_address(start_idx) {
+                Ok(address) => address,
+                Err(err) => return (Err(err), self),
+            },
+            None => return (Err(SdmmcError::ENOCARD), self),
+        };
         let trans_meth: BlockTransmissionMode = {
             if let Some(ref device) = self.mmc_device {
                 match device {
-                    MmcDevice::Sdcard(sdcard) => sdcard.method.clone(),
-                    MmcDevice::EMmc(emmc) => emmc.method.clone(),
+                    MmcDevice::Sdcard(sdcard) => {
+                        if flags.reliable_write {
+                            return (Err(SdmmcError::EUNSUPPORTEDCARD), self);
+                        }
+                        if sdcard.card_specific_data.write_protected() {
+                            return (Err(SdmmcError::EWRITEPROTECTED), self);
+                        }
+                        sdcard.method.clone()
+                    }
+                    MmcDevice::EMmc(emmc) => {
+                        if let Err(err) = emmc.check_block_range(start_idx, blockcnt) {
+                            return (Err(err), self);
+                        }
+                        cmd23_flags = match emmc.write_request_bits(start_idx, blockcnt, flags) {
+                            Ok(cmd23_flags) => cmd23_flags,
+                            Err(err) => return (Err(err), self),
+                        };
+                        if cmd23_flags != 0 {
+                            BlockTransmissionMode::SetBlockCount
+                        } else {
+                            emmc.method.clone()
+                        }
+                    }
                     MmcDevice::Unknown => return (Err(SdmmcError::EUNSUPPORTEDCARD), self),
                 }
             } else {
@@ -1183,6 +2204,19 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
             }
         };
 
+        // Legacy write cmds are not accepted while the command queue is on
+        if self.emmc_cmdq_enabled() {
+            let request: CmdqRequest = CmdqRequest {
+                flags: MmcDataFlag::SdmmcDataWrite,
+                start_idx,
+                blockcnt,
+                addr: source,
+                write_flags: flags,
+            };
+            let res: Result<(), SdmmcError> = self.cmdq_run_single(request).await;
+            return (res, self);
+        }
+
         let cmd: SdmmcCmd;
         let res: Result<(), SdmmcError>;
 
@@ -1193,17 +2227,29 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
             addr: source,
         };
         let mut resp: [u32; 4] = [0; 4];
+
+        if let Some(trigger) = self.emmc_hpi_trigger() {
+            res = self
+                .emmc_interruptible_write(&data, card_address, cmd23_flags, trigger, &mut resp)
+                .await;
+            return (Self::check_write_violation(res, resp[0]), self);
+        }
         // TODO: Add more validation check in the future
 
-        if blockcnt == 1 {
+        if let Err(err) = Self::sdmmc_address_extension(&mut self.hardware, address_extension).await
+        {
+            return (Err(err), self);
+        }
+
+        if blockcnt == 1 && cmd23_flags == 0 {
             cmd = SdmmcCmd {
                 cmdidx: MMC_CMD_WRITE_SINGLE_BLOCK,
                 resp_type: MMC_RSP_R1,
-                cmdarg: start_idx as u32,
+                cmdarg: card_address,
             };
             res = Self::sdmmc_async_request(&mut self.hardware, &cmd, Some(&data), &mut resp).await;
 
-            return (res, self);
+            return (Self::check_write_violation(res, resp[0]), self);
         } else {
             // TODO: Add if here to determine if the card support cmd23 or not to determine to use cmd23 or cmd12
             // Set the expected number of blocks
@@ -1211,12 +2257,22 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
             cmd = SdmmcCmd {
                 cmdidx: MMC_CMD_WRITE_MULTIPLE_BLOCK,
                 resp_type: MMC_RSP_R1,
-                cmdarg: start_idx as u32,
+                cmdarg: card_address,
             };
 
-            res =
-                Self::sdmmc_multi_blocks_io(&mut self.hardware, &cmd, &data, &mut resp, trans_meth)
-                    .await;
+            // The status of the stop command does not carry the violation any more
+            res = Self::check_write_violation(
+                Self::sdmmc_multi_blocks_io(
+                    &mut self.hardware,
+                    &cmd,
+                    &data,
+                    &mut resp,
+                    trans_meth,
+                    cmd23_flags,
+                )
+                .await,
+                resp[0],
+            );
 
             match res {
                 Ok(()) => {}
@@ -1236,154 +2292,2480 @@ impl<T: SdmmcHardware, S: Sleep, V: VoltageOps> SdmmcProtocol<T, S, V> {
         }
     }
 
-    // TODO: correct erase block alignment, error handling and sdcard internal erasure timing(this erasure seems to be non-blocking?)
-    // It is likely(from reading Linux source code), polling in some host controller could be necessary
-    pub async fn erase_block(
-        mut self,
-        start_idx: u64,
-        end_idx: u64,
-    ) -> (Result<(), SdmmcError>, SdmmcProtocol<T, S, V>) {
-        let mut cmd: SdmmcCmd;
-        let mut res: Result<(), SdmmcError>;
-
-        let mut resp: [u32; 4] = [0; 4];
+    /// Send CMD22 with the upper bits of the block address, SDUC cards take them for the next read, write or erase cmd
+    async fn sdmmc_address_extension(
+        hardware: &mut T,
+        address_extension: Option<u32>,
+    ) -> Result<(), SdmmcError> {
+        let Some(address_extension) = address_extension else {
+            return Ok(());
+        };
 
-        cmd = SdmmcCmd {
-            cmdidx: SD_CMD_ERASE_WR_BLK_START,
+        let cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: SD_CMD_ADDRESS_EXTENSION,
             resp_type: MMC_RSP_R1,
-            cmdarg: start_idx as u32,
+            cmdarg: address_extension & 0x3F,
+        };
+        let mut resp: [u32; 4] = [0; 4];
+
+        Self::sdmmc_async_request(hardware, &cmd, None, &mut resp).await
+    }
+
+    /// The card rejects writes to write protected groups by setting WP_VIOLATION in the card status
+    fn check_write_violation(res: Result<(), SdmmcError>, status: u32) -> Result<(), SdmmcError> {
+        if status & MMC_STATUS_WP_VIOLATION != 0 {
+            return Err(SdmmcError::EWRITEPROTECTED);
+        }
+        res
+    }
+
+    /// Combine several writes into one CMD23/CMD25 transaction through the packed command of eMMC 4.5
+    /// Each entry can request a reliable write of its own, the entries are written in order
+    ///
+    /// # Safety
+    /// `memory` and `physical_memory_addr` must point to the start of the same buffer that the host can DMA from.
+    /// The first 512 bytes of the buffer are overwritten by the packed header and the data of the entries
+    /// follows the header back to back. `cache_clean_function` is called after the header is written
+    /// and should clean the cache for the whole buffer
+    pub async unsafe fn emmc_packed_write(
+        mut self,
+        entries: &[PackedWriteEntry],
+        memory: *mut [u8; PACKED_HEADER_SIZE],
+        cache_clean_function: fn(),
+        physical_memory_addr: u64,
+    ) -> (Result<(), SdmmcError>, SdmmcProtocol<T, S, V>) {
+        let res: Result<(), SdmmcError> = self
+            .emmc_packed_write_inner(entries, memory, cache_clean_function, physical_memory_addr)
+            .await;
+        (res, self)
+    }
+
+    async fn emmc_packed_write_inner(
+        &mut self,
+        entries: &[PackedWriteEntry],
+        memory: *mut [u8; PACKED_HEADER_SIZE],
+        cache_clean_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<(), SdmmcError> {
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        let (blockcnt, card_address) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                // Packed commands are legacy cmds and RPMB has its own frame format
+                if emmc.cmdq_enabled || emmc.current_partition == EmmcPartition::Rpmb {
+                    return Err(SdmmcError::EINVAL);
+                }
+                // The caller of emmc_packed_write guarantees the memory is valid
+                let header: &mut [u8; PACKED_HEADER_SIZE] = unsafe { &mut *memory };
+                // packed_write_header rejects an empty entry list
+                (
+                    emmc.packed_write_header(entries, header)?,
+                    emmc.card_address(entries[0].start_idx)?,
+                )
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        core::sync::atomic::fence(Ordering::Release);
+
+        cache_clean_function();
+
+        let cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_WRITE_MULTIPLE_BLOCK,
+            resp_type: MMC_RSP_R1,
+            // The address of the first entry, the device takes the rest from the header
+            cmdarg: card_address,
+        };
+
+        let data: MmcData = MmcData {
+            blocksize: SDCARD_DEFAULT_SECTOR_SIZE,
+            blockcnt,
+            flags: MmcDataFlag::SdmmcDataWrite,
+            addr: physical_memory_addr,
+        };
+        let mut resp: [u32; 4] = [0; 4];
+
+        let res: Result<(), SdmmcError> = Self::sdmmc_multi_blocks_io(
+            &mut self.hardware,
+            &cmd,
+            &data,
+            &mut resp,
+            BlockTransmissionMode::SetBlockCount,
+            MMC_CMD23_ARG_PACKED,
+        )
+        .await;
+
+        if res.is_err() {
+            let cmd: SdmmcCmd = SdmmcCmd {
+                cmdidx: MMC_CMD_STOP_TRANSMISSION,
+                resp_type: MMC_RSP_R1B,
+                cmdarg: 0,
+            };
+            let _ = Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await;
+        }
+
+        res
+    }
+
+    // TODO: correct erase block alignment, error handling and sdcard internal erasure timing(this erasure seems to be non-blocking?)
+    // It is likely(from reading Linux source code), polling in some host controller could be necessary
+    pub async fn erase_block(
+        mut self,
+        start_idx: u64,
+        end_idx: u64,
+    ) -> (Result<(), SdmmcError>, SdmmcProtocol<T, S, V>) {
+        // eMMC uses its own erase group cmds
+        if let Some(MmcDevice::EMmc(_)) = self.mmc_device {
+            return self
+                .emmc_erase(start_idx, end_idx, EmmcEraseType::Erase)
+                .await;
+        }
+
+        let mut cmd: SdmmcCmd;
+        let mut res: Result<(), SdmmcError>;
+
+        let mut resp: [u32; 4] = [0; 4];
+
+        let (start_address, end_address) = match self.mmc_device {
+            Some(ref device) => {
+                match (device.card_address(start_idx), device.card_address(end_idx)) {
+                    (Ok(start_address), Ok(end_address)) => (start_address, end_address),
+                    (Err(err), _) | (_, Err(err)) => return (Err(err), self),
+                }
+            }
+            None => return (Err(SdmmcError::ENOCARD), self),
+        };
+
+        if let Err(err) = Self::sdmmc_address_extension(&mut self.hardware, start_address.1).await {
+            return (Err(err), self);
+        }
+
+        cmd = SdmmcCmd {
+            cmdidx: SD_CMD_ERASE_WR_BLK_START,
+            resp_type: MMC_RSP_R1,
+            cmdarg: start_address.0,
+        };
+
+        res = Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await;
+
+        if let Err(_) = res {
+            return (res, self);
+        }
+
+        if let Err(err) = Self::sdmmc_address_extension(&mut self.hardware, end_address.1).await {
+            return (Err(err), self);
+        }
+
+        cmd = SdmmcCmd {
+            cmdidx: SD_CMD_ERASE_WR_BLK_END,
+            resp_type: MMC_RSP_R1,
+            cmdarg: end_address.0,
+        };
+
+        res = Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await;
+
+        if let Err(_) = res {
+            return (res, self);
+        }
+
+        cmd = SdmmcCmd {
+            cmdidx: MMC_CMD_ERASE,
+            resp_type: MMC_RSP_R1B,
+            cmdarg: SD_ERASE_ARG,
+        };
+
+        res = Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await;
+
+        return (res, self);
+    }
+
+    /// Erase blocks `start_idx` to `end_idx` (inclusive) of the selected eMMC partition
+    /// Erase and secure erase need both ends to be aligned to the erase group
+    /// The eMMC is polled until the operation finishes, with the timeout calculated from EXT_CSD
+    pub async fn emmc_erase(
+        mut self,
+        start_idx: u64,
+        end_idx: u64,
+        erase_type: EmmcEraseType,
+    ) -> (Result<(), SdmmcError>, SdmmcProtocol<T, S, V>) {
+        let res: Result<(), SdmmcError> =
+            self.emmc_erase_inner(start_idx, end_idx, erase_type).await;
+        (res, self)
+    }
+
+    async fn emmc_erase_inner(
+        &mut self,
+        start_idx: u64,
+        end_idx: u64,
+        erase_type: EmmcEraseType,
+    ) -> Result<(), SdmmcError> {
+        let (rca, timeout_ms) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => (
+                emmc.relative_card_addr,
+                emmc.erase_timeout_ms(start_idx, end_idx, erase_type)?,
+            ),
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        // eMMC never needs the address extension
+        let (start_address, end_address) = match self.mmc_device {
+            Some(ref device) => (
+                device.card_address(start_idx)?.0,
+                device.card_address(end_idx)?.0,
+            ),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        match erase_type {
+            EmmcEraseType::Erase => {
+                self.emmc_erase_sequence(start_address, end_address, MMC_ERASE_ARG, rca, timeout_ms)
+                    .await
+            }
+            EmmcEraseType::Trim => {
+                self.emmc_erase_sequence(start_address, end_address, MMC_TRIM_ARG, rca, timeout_ms)
+                    .await
+            }
+            EmmcEraseType::Discard => {
+                self.emmc_erase_sequence(
+                    start_address,
+                    end_address,
+                    MMC_DISCARD_ARG,
+                    rca,
+                    timeout_ms,
+                )
+                .await
+            }
+            EmmcEraseType::SecureErase => {
+                self.emmc_erase_sequence(
+                    start_address,
+                    end_address,
+                    MMC_SECURE_ERASE_ARG,
+                    rca,
+                    timeout_ms,
+                )
+                .await
+            }
+            EmmcEraseType::SecureTrim => {
+                // The first step marks the blocks and the second step performs the erase
+                self.emmc_erase_sequence(
+                    start_address,
+                    end_address,
+                    MMC_SECURE_TRIM1_ARG,
+                    rca,
+                    timeout_ms,
+                )
+                .await?;
+                self.emmc_erase_sequence(
+                    start_address,
+                    end_address,
+                    MMC_SECURE_TRIM2_ARG,
+                    rca,
+                    timeout_ms,
+                )
+                .await
+            }
+        }
+    }
+
+    /// CMD35 and CMD36 set the erase range and CMD38 starts the erase
+    /// The addresses are the ones resolved by card_address
+    async fn emmc_erase_sequence(
+        &mut self,
+        start_address: u32,
+        end_address: u32,
+        erase_arg: u32,
+        rca: u16,
+        timeout_ms: u32,
+    ) -> Result<(), SdmmcError> {
+        let mut resp: [u32; 4] = [0; 4];
+
+        let mut cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_ERASE_GROUP_START,
+            resp_type: MMC_RSP_R1,
+            cmdarg: start_address,
+        };
+
+        Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await?;
+
+        cmd = SdmmcCmd {
+            cmdidx: MMC_CMD_ERASE_GROUP_END,
+            resp_type: MMC_RSP_R1,
+            cmdarg: end_address,
+        };
+
+        Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await?;
+
+        cmd = SdmmcCmd {
+            cmdidx: MMC_CMD_ERASE,
+            resp_type: MMC_RSP_R1B,
+            cmdarg: erase_arg,
+        };
+
+        Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await?;
+
+        // The host may stop waiting for the busy signal long before the erase finishes,
+        // the card status is polled to know when the erase is done and whether it has succeeded
+        self.emmc_wait_busy_async(rca, timeout_ms, MMC_STATUS_ERASE_ERRORS)
+            .await
+    }
+
+    /// Poll the card status until the eMMC leaves programming state, any bit in `error_mask`
+    /// being set in the card status fails the operation
+    /// Same as EMmc::emmc_wait_busy, but the executor gets the CPU back between polls
+    async fn emmc_wait_busy_async(
+        &mut self,
+        rca: u16,
+        timeout_ms: u32,
+        error_mask: u32,
+    ) -> Result<(), SdmmcError> {
+        let mut resp: [u32; 4] = [0; 4];
+        let cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_SEND_STATUS,
+            resp_type: MMC_RSP_R1,
+            cmdarg: (rca as u32) << 16,
+        };
+
+        let mut deadline: PollDeadline = PollDeadline::new(&mut self.sleep, timeout_ms);
+
+        loop {
+            Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await?;
+
+            if resp[0] & error_mask != 0 {
+                dev_log!("eMMC operation error, status: 0x{:08x}\n", resp[0]);
+                return Err(SdmmcError::EINVAL);
+            }
+
+            if resp[0] & MMC_STATUS_RDY_FOR_DATA != 0
+                && resp[0] & MMC_STATUS_CURR_STATE != MMC_STATE_PRG
+            {
+                return Ok(());
+            }
+
+            deadline.next_poll(&mut self.sleep).await?;
+        }
+    }
+
+    /// The RPMB partition must be selected through emmc_select_partition first
+    pub async fn rpmb_program_key(
+        mut self,
+        buffer: &RpmbFrameBuffer,
+        key: &[u8; RPMB_KEY_MAC_SIZE],
+    ) -> (Result<(), SdmmcError>, SdmmcProtocol<T, S, V>) {
+        let res: Result<(), SdmmcError> = self.rpmb_program_key_inner(buffer, key).await;
+        (res, self)
+    }
+
+    /// Read the RPMB write counter, the response is authenticated with the MAC and a fresh nonce
+    /// The RPMB partition must be selected through emmc_select_partition first
+    pub async fn rpmb_read_counter<A: RpmbAuthenticator>(
+        mut self,
+        buffer: &RpmbFrameBuffer,
+        authenticator: &mut A,
+    ) -> (Result<u32, SdmmcError>, SdmmcProtocol<T, S, V>) {
+        let res: Result<u32, SdmmcError> =
+            self.rpmb_read_counter_inner(buffer, authenticator).await;
+        (res, self)
+    }
+
+    /// Authenticated write of one 256 bytes half sector to the RPMB partition at `address`
+    /// Returns the write counter after the write
+    /// The RPMB partition must be selected through emmc_select_partition first
+    pub async fn rpmb_write<A: RpmbAuthenticator>(
+        mut self,
+        buffer: &RpmbFrameBuffer,
+        authenticator: &mut A,
+        address: u16,
+        data: &[u8; RPMB_DATA_SIZE],
+    ) -> (Result<u32, SdmmcError>, SdmmcProtocol<T, S, V>) {
+        let res: Result<u32, SdmmcError> = self
+            .rpmb_write_inner(buffer, authenticator, address, data)
+            .await;
+        (res, self)
+    }
+
+    /// Authenticated read of one 256 bytes half sector from the RPMB partition at `address`
+    /// The RPMB partition must be selected through emmc_select_partition first
+    pub async fn rpmb_read<A: RpmbAuthenticator>(
+        mut self,
+        buffer: &RpmbFrameBuffer,
+        authenticator: &mut A,
+        address: u16,
+        data: &mut [u8; RPMB_DATA_SIZE],
+    ) -> (Result<(), SdmmcError>, SdmmcProtocol<T, S, V>) {
+        let res: Result<(), SdmmcError> = self
+            .rpmb_read_inner(buffer, authenticator, address, data)
+            .await;
+        (res, self)
+    }
+
+    async fn rpmb_program_key_inner(
+        &mut self,
+        buffer: &RpmbFrameBuffer,
+        key: &[u8; RPMB_KEY_MAC_SIZE],
+    ) -> Result<(), SdmmcError> {
+        self.rpmb_check_partition()?;
+
+        let mut frame: RpmbFrame = RpmbFrame::request(RPMB_REQ_KEY_PROGRAM);
+        frame.key_mac = *key;
+        buffer.write_frame(&frame, None)?;
+        self.rpmb_transfer_frame(buffer, MmcDataFlag::SdmmcDataWrite, true)
+            .await?;
+
+        self.rpmb_read_result(buffer, RPMB_RESP_KEY_PROGRAM, None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn rpmb_read_counter_inner(
+        &mut self,
+        buffer: &RpmbFrameBuffer,
+        authenticator: &mut dyn RpmbAuthenticator,
+    ) -> Result<u32, SdmmcError> {
+        self.rpmb_check_partition()?;
+
+        let mut frame: RpmbFrame = RpmbFrame::request(RPMB_REQ_READ_COUNTER);
+        frame.nonce = authenticator.rpmb_nonce();
+        buffer.write_frame(&frame, None)?;
+        self.rpmb_transfer_frame(buffer, MmcDataFlag::SdmmcDataWrite, false)
+            .await?;
+
+        self.rpmb_transfer_frame(buffer, MmcDataFlag::SdmmcDataRead, false)
+            .await?;
+        let response: RpmbFrame = buffer.read_frame(RPMB_RESP_READ_COUNTER, Some(authenticator))?;
+
+        if response.nonce != frame.nonce {
+            return Err(SdmmcError::ERPMB(RPMB_RESULT_AUTH_FAILURE));
+        }
+
+        Ok(response.write_counter)
+    }
+
+    async fn rpmb_write_inner(
+        &mut self,
+        buffer: &RpmbFrameBuffer,
+        authenticator: &mut dyn RpmbAuthenticator,
+        address: u16,
+        data: &[u8; RPMB_DATA_SIZE],
+    ) -> Result<u32, SdmmcError> {
+        let write_counter: u32 = self.rpmb_read_counter_inner(buffer, authenticator).await?;
+
+        let mut frame: RpmbFrame = RpmbFrame::request(RPMB_REQ_AUTH_WRITE);
+        frame.data = *data;
+        frame.write_counter = write_counter;
+        frame.address = address;
+        frame.block_count = 1;
+        buffer.write_frame(&frame, Some(authenticator))?;
+        self.rpmb_transfer_frame(buffer, MmcDataFlag::SdmmcDataWrite, true)
+            .await?;
+
+        let response: RpmbFrame = self
+            .rpmb_read_result(buffer, RPMB_RESP_AUTH_WRITE, Some(authenticator))
+            .await?;
+
+        if response.address != address || response.write_counter != write_counter.wrapping_add(1) {
+            return Err(SdmmcError::ERPMB(RPMB_RESULT_AUTH_FAILURE));
+        }
+
+        Ok(response.write_counter)
+    }
+
+    async fn rpmb_read_inner(
+        &mut self,
+        buffer: &RpmbFrameBuffer,
+        authenticator: &mut dyn RpmbAuthenticator,
+        address: u16,
+        data: &mut [u8; RPMB_DATA_SIZE],
+    ) -> Result<(), SdmmcError> {
+        self.rpmb_check_partition()?;
+
+        let mut frame: RpmbFrame = RpmbFrame::request(RPMB_REQ_AUTH_READ);
+        frame.nonce = authenticator.rpmb_nonce();
+        frame.address = address;
+        buffer.write_frame(&frame, None)?;
+        self.rpmb_transfer_frame(buffer, MmcDataFlag::SdmmcDataWrite, false)
+            .await?;
+
+        self.rpmb_transfer_frame(buffer, MmcDataFlag::SdmmcDataRead, false)
+            .await?;
+        let response: RpmbFrame = buffer.read_frame(RPMB_RESP_AUTH_READ, Some(authenticator))?;
+
+        if response.nonce != frame.nonce || response.address != address {
+            return Err(SdmmcError::ERPMB(RPMB_RESULT_AUTH_FAILURE));
+        }
+
+        *data = response.data;
+
+        Ok(())
+    }
+
+    /// Ask the eMMC for the result of the previous write request and read it back,
+    /// `expected_resp` is the response type of that write request
+    async fn rpmb_read_result(
+        &mut self,
+        buffer: &RpmbFrameBuffer,
+        expected_resp: u16,
+        authenticator: Option<&mut dyn RpmbAuthenticator>,
+    ) -> Result<RpmbFrame, SdmmcError> {
+        buffer.write_frame(&RpmbFrame::request(RPMB_REQ_RESULT_READ), None)?;
+        self.rpmb_transfer_frame(buffer, MmcDataFlag::SdmmcDataWrite, false)
+            .await?;
+
+        self.rpmb_transfer_frame(buffer, MmcDataFlag::SdmmcDataRead, false)
+            .await?;
+        buffer.read_frame(expected_resp, authenticator)
+    }
+
+    fn rpmb_check_partition(&self) -> Result<(), SdmmcError> {
+        match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) if emmc.current_partition == EmmcPartition::Rpmb => {
+                Ok(())
+            }
+            Some(MmcDevice::EMmc(_)) => Err(SdmmcError::EINVAL),
+            Some(_) => Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => Err(SdmmcError::ENOCARD),
+        }
+    }
+
+    /// Every RPMB frame is transferred with CMD23 followed by CMD25/CMD18,
+    /// requests that change the RPMB partition must set the reliable write bit in CMD23
+    async fn rpmb_transfer_frame(
+        &mut self,
+        buffer: &RpmbFrameBuffer,
+        flags: MmcDataFlag,
+        reliable_write: bool,
+    ) -> Result<(), SdmmcError> {
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        let mut resp: [u32; 4] = [0; 4];
+
+        let cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: match flags {
+                MmcDataFlag::SdmmcDataWrite => MMC_CMD_WRITE_MULTIPLE_BLOCK,
+                MmcDataFlag::SdmmcDataRead => MMC_CMD_READ_MULTIPLE_BLOCK,
+            },
+            resp_type: MMC_RSP_R1,
+            cmdarg: 0,
+        };
+
+        let data: MmcData = MmcData {
+            blocksize: RPMB_FRAME_SIZE as u32,
+            blockcnt: 1,
+            flags,
+            addr: buffer.physical_memory(),
+        };
+
+        let cmd23_flags: u32 = if reliable_write {
+            MMC_CMD23_ARG_REL_WR
+        } else {
+            0
+        };
+
+        Self::sdmmc_multi_blocks_io(
+            &mut self.hardware,
+            &cmd,
+            &data,
+            &mut resp,
+            BlockTransmissionMode::SetBlockCount,
+            cmd23_flags,
+        )
+        .await
+    }
+
+    /// Write back the data in the volatile cache of the eMMC to the non-volatile storage
+    /// Does nothing if the cache is not enabled, as every write already goes to the non-volatile storage
+    pub async fn flush(mut self) -> (Result<(), SdmmcError>, SdmmcProtocol<T, S, V>) {
+        let rca: u16 = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) if emmc.cache_enabled => emmc.relative_card_addr,
+            Some(_) => return (Ok(()), self),
+            None => return (Err(SdmmcError::ENOCARD), self),
+        };
+
+        if let Err(err) = self.emmc_stop_bkops().and_then(|_| self.cmdq_check_idle()) {
+            return (Err(err), self);
+        }
+
+        let mut resp: [u32; 4] = [0; 4];
+
+        // The card stays busy until the whole cache is flushed
+        let cmd: SdmmcCmd = EMmc::switch_cmd(EXT_CSD_FLUSH_CACHE, 1);
+
+        let mut res: Result<(), SdmmcError> =
+            Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await;
+
+        // The host may stop waiting for the busy signal before the flush finishes,
+        // and a flush rejected by the eMMC is only reported through SWITCH_ERROR
+        if res.is_ok() {
+            res = self
+                .emmc_wait_busy_async(rca, EMMC_CACHE_FLUSH_TIMEOUT_MS, MMC_STATUS_SWITCH_ERROR)
+                .await;
+        }
+
+        (res, self)
+    }
+
+    /// Update the firmware of the eMMC through field firmware update, check JESD84-B51 section 6.6.18
+    /// The firmware image of `blockcnt` blocks at `source` is written to FFU_ARG in FFU mode,
+    /// then the installation is started through MODE_OPERATION_CODES if the device supports it.
+    /// Otherwise the new firmware is only installed after the next power cycle or hardware reset,
+    /// which is reported as `FfuInstallation::PendingReset`
+    ///
+    /// # Safety
+    /// Same as tune_performance, the memory is used to read the FFU status from EXT_CSD
+    pub async unsafe fn emmc_ffu(
+        mut self,
+        blockcnt: u32,
+        source: u64,
+        memory: *mut [u8; EXT_CSD_SIZE],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> (Result<FfuInstallation, SdmmcError>, SdmmcProtocol<T, S, V>) {
+        let res: Result<FfuInstallation, SdmmcError> = unsafe {
+            self.emmc_ffu_inner(
+                blockcnt,
+                source,
+                memory,
+                cache_invalidate_function,
+                physical_memory_addr,
+            )
+        }
+        .await;
+        (res, self)
+    }
+
+    async unsafe fn emmc_ffu_inner(
+        &mut self,
+        blockcnt: u32,
+        source: u64,
+        memory: *mut [u8; EXT_CSD_SIZE],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<FfuInstallation, SdmmcError> {
+        let (rca, generic_cmd6_time_ms, ffu_arg, install_supported, install_timeout_ms) =
+            match self.mmc_device {
+                Some(MmcDevice::EMmc(ref emmc)) => {
+                    let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                    if !ext_csd.ffu_support {
+                        return Err(SdmmcError::EFFUUNSUPPORTED);
+                    }
+                    // The firmware is written with legacy cmds
+                    if emmc.cmdq_enabled {
+                        return Err(SdmmcError::EINVAL);
+                    }
+                    // The image is written in units of the data sector size
+                    if blockcnt == 0
+                        || blockcnt > 0xFFFF
+                        || !blockcnt.is_multiple_of(ext_csd.data_sector_size / 512)
+                    {
+                        return Err(SdmmcError::EINVAL);
+                    }
+                    (
+                        emmc.relative_card_addr,
+                        ext_csd.generic_cmd6_time_ms,
+                        ext_csd.ffu_arg,
+                        ext_csd.ffu_install_supported,
+                        ext_csd
+                            .operation_code_timeout_ms
+                            .max(ext_csd.generic_cmd6_time_ms),
+                    )
+                }
+                Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+                None => return Err(SdmmcError::ENOCARD),
+            };
+
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_MODE_CONFIG,
+            EXT_CSD_MODE_CONFIG_FFU,
+            generic_cmd6_time_ms,
+        )?;
+
+        let cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_WRITE_MULTIPLE_BLOCK,
+            resp_type: MMC_RSP_R1,
+            cmdarg: ffu_arg,
+        };
+
+        let data: MmcData = MmcData {
+            blocksize: SDCARD_DEFAULT_SECTOR_SIZE,
+            blockcnt,
+            flags: MmcDataFlag::SdmmcDataWrite,
+            addr: source,
+        };
+        let mut resp: [u32; 4] = [0; 4];
+
+        let res: Result<(), SdmmcError> = Self::sdmmc_multi_blocks_io(
+            &mut self.hardware,
+            &cmd,
+            &data,
+            &mut resp,
+            BlockTransmissionMode::SetBlockCount,
+            0,
+        )
+        .await;
+
+        if res.is_err() {
+            let cmd: SdmmcCmd = SdmmcCmd {
+                cmdidx: MMC_CMD_STOP_TRANSMISSION,
+                resp_type: MMC_RSP_R1B,
+                cmdarg: 0,
+            };
+            let _ = Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await;
+        }
+
+        // Installing the firmware brings the device back to normal mode by itself
+        let installation: FfuInstallation = if res.is_ok() && install_supported {
+            EMmc::emmc_switch(
+                &mut self.hardware,
+                &mut self.sleep,
+                rca,
+                EXT_CSD_MODE_OPERATION_CODES,
+                EXT_CSD_FFU_INSTALL,
+                install_timeout_ms,
+            )?;
+            FfuInstallation::Installed
+        } else {
+            EMmc::emmc_switch(
+                &mut self.hardware,
+                &mut self.sleep,
+                rca,
+                EXT_CSD_MODE_CONFIG,
+                EXT_CSD_MODE_CONFIG_NORMAL,
+                generic_cmd6_time_ms,
+            )?;
+            FfuInstallation::PendingReset
+        };
+
+        res?;
+
+        let ext_csd: ExtCsd = unsafe {
+            EMmc::emmc_get_ext_csd(
+                &mut self.hardware,
+                &mut self.sleep,
+                physical_memory_addr,
+                memory,
+                cache_invalidate_function,
+            )?
+        };
+
+        let ffu_status: u8 = ext_csd.ffu_status;
+        let sectors_expected: u32 = blockcnt / (ext_csd.data_sector_size / 512);
+        let sectors_programmed: u32 = ext_csd.fw_sectors_programmed;
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.update_ext_csd(ext_csd);
+        }
+
+        if ffu_status != FFU_STATUS_SUCCESS {
+            return Err(SdmmcError::EFFU(ffu_status));
+        }
+        if sectors_programmed != sectors_expected {
+            dev_log!(
+                "FFU only programmed {} out of {} sectors\n",
+                sectors_programmed,
+                sectors_expected
+            );
+            return Err(SdmmcError::EFFU(FFU_STATUS_DOWNLOAD_ERROR));
+        }
+
+        Ok(installation)
+    }
+
+    /// Function to execute one sdmmc request asynchronously
+    /// The resp could be used for future error parsing
+    async fn sdmmc_async_request(
+        hardware: &mut T,
+        cmd: &SdmmcCmd,
+        data: Option<&MmcData>,
+        resp: &mut [u32; 4],
+    ) -> Result<(), SdmmcError> {
+        // Send the command to the host sdcard
+        hardware.sdmmc_send_command(cmd, data)?;
+        // Wait until sdcard return the result to the driver
+        let res: Result<(), SdmmcError> = SdmmcCmdFuture::new(hardware, cmd, resp).await;
+        // Acknowledge interrupts
+        hardware.sdmmc_ack_interrupt()?;
+
+        res
+    }
+
+    /// Change this function so that if a request report back error
+    /// MMC_CMD_STOP_TRANSMISSION must be sent in the end so the card
+    /// stop transmission correctly
+    /// `cmd23_flags` is only used with BlockTransmissionMode::SetBlockCount
+    async fn sdmmc_multi_blocks_io(
+        hardware: &mut T,
+        request_cmd: &SdmmcCmd,
+        data: &MmcData,
+        resp: &mut [u32; 4],
+        transmission_mode: BlockTransmissionMode,
+        cmd23_flags: u32,
+    ) -> Result<(), SdmmcError> {
+        // Trasmission stage
+        match transmission_mode {
+            BlockTransmissionMode::SetBlockCount => {
+                let cmd: SdmmcCmd = SdmmcCmd {
+                    cmdidx: MMC_CMD_SET_BLOCK_COUNT,
+                    resp_type: MMC_RSP_R1,
+                    // block count for the upcoming CMD25 operation, plus eMMC request bits like reliable write
+                    cmdarg: data.blockcnt | cmd23_flags,
+                };
+
+                Self::sdmmc_async_request(hardware, &cmd, None, resp).await?;
+
+                Self::sdmmc_async_request(hardware, &request_cmd, Some(&data), resp).await
+            }
+            BlockTransmissionMode::StopTransmission => {
+                let temp_res: Result<(), SdmmcError> =
+                    Self::sdmmc_async_request(hardware, &request_cmd, Some(&data), resp).await;
+
+                // Dependent on whether the card is mmc or not, the response type is different
+                // TODO: Add mmc checks here
+                let cmd: SdmmcCmd = SdmmcCmd {
+                    cmdidx: MMC_CMD_STOP_TRANSMISSION,
+                    resp_type: MMC_RSP_R1B,
+                    cmdarg: 0,
+                };
+
+                // Technically this command has very little possibility to fail
+                // Unless for hardware or environment issue
+                let _ = Self::sdmmc_async_request(hardware, &cmd, None, resp).await;
+
+                temp_res
+            }
+            BlockTransmissionMode::AutoStop => {
+                Self::sdmmc_async_request(hardware, &request_cmd, Some(&data), resp).await
+            }
+        }
+    }
+
+    pub fn print_card_info(&self) {
+        if let Some(ref device) = self.mmc_device {
+            match device {
+                MmcDevice::Sdcard(sdcard) => {
+                    sdcard.print_info();
+                }
+                MmcDevice::EMmc(emmc) => {
+                    emmc.print_info();
+                }
+                MmcDevice::Unknown => {
+                    dev_log!("Unknown card!\n");
+                }
+            }
+        } else {
+            dev_log!("No available device! Try initialize the card first\n");
+        }
+    }
+
+    pub fn card_info(&self) -> Result<CardInfo, SdmmcError> {
+        let res: Result<CardInfo, SdmmcError>;
+        if let Some(ref device) = self.mmc_device {
+            match device {
+                MmcDevice::Sdcard(sdcard) => {
+                    res = Ok(sdcard.sdcard_info());
+                }
+                MmcDevice::EMmc(emmc) => {
+                    res = Ok(emmc.emmc_info());
+                }
+                MmcDevice::Unknown => {
+                    res = Err(SdmmcError::ENOTIMPLEMENTED);
+                }
+            }
+        } else {
+            res = Err(SdmmcError::ENOCARD);
+        }
+        res
+    }
+
+    /// Lifetime estimation and reserved blocks consumption of the eMMC
+    /// Only available after tune_performance as the values come from the EXT_CSD read there
+    pub fn health_report(&self) -> Result<HealthReport, SdmmcError> {
+        match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => emmc.health_report(),
+            Some(_) => Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => Err(SdmmcError::ENOCARD),
+        }
+    }
+
+    /// Read the raw 512 bytes vendor health report of the eMMC through CMD62
+    /// The layout of the report is vendor specific and not all devices implement it
+    /// The eMMC is taken out of the vendor mode again before returning, refused while the command queue is on
+    ///
+    /// # Safety
+    /// `memory` and `physical_memory_addr` must point to the same 512 bytes region that the host can DMA into,
+    /// `cache_invalidate_function` should invalidate the cache for that region
+    pub unsafe fn emmc_vendor_health_report(
+        &mut self,
+        memory: *mut [u8; EXT_CSD_SIZE],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<[u8; EXT_CSD_SIZE], SdmmcError> {
+        match self.mmc_device {
+            // The report is read through CMD17, which is not accepted while the command queue is on
+            Some(MmcDevice::EMmc(ref emmc)) if emmc.cmdq_enabled => return Err(SdmmcError::EINVAL),
+            Some(MmcDevice::EMmc(_)) => {}
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        }
+
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        unsafe {
+            EMmc::emmc_vendor_health_report(
+                &mut self.hardware,
+                &mut self.sleep,
+                physical_memory_addr,
+                memory,
+                cache_invalidate_function,
+            )
+        }
+    }
+
+    /// List the hardware partitions of the eMMC
+    /// Only available after tune_performance as the partition sizes come from the EXT_CSD
+    pub fn emmc_partition_info(&self) -> Result<EmmcPartitionInfo, SdmmcError> {
+        match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => emmc.partition_info(),
+            Some(_) => Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => Err(SdmmcError::ENOCARD),
+        }
+    }
+
+    /// Switch the hardware partition accessed by read_block/write_block through PARTITION_CONFIG
+    /// The block index used by read_block/write_block is relative to the start of the selected partition
+    /// While the RPMB partition is selected, read_block/write_block are refused, use the rpmb_* functions instead
+    pub fn emmc_select_partition(&mut self, partition: EmmcPartition) -> Result<(), SdmmcError> {
+        let rca: u16;
+        let partition_config: u8;
+        let timeout_ms: u32;
+        match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                if emmc.partition_info()?.size(partition) == 0 {
+                    return Err(SdmmcError::EINVAL);
+                }
+                if emmc.current_partition == partition {
+                    return Ok(());
+                }
+                // RPMB cannot be accessed through the command queue
+                if partition == EmmcPartition::Rpmb && emmc.cmdq_enabled {
+                    return Err(SdmmcError::EINVAL);
+                }
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                rca = emmc.relative_card_addr;
+                partition_config =
+                    (ext_csd.partition_config & !EXT_CSD_PART_CONFIG_ACC_MASK) | partition as u8;
+                timeout_ms = if ext_csd.partition_switch_time_ms != 0 {
+                    ext_csd.partition_switch_time_ms
+                } else {
+                    ext_csd.generic_cmd6_time_ms
+                };
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        }
+
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_PART_CONFIG,
+            partition_config,
+            timeout_ms,
+        )?;
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.current_partition = partition;
+            if let Some(ref mut ext_csd) = emmc.ext_csd {
+                ext_csd.partition_config = partition_config;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the general purpose partitions and the enhanced user data area, then set PARTITION_SETTING_COMPLETED
+    /// The layout is checked against MAX_ENH_SIZE_MULT and HC_WP_GRP_SIZE first, with `dry_run` nothing is written.
+    /// This can only be done once in the lifetime of the eMMC, the new layout takes effect after the next power cycle.
+    /// If this fails halfway, the eMMC drops the partial layout at the next power cycle and it can be tried again
+    pub fn emmc_provision_partitions(
+        &mut self,
+        layout: &EmmcPartitionLayout,
+        dry_run: bool,
+    ) -> Result<EmmcPartitionPlan, SdmmcError> {
+        let (rca, generic_cmd6_time_ms, mut plan, registers) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                (
+                    emmc.relative_card_addr,
+                    ext_csd.generic_cmd6_time_ms,
+                    emmc.check_partition_layout(layout)?,
+                    emmc.partition_layout_registers(layout)?,
+                )
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        if dry_run {
+            return Ok(plan);
+        }
+
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        // The partition sizes are in high capacity write protect groups, which requires ERASE_GROUP_DEF
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_ERASE_GROUP_DEF,
+            1,
+            generic_cmd6_time_ms,
+        )?;
+        if let Some(MmcDevice::EMmc(EMmc {
+            ext_csd: Some(ref mut ext_csd),
+            ..
+        })) = self.mmc_device
+        {
+            ext_csd.erase_group_def = true;
+        }
+
+        for (index, value) in registers {
+            EMmc::emmc_switch(
+                &mut self.hardware,
+                &mut self.sleep,
+                rca,
+                index,
+                value,
+                generic_cmd6_time_ms,
+            )?;
+        }
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_PARTITION_SETTING_COMPLETED,
+            EXT_CSD_PARTITION_SETTING_COMPLETE,
+            generic_cmd6_time_ms,
+        )?;
+        if let Some(MmcDevice::EMmc(EMmc {
+            ext_csd: Some(ref mut ext_csd),
+            ..
+        })) = self.mmc_device
+        {
+            ext_csd.partition_setting_completed = true;
+        }
+
+        info!("eMMC partition layout is written, power cycle the device to apply it\n");
+
+        plan.committed = true;
+        Ok(plan)
+    }
+
+    /// Choose the partition the eMMC boots from and whether the boot acknowledge is sent,
+    /// through BOOT_PARTITION_ENABLE and BOOT_ACK of PARTITION_CONFIG
+    ///
+    /// # Safety
+    /// Same as tune_performance, the memory is used to read the EXT_CSD back
+    pub unsafe fn emmc_set_boot_partition(
+        &mut self,
+        partition: EmmcBootPartition,
+        boot_ack: bool,
+        memory: *mut [u8; EXT_CSD_SIZE],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<(), SdmmcError> {
+        let partition_config: u8 = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                if partition != EmmcBootPartition::Disabled
+                    && partition != EmmcBootPartition::UserData
+                    && ext_csd.boot_partition_size == 0
+                {
+                    return Err(SdmmcError::EINVAL);
+                }
+                // The partition being accessed is kept unchanged
+                let mut value: u8 = (ext_csd.partition_config & EXT_CSD_PART_CONFIG_ACC_MASK)
+                    | ((partition as u8) << EXT_CSD_BOOT_PART_ENABLE_SHIFT);
+                if boot_ack {
+                    value |= EXT_CSD_BOOT_ACK;
+                }
+                value
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        unsafe {
+            self.emmc_switch_verified(
+                EXT_CSD_PART_CONFIG,
+                partition_config,
+                |ext_csd| ext_csd.partition_config == partition_config,
+                memory,
+                cache_invalidate_function,
+                physical_memory_addr,
+            )
+        }
+    }
+
+    /// Configure the bus used by the boot operation through BOOT_BUS_CONDITIONS
+    ///
+    /// # Safety
+    /// Same as tune_performance, the memory is used to read the EXT_CSD back
+    pub unsafe fn emmc_set_boot_bus_conditions(
+        &mut self,
+        conditions: EmmcBootBusConditions,
+        memory: *mut [u8; EXT_CSD_SIZE],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<(), SdmmcError> {
+        let value: u8 = conditions.value()?;
+
+        unsafe {
+            self.emmc_switch_verified(
+                EXT_CSD_BOOT_BUS_CONDITIONS,
+                value,
+                |ext_csd| ext_csd.boot_bus_conditions == value,
+                memory,
+                cache_invalidate_function,
+                physical_memory_addr,
+            )
+        }
+    }
+
+    /// Write protect the boot partitions through BOOT_WP, `partition` is Boot0 or Boot1, None protects both
+    /// Permanent write protection can never be removed, check B_PERM_WP_DIS before using it
+    ///
+    /// # Safety
+    /// Same as tune_performance, the memory is used to read the EXT_CSD back
+    pub unsafe fn emmc_set_boot_wp(
+        &mut self,
+        protect: EmmcBootWriteProtect,
+        partition: Option<EmmcPartition>,
+        memory: *mut [u8; EXT_CSD_SIZE],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<(), SdmmcError> {
+        let (enable, section_select, disabled) = match protect {
+            EmmcBootWriteProtect::PowerOn => (
+                EXT_CSD_BOOT_WP_B_PWR_WP_EN,
+                EXT_CSD_BOOT_WP_B_PWR_WP_SEC_SEL,
+                EXT_CSD_BOOT_WP_B_PWR_WP_DIS,
+            ),
+            EmmcBootWriteProtect::Permanent => (
+                EXT_CSD_BOOT_WP_B_PERM_WP_EN,
+                EXT_CSD_BOOT_WP_B_PERM_WP_SEC_SEL,
+                EXT_CSD_BOOT_WP_B_PERM_WP_DIS,
+            ),
+        };
+
+        // Without B_SEC_WP_SEL, the protection applies to both boot partitions
+        let value: u8 = match partition {
+            None => enable,
+            Some(EmmcPartition::Boot0) => enable | EXT_CSD_BOOT_WP_B_SEC_WP_SEL,
+            Some(EmmcPartition::Boot1) => enable | EXT_CSD_BOOT_WP_B_SEC_WP_SEL | section_select,
+            Some(_) => return Err(SdmmcError::EINVAL),
+        };
+
+        match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                if ext_csd.boot_partition_size == 0 || ext_csd.boot_wp & disabled != 0 {
+                    return Err(SdmmcError::EINVAL);
+                }
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        }
+
+        unsafe {
+            self.emmc_switch_verified(
+                EXT_CSD_BOOT_WP,
+                value,
+                |ext_csd| ext_csd.boot_wp & value == value,
+                memory,
+                cache_invalidate_function,
+                physical_memory_addr,
+            )
+        }
+    }
+
+    /// Size of the write protect group of the eMMC in number of 512 bytes sectors
+    /// Only available after tune_performance as the group size depends on ERASE_GROUP_DEF
+    pub fn emmc_write_protect_group_size(&self) -> Result<u32, SdmmcError> {
+        match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => match emmc.write_protect_group_size() {
+                0 => Err(SdmmcError::EUNSUPPORTEDCARD),
+                group_size => Ok(group_size),
+            },
+            Some(_) => Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => Err(SdmmcError::ENOCARD),
+        }
+    }
+
+    /// Write protect `groups` write protect groups starting from `start_idx` through CMD28
+    /// `start_idx` must be aligned to emmc_write_protect_group_size.
+    /// Power on and permanent protection are selected through USER_WP, the enable bits are cleared afterwards.
+    /// Permanent protection can never be removed
+    pub fn emmc_set_write_protect(
+        &mut self,
+        start_idx: u64,
+        groups: u32,
+        protect: WriteProtectType,
+    ) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms, group_size, user_wp) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                let group_size: u32 = emmc.check_write_protect_range(start_idx, groups)?;
+                let (enable, disabled) = match protect {
+                    WriteProtectType::None => return Err(SdmmcError::EINVAL),
+                    WriteProtectType::Temporary => (0, 0),
+                    WriteProtectType::PowerOn => (EXT_CSD_US_PWR_WP_EN, EXT_CSD_US_PWR_WP_DIS),
+                    WriteProtectType::Permanent => (EXT_CSD_US_PERM_WP_EN, EXT_CSD_US_PERM_WP_DIS),
+                };
+                if ext_csd.user_wp & disabled != 0 {
+                    return Err(SdmmcError::EINVAL);
+                }
+                (
+                    emmc.relative_card_addr,
+                    ext_csd.generic_cmd6_time_ms,
+                    group_size,
+                    (ext_csd.user_wp & !(EXT_CSD_US_PWR_WP_EN | EXT_CSD_US_PERM_WP_EN)) | enable,
+                )
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        self.emmc_set_user_wp(rca, user_wp, generic_cmd6_time_ms)?;
+
+        let res: Result<(), SdmmcError> = self.emmc_write_protect_groups(
+            MMC_CMD_SET_WRITE_PROT,
+            rca,
+            start_idx,
+            groups,
+            group_size,
+            generic_cmd6_time_ms,
+        );
+
+        // Later CMD28 sends should not apply the same protection by accident
+        let restore: u8 = user_wp & !(EXT_CSD_US_PWR_WP_EN | EXT_CSD_US_PERM_WP_EN);
+        self.emmc_set_user_wp(rca, restore, generic_cmd6_time_ms)?;
+
+        res
+    }
+
+    /// Remove the temporary write protection of `groups` write protect groups starting from `start_idx` through CMD29
+    pub fn emmc_clear_write_protect(
+        &mut self,
+        start_idx: u64,
+        groups: u32,
+    ) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms, group_size) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                (
+                    emmc.relative_card_addr,
+                    ext_csd.generic_cmd6_time_ms,
+                    emmc.check_write_protect_range(start_idx, groups)?,
+                )
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        self.emmc_write_protect_groups(
+            MMC_CMD_CLR_WRITE_PROT,
+            rca,
+            start_idx,
+            groups,
+            group_size,
+            generic_cmd6_time_ms,
+        )
+    }
+
+    /// Whether the 32 write protect groups starting from `start_idx` are write protected, read through CMD30
+    /// Bit N is set if the Nth group is protected
+    ///
+    /// # Safety
+    /// `memory` and `physical_memory_addr` must point to the same region that the host can DMA into,
+    /// `cache_invalidate_function` should invalidate the cache for that region
+    pub unsafe fn emmc_write_protect_status(
+        &mut self,
+        start_idx: u64,
+        memory: *mut [u8; 64],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<u32, SdmmcError> {
+        let value: u64 = unsafe {
+            self.emmc_read_write_protect(
+                MMC_CMD_SEND_WRITE_PROT,
+                start_idx,
+                memory,
+                cache_invalidate_function,
+                physical_memory_addr,
+            )?
+        };
+
+        Ok(value as u32)
+    }
+
+    /// Type of the write protection of the 32 write protect groups starting from `start_idx`, read through CMD31
+    ///
+    /// # Safety
+    /// Same as emmc_write_protect_status
+    pub unsafe fn emmc_write_protect_type(
+        &mut self,
+        start_idx: u64,
+        memory: *mut [u8; 64],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<[WriteProtectType; 32], SdmmcError> {
+        let value: u64 = unsafe {
+            self.emmc_read_write_protect(
+                MMC_CMD_SEND_WRITE_PROT_TYPE,
+                start_idx,
+                memory,
+                cache_invalidate_function,
+                physical_memory_addr,
+            )?
+        };
+
+        // Two bits for each group, the first group is in the lowest two bits
+        let mut types: [WriteProtectType; 32] = [WriteProtectType::None; 32];
+        for (i, wp_type) in types.iter_mut().enumerate() {
+            *wp_type = WriteProtectType::new(((value >> (i * 2)) & 0x3) as u8);
+        }
+
+        Ok(types)
+    }
+
+    unsafe fn emmc_read_write_protect(
+        &mut self,
+        cmdidx: u32,
+        start_idx: u64,
+        memory: *mut [u8; 64],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<u64, SdmmcError> {
+        let address: u32 = match self.mmc_device {
+            Some(ref device @ MmcDevice::EMmc(ref emmc)) => {
+                emmc.check_write_protect_range(start_idx, 1)?;
+                device.card_address(start_idx)?.0
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        unsafe {
+            EMmc::emmc_send_write_protect(
+                &mut self.hardware,
+                &mut self.sleep,
+                cmdidx,
+                address,
+                physical_memory_addr,
+                memory,
+                cache_invalidate_function,
+            )
+        }
+    }
+
+    /// Send CMD28 or CMD29 to every group in the range, each of them keeps the eMMC busy while programming
+    /// Every address is resolved before the first cmd, so an address out of range does not leave
+    /// the range partially protected
+    fn emmc_write_protect_groups(
+        &mut self,
+        cmdidx: u32,
+        rca: u16,
+        start_idx: u64,
+        groups: u32,
+        group_size: u32,
+        timeout_ms: u32,
+    ) -> Result<(), SdmmcError> {
+        let mut resp: [u32; 4] = [0; 4];
+
+        let group_address = |device: &MmcDevice, group: u64| -> Result<u32, SdmmcError> {
+            let idx: u64 = group
+                .checked_mul(group_size as u64)
+                .and_then(|offset| start_idx.checked_add(offset))
+                .ok_or(SdmmcError::EINVAL)?;
+            Ok(device.card_address(idx)?.0)
+        };
+
+        let device: &MmcDevice = self.mmc_device.as_ref().ok_or(SdmmcError::ENOCARD)?;
+        // The addresses grow with the group, so checking the last group covers the whole range
+        if groups != 0 {
+            group_address(device, groups as u64 - 1)?;
+        }
+
+        for group in 0..groups as u64 {
+            let cmdarg: u32 =
+                group_address(self.mmc_device.as_ref().ok_or(SdmmcError::ENOCARD)?, group)?;
+
+            let cmd: SdmmcCmd = SdmmcCmd {
+                cmdidx,
+                resp_type: MMC_RSP_R1B,
+                cmdarg,
+            };
+
+            self.hardware
+                .sdmmc_do_request(&mut self.sleep, &cmd, None, &mut resp, 0)?;
+
+            EMmc::emmc_wait_busy(
+                &mut self.hardware,
+                &mut self.sleep,
+                rca,
+                timeout_ms,
+                MMC_STATUS_WP_ERRORS,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn emmc_set_user_wp(
+        &mut self,
+        rca: u16,
+        user_wp: u8,
+        generic_cmd6_time_ms: u32,
+    ) -> Result<(), SdmmcError> {
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_USER_WP,
+            user_wp,
+            generic_cmd6_time_ms,
+        )?;
+
+        if let Some(MmcDevice::EMmc(EMmc {
+            ext_csd: Some(ref mut ext_csd),
+            ..
+        })) = self.mmc_device
+        {
+            ext_csd.user_wp = user_wp;
+        }
+
+        Ok(())
+    }
+
+    /// Write one EXT_CSD byte through CMD6, then read the EXT_CSD back and check the new value with `verify`
+    /// Returns EIO if the eMMC has not taken the value
+    unsafe fn emmc_switch_verified(
+        &mut self,
+        index: usize,
+        value: u8,
+        verify: impl Fn(&ExtCsd) -> bool,
+        memory: *mut [u8; EXT_CSD_SIZE],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                (emmc.relative_card_addr, ext_csd.generic_cmd6_time_ms)
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            index,
+            value,
+            generic_cmd6_time_ms,
+        )?;
+
+        let ext_csd: ExtCsd = unsafe {
+            EMmc::emmc_get_ext_csd(
+                &mut self.hardware,
+                &mut self.sleep,
+                physical_memory_addr,
+                memory,
+                cache_invalidate_function,
+            )?
+        };
+
+        let matched: bool = verify(&ext_csd);
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.update_ext_csd(ext_csd);
+        }
+
+        if !matched {
+            dev_log!("EXT_CSD[{}] does not read back as {:#x}\n", index, value);
+            return Err(SdmmcError::EIO);
+        }
+
+        Ok(())
+    }
+
+    /// Turn the volatile cache of the eMMC on or off through CACHE_CTRL
+    /// With the cache on, data is only guaranteed to be durable after flush() or a force unit access write
+    /// Turning the cache off flushes the cache as well
+    pub fn emmc_enable_cache(&mut self, enable: bool) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                if ext_csd.cache_size == 0 {
+                    return Err(SdmmcError::EUNSUPPORTEDCARD);
+                }
+                if emmc.cache_enabled == enable {
+                    return Ok(());
+                }
+                (emmc.relative_card_addr, ext_csd.generic_cmd6_time_ms)
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_CACHE_CTRL,
+            enable as u8,
+            generic_cmd6_time_ms,
+        )?;
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.cache_enabled = enable;
+        }
+
+        Ok(())
+    }
+
+    /// Physically remove the data in the unmapped user data area through SANITIZE_START
+    /// Blocks until the sanitize finishes, which could take several minutes
+    pub fn emmc_sanitize(&mut self) -> Result<(), SdmmcError> {
+        let rca: u16 = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                if ext_csd.sec_feature_support & EXT_CSD_SEC_SANITIZE == 0 {
+                    return Err(SdmmcError::EUNSUPPORTEDCARD);
+                }
+                emmc.relative_card_addr
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_SANITIZE_START,
+            1,
+            EMMC_SANITIZE_TIMEOUT_MS,
+        )
+    }
+
+    /// Enable the background operations of the eMMC through BKOPS_EN
+    /// Manual mode also turns on HPI, so the background operations could be interrupted when I/O arrives
+    /// Manual enable is one time programmable on some devices
+    pub fn emmc_enable_bkops(&mut self, mode: BkopsMode) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms, bkops_en) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                if !ext_csd.bkops_support {
+                    return Err(SdmmcError::EUNSUPPORTEDCARD);
+                }
+                let bit: u8 = match mode {
+                    BkopsMode::Manual => {
+                        // Without HPI, I/O would wait until the background operations finish
+                        if ext_csd.hpi_features & EXT_CSD_HPI_SUPPORT == 0 {
+                            return Err(SdmmcError::EUNSUPPORTEDCARD);
+                        }
+                        EXT_CSD_BKOPS_MANUAL_EN
+                    }
+                    BkopsMode::Auto => {
+                        if ext_csd.revision < 8 {
+                            return Err(SdmmcError::EUNSUPPORTEDCARD);
+                        }
+                        EXT_CSD_BKOPS_AUTO_EN
+                    }
+                };
+                (
+                    emmc.relative_card_addr,
+                    ext_csd.generic_cmd6_time_ms,
+                    ext_csd.bkops_en | bit,
+                )
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        if mode == BkopsMode::Manual {
+            self.emmc_enable_hpi()?;
+        }
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_BKOPS_EN,
+            bkops_en,
+            generic_cmd6_time_ms,
+        )?;
+
+        if let Some(MmcDevice::EMmc(EMmc {
+            ext_csd: Some(ref mut ext_csd),
+            ..
+        })) = self.mmc_device
+        {
+            ext_csd.bkops_en = bkops_en;
+        }
+
+        Ok(())
+    }
+
+    /// Called by the host when there is no pending I/O, the EXT_CSD is read again to get BKOPS_STATUS
+    /// and the manual background operations are started if the policy agrees
+    /// The next request interrupts the background operations with HPI
+    /// Returns whether the background operations are running
+    ///
+    /// # Safety
+    /// `memory` and `physical_memory_addr` must point to the same 512 bytes region that the host can DMA into,
+    /// `cache_invalidate_function` should invalidate the cache for that region
+    pub unsafe fn emmc_bkops_idle<P: BkopsPolicy>(
+        &mut self,
+        policy: &mut P,
+        memory: *mut [u8; EXT_CSD_SIZE],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<bool, SdmmcError> {
+        match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                if ext_csd.bkops_en & EXT_CSD_BKOPS_MANUAL_EN == 0 {
+                    return Err(SdmmcError::EINVAL);
+                }
+                if emmc.bkops_running {
+                    return Ok(true);
+                }
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        }
+
+        self.cmdq_check_idle()?;
+
+        let ext_csd: ExtCsd = unsafe {
+            EMmc::emmc_get_ext_csd(
+                &mut self.hardware,
+                &mut self.sleep,
+                physical_memory_addr,
+                memory,
+                cache_invalidate_function,
+            )?
+        };
+
+        let status: BkopsStatus = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref mut emmc)) => {
+                emmc.update_ext_csd(ext_csd);
+                emmc.bkops_status()?
+            }
+            _ => return Err(SdmmcError::EUNDEFINED),
+        };
+
+        if status == BkopsStatus::NotRequired || !policy.bkops_should_start(status) {
+            return Ok(false);
+        }
+
+        // The eMMC stays busy until the background operations finish, so the busy signal is not waited for
+        let mut cmd: SdmmcCmd = EMmc::switch_cmd(EXT_CSD_BKOPS_START, 1);
+        cmd.resp_type = MMC_RSP_R1;
+        let mut resp: [u32; 4] = [0; 4];
+
+        self.hardware
+            .sdmmc_do_request(&mut self.sleep, &cmd, None, &mut resp, 0)?;
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.bkops_running = true;
+        }
+
+        Ok(true)
+    }
+
+    /// Interrupt the manual background operations with HPI if they are still running
+    /// Called before every request to the eMMC, does nothing for sdcard
+    pub fn emmc_stop_bkops(&mut self) -> Result<(), SdmmcError> {
+        let (rca, hpi_features, timeout_ms) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) if emmc.bkops_running => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                (
+                    emmc.relative_card_addr,
+                    ext_csd.hpi_features,
+                    ext_csd.hpi_timeout_ms(),
+                )
+            }
+            _ => return Ok(()),
+        };
+
+        // HPI is only valid while the eMMC is still busy with the background operations
+        let status: u32 = EMmc::emmc_send_status(&mut self.hardware, &mut self.sleep, rca)?;
+        if status & MMC_STATUS_CURR_STATE == MMC_STATE_PRG {
+            EMmc::emmc_send_hpi(&mut self.hardware, &mut self.sleep, rca, hpi_features)?;
+            EMmc::emmc_wait_busy(&mut self.hardware, &mut self.sleep, rca, timeout_ms, 0)?;
+        }
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.bkops_running = false;
+        }
+
+        Ok(())
+    }
+
+    /// Let `trigger` interrupt long writes of the eMMC through HPI, so a latency critical read does not wait for them
+    /// Once the trigger is set, the write being executed resolves with EINTERRUPTED after the eMMC has left it,
+    /// the data of that write is incomplete, emmc_interrupted_write_progress tells where to resume it.
+    /// The driver clears the trigger when it interrupts a write. None removes the trigger, HPI itself stays enabled
+    /// Writes are only interrupted if the host implements sdmmc_abort_data
+    pub fn emmc_set_hpi_trigger(
+        &mut self,
+        trigger: Option<&'static AtomicBool>,
+    ) -> Result<(), SdmmcError> {
+        if trigger.is_some() {
+            self.emmc_stop_bkops()?;
+            self.cmdq_check_idle()?;
+            self.emmc_enable_hpi()?;
+        }
+
+        self.hpi_trigger = trigger;
+
+        Ok(())
+    }
+
+    /// Turn on HPI through HPI_MGMT
+    fn emmc_enable_hpi(&mut self) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                if ext_csd.hpi_features & EXT_CSD_HPI_SUPPORT == 0 {
+                    return Err(SdmmcError::EUNSUPPORTEDCARD);
+                }
+                if emmc.hpi_enabled {
+                    return Ok(());
+                }
+                (emmc.relative_card_addr, ext_csd.generic_cmd6_time_ms)
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_HPI_MGMT,
+            1,
+            generic_cmd6_time_ms,
+        )?;
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.hpi_enabled = true;
+        }
+
+        Ok(())
+    }
+
+    /// Write through CMD23 and CMD25, the write can be interrupted by the HPI trigger
+    /// while the data is being transferred or programmed
+    /// `card_address` is the address resolved by card_address, the card status of CMD25 is left in `resp`
+    async fn emmc_interruptible_write(
+        &mut self,
+        data: &MmcData,
+        card_address: u32,
+        cmd23_flags: u32,
+        trigger: &'static AtomicBool,
+        resp: &mut [u32; 4],
+    ) -> Result<(), SdmmcError> {
+        let mut cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_SET_BLOCK_COUNT,
+            resp_type: MMC_RSP_R1,
+            cmdarg: data.blockcnt | cmd23_flags,
+        };
+
+        Self::sdmmc_async_request(&mut self.hardware, &cmd, None, resp).await?;
+
+        cmd = SdmmcCmd {
+            cmdidx: MMC_CMD_WRITE_MULTIPLE_BLOCK,
+            resp_type: MMC_RSP_R1,
+            cmdarg: card_address,
+        };
+
+        self.hardware.sdmmc_send_command(&cmd, Some(data))?;
+        let mut res: Result<(), SdmmcError> = SdmmcCmdFuture::new(&mut self.hardware, &cmd, resp)
+            .with_interrupt(trigger)
+            .await;
+
+        if let Err(SdmmcError::EINTERRUPTED) = res {
+            // The data is still being transferred, the host has to let go of it before the card is stopped
+            match self.hardware.sdmmc_abort_data() {
+                Ok(()) => {
+                    self.hardware.sdmmc_ack_interrupt()?;
+                    self.emmc_interrupt_write().await?;
+                    return Err(SdmmcError::EINTERRUPTED);
+                }
+                Err(SdmmcError::ENOTIMPLEMENTED) => {
+                    dev_log!("The host cannot abort the data transfer, let the write finish\n");
+                    res = SdmmcCmdFuture::new(&mut self.hardware, &cmd, resp).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        self.hardware.sdmmc_ack_interrupt()?;
+
+        match res {
+            Err(err) => {
+                cmd = SdmmcCmd {
+                    cmdidx: MMC_CMD_STOP_TRANSMISSION,
+                    resp_type: MMC_RSP_R1B,
+                    cmdarg: 0,
+                };
+                let mut stop_resp: [u32; 4] = [0; 4];
+                let _ =
+                    Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut stop_resp).await;
+                Err(err)
+            }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// Bring the eMMC back to transfer state through HPI after the write being executed is interrupted,
+    /// check mmc_interrupt_hpi() in Linux
+    async fn emmc_interrupt_write(&mut self) -> Result<(), SdmmcError> {
+        let (rca, hpi_features, timeout_ms) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                (
+                    emmc.relative_card_addr,
+                    ext_csd.hpi_features,
+                    ext_csd.hpi_timeout_ms(),
+                )
+            }
+            _ => return Err(SdmmcError::EUNDEFINED),
+        };
+
+        let mut resp: [u32; 4] = [0; 4];
+        let cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_SEND_STATUS,
+            resp_type: MMC_RSP_R1,
+            cmdarg: (rca as u32) << 16,
+        };
+
+        Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await?;
+
+        match resp[0] & MMC_STATUS_CURR_STATE {
+            MMC_STATE_RCV => {
+                // HPI through CMD13 is only accepted in programming state, so the data transfer is stopped first
+                // without waiting for the busy signal
+                if hpi_features & EXT_CSD_HPI_IMPL_CMD12 == 0 {
+                    let cmd: SdmmcCmd = SdmmcCmd {
+                        cmdidx: MMC_CMD_STOP_TRANSMISSION,
+                        resp_type: MMC_RSP_R1,
+                        cmdarg: 0,
+                    };
+                    Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await?;
+                }
+            }
+            MMC_STATE_PRG => {}
+            // The write has already finished
+            _ => return Ok(()),
+        }
+
+        let cmd: SdmmcCmd = EMmc::hpi_cmd(rca, hpi_features);
+        Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await?;
+
+        self.emmc_wait_busy_async(rca, timeout_ms, 0).await
+    }
+
+    /// Number of blocks programmed by the last write interrupted through HPI, read from CORRECTLY_PRG_SECTORS_NUM
+    /// The interrupted write is resumed by writing the rest of the blocks from that point on,
+    /// as long as no other write has been issued in between
+    ///
+    /// # Safety
+    /// Same as tune_performance, the memory is used to read EXT_CSD
+    pub unsafe fn emmc_interrupted_write_progress(
+        &mut self,
+        memory: *mut [u8; EXT_CSD_SIZE],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
+    ) -> Result<u32, SdmmcError> {
+        match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) if emmc.hpi_enabled => {}
+            Some(MmcDevice::EMmc(_)) => return Err(SdmmcError::EINVAL),
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        }
+
+        self.cmdq_check_idle()?;
+
+        let ext_csd: ExtCsd = unsafe {
+            EMmc::emmc_get_ext_csd(
+                &mut self.hardware,
+                &mut self.sleep,
+                physical_memory_addr,
+                memory,
+                cache_invalidate_function,
+            )?
+        };
+
+        Ok(ext_csd.correctly_prg_sectors_num)
+    }
+
+    /// Turn the command queue of the eMMC on or off through CMDQ_MODE_EN, introduced in eMMC 5.1
+    /// While the command queue is on, read_block and write_block go through the queue one task at a time
+    /// and the RPMB partition cannot be selected
+    pub fn emmc_enable_cmdq(&mut self, enable: bool) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms, depth) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                let ext_csd: &ExtCsd = emmc.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+                if !ext_csd.cmdq_support {
+                    return Err(SdmmcError::EUNSUPPORTEDCARD);
+                }
+                if emmc.cmdq_enabled == enable {
+                    return Ok(());
+                }
+                if enable && emmc.current_partition == EmmcPartition::Rpmb {
+                    return Err(SdmmcError::EINVAL);
+                }
+                (
+                    emmc.relative_card_addr,
+                    ext_csd.generic_cmd6_time_ms,
+                    ext_csd.cmdq_depth,
+                )
+            }
+            Some(_) => return Err(SdmmcError::EUNSUPPORTEDCARD),
+            None => return Err(SdmmcError::ENOCARD),
+        };
+
+        if !self.cmdq.is_empty() {
+            return Err(SdmmcError::EBUSY);
+        }
+
+        self.emmc_stop_bkops()?;
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_CMDQ_MODE_EN,
+            enable as u8,
+            generic_cmd6_time_ms,
+        )?;
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.cmdq_enabled = enable;
+        }
+        self.cmdq = CmdqQueue::new(if enable { depth } else { 1 });
+
+        Ok(())
+    }
+
+    /// Queue one read or write task, returns the task tag used to report its completion in cmdq_execute
+    /// Returns EBUSY if every tag is in use, the completions need to be collected through cmdq_execute first
+    /// When the command queue is not enabled, e.g. the device reports CMDQ_SUPPORT=0 or the card is a sdcard,
+    /// the request is executed right away through the legacy path with a queue depth of one
+    pub async fn cmdq_submit(
+        mut self,
+        request: CmdqRequest,
+    ) -> (Result<u8, SdmmcError>, SdmmcProtocol<T, S, V>) {
+        let tag: u8 = match self.cmdq.alloc_tag() {
+            Ok(tag) => tag,
+            Err(err) => return (Err(err), self),
         };
 
-        res = Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await;
+        if self.emmc_cmdq_enabled() {
+            let res: Result<(), SdmmcError> = self.cmdq_queue_task(tag, request).await;
+            return (res.map(|_| tag), self);
+        }
 
-        if let Err(_) = res {
-            return (res, self);
+        let (res, mut protocol) = match request.flags {
+            MmcDataFlag::SdmmcDataRead => {
+                self.read_block(request.blockcnt, request.start_idx, request.addr)
+                    .await
+            }
+            MmcDataFlag::SdmmcDataWrite => {
+                self.write_block_with_flags(
+                    request.blockcnt,
+                    request.start_idx,
+                    request.addr,
+                    request.write_flags,
+                )
+                .await
+            }
+        };
+        if res.is_ok() {
+            protocol.cmdq.complete(tag);
+        }
+
+        (res.map(|_| tag), protocol)
+    }
+
+    /// Execute the queued tasks in the order the eMMC prefers until the queue is empty
+    /// Returns the completion bitmap, bit N is set if the task with tag N has finished and the tag can be reused
+    /// If a task fails, the whole queue is discarded and the tasks not reported as completed have to be submitted again,
+    /// the tasks finished before the failure are reported by the next cmdq_execute
+    pub async fn cmdq_execute(mut self) -> (Result<u32, SdmmcError>, SdmmcProtocol<T, S, V>) {
+        let res: Result<u32, SdmmcError> = self
+            .cmdq_execute_inner()
+            .await
+            .map(|_| self.cmdq.take_completed(u32::MAX));
+        (res, self)
+    }
+
+    /// The HPI trigger only takes effect once HPI is enabled on the eMMC
+    fn emmc_hpi_trigger(&self) -> Option<&'static AtomicBool> {
+        match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) if emmc.hpi_enabled => self.hpi_trigger,
+            _ => None,
+        }
+    }
+
+    fn emmc_cmdq_enabled(&self) -> bool {
+        matches!(self.mmc_device, Some(MmcDevice::EMmc(ref emmc)) if emmc.cmdq_enabled)
+    }
+
+    /// Requests that are not read or write can only be sent when the command queue is empty
+    fn cmdq_check_idle(&self) -> Result<(), SdmmcError> {
+        if self.cmdq.pending != 0 {
+            return Err(SdmmcError::EBUSY);
         }
+        Ok(())
+    }
+
+    /// CMD44 sends the task parameters and CMD45 sends the start address of the task
+    /// The address is resolved before CMD44, so a task that does not fit is refused before reaching the device
+    async fn cmdq_queue_task(&mut self, tag: u8, request: CmdqRequest) -> Result<(), SdmmcError> {
+        let card_address: u32 = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => {
+                emmc.check_block_range(request.start_idx, request.blockcnt)?;
+                if request.flags == MmcDataFlag::SdmmcDataWrite {
+                    emmc.write_request_bits(
+                        request.start_idx,
+                        request.blockcnt,
+                        request.write_flags,
+                    )?;
+                }
+                emmc.card_address(request.start_idx)?
+            }
+            _ => return Err(SdmmcError::EUNDEFINED),
+        };
+
+        self.emmc_stop_bkops()?;
+
+        let mut resp: [u32; 4] = [0; 4];
+
+        let mut cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_QUEUED_TASK_PARAMS,
+            resp_type: MMC_RSP_R1,
+            cmdarg: request.task_params(tag)?,
+        };
+
+        Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await?;
 
         cmd = SdmmcCmd {
-            cmdidx: SD_CMD_ERASE_WR_BLK_END,
+            cmdidx: MMC_CMD_QUEUED_TASK_ADDRESS,
             resp_type: MMC_RSP_R1,
-            cmdarg: end_idx as u32,
+            cmdarg: card_address,
         };
 
-        res = Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await;
+        if let Err(err) = Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await
+        {
+            // The task parameters are already in the device
+            self.cmdq_discard().await;
+            return Err(err);
+        }
 
-        if let Err(_) = res {
-            return (res, self);
+        self.cmdq.insert(tag, request);
+
+        Ok(())
+    }
+
+    async fn cmdq_execute_inner(&mut self) -> Result<(), SdmmcError> {
+        let rca: u16 = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) if emmc.cmdq_enabled => emmc.relative_card_addr,
+            _ => return Ok(()),
+        };
+
+        let mut resp: [u32; 4] = [0; 4];
+        let mut deadline: PollDeadline =
+            PollDeadline::new(&mut self.sleep, CMDQ_QUEUE_STATUS_TIMEOUT_MS);
+
+        while self.cmdq.pending != 0 {
+            // CMD13 with the SQS bit returns the queue status register instead of the card status
+            let cmd: SdmmcCmd = SdmmcCmd {
+                cmdidx: MMC_CMD_SEND_STATUS,
+                resp_type: MMC_RSP_R1,
+                cmdarg: ((rca as u32) << 16) | MMC_CMD_ARG_SQS,
+            };
+
+            if let Err(err) =
+                Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await
+            {
+                self.cmdq_discard().await;
+                return Err(err);
+            }
+
+            let mut ready: u32 = resp[0] & self.cmdq.pending;
+            if ready == 0 {
+                if let Err(err) = deadline.next_poll(&mut self.sleep).await {
+                    self.cmdq_discard().await;
+                    return Err(err);
+                }
+                continue;
+            }
+            deadline = PollDeadline::new(&mut self.sleep, CMDQ_QUEUE_STATUS_TIMEOUT_MS);
+
+            while ready != 0 {
+                let tag: u8 = ready.trailing_zeros() as u8;
+                ready &= ready - 1;
+
+                let request: CmdqRequest = match self.cmdq.request(tag) {
+                    Some(request) => *request,
+                    None => {
+                        self.cmdq_discard().await;
+                        return Err(SdmmcError::EUNDEFINED);
+                    }
+                };
+
+                let cmd: SdmmcCmd = SdmmcCmd {
+                    cmdidx: match request.flags {
+                        MmcDataFlag::SdmmcDataRead => MMC_CMD_EXECUTE_READ_TASK,
+                        MmcDataFlag::SdmmcDataWrite => MMC_CMD_EXECUTE_WRITE_TASK,
+                    },
+                    resp_type: MMC_RSP_R1,
+                    cmdarg: (tag as u32) << 16,
+                };
+
+                let data: MmcData = MmcData {
+                    blocksize: SDCARD_DEFAULT_SECTOR_SIZE,
+                    blockcnt: request.blockcnt,
+                    flags: request.flags,
+                    addr: request.addr,
+                };
+
+                if let Err(err) =
+                    Self::sdmmc_async_request(&mut self.hardware, &cmd, Some(&data), &mut resp)
+                        .await
+                {
+                    self.cmdq_discard().await;
+                    return Err(err);
+                }
+
+                self.cmdq.complete(tag);
+            }
         }
 
-        cmd = SdmmcCmd {
-            cmdidx: MMC_CMD_ERASE,
+        Ok(())
+    }
+
+    /// Run one read or write request through the command queue, used by read_block and write_block
+    /// Other queued tasks are executed as well, their completions are still reported by cmdq_execute
+    async fn cmdq_run_single(&mut self, request: CmdqRequest) -> Result<(), SdmmcError> {
+        let tag: u8 = self.cmdq.alloc_tag()?;
+
+        self.cmdq_queue_task(tag, request).await?;
+        self.cmdq_execute_inner().await?;
+        self.cmdq.take_completed(1 << tag);
+
+        Ok(())
+    }
+
+    /// CMD48 discards every task in the device queue, used to recover from a failed task
+    async fn cmdq_discard(&mut self) {
+        let mut resp: [u32; 4] = [0; 4];
+        let cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_CMDQ_TASK_MGMT,
             resp_type: MMC_RSP_R1B,
-            cmdarg: SD_ERASE_ARG,
+            cmdarg: MMC_CMDQ_DISCARD_QUEUE,
         };
 
-        res = Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await;
+        // The queue on the driver side is dropped regardless, nothing else could be done if this fails
+        let _ = Self::sdmmc_async_request(&mut self.hardware, &cmd, None, &mut resp).await;
 
-        return (res, self);
+        self.cmdq.discard();
     }
 
-    /// Function to execute one sdmmc request asynchronously
-    /// The resp could be used for future error parsing
-    async fn sdmmc_async_request(
-        hardware: &mut T,
-        cmd: &SdmmcCmd,
-        data: Option<&MmcData>,
-        resp: &mut [u32; 4],
-    ) -> Result<(), SdmmcError> {
-        // Send the command to the host sdcard
-        hardware.sdmmc_send_command(cmd, data)?;
-        // Wait until sdcard return the result to the driver
-        let res: Result<(), SdmmcError> = SdmmcCmdFuture::new(hardware, cmd, resp).await;
-        // Acknowledge interrupts
-        hardware.sdmmc_ack_interrupt()?;
+    /// Suspend the card before the system goes to sleep
+    /// With VoltageOps, the eMMC is notified through POWER_OFF_SHORT, or put into sleep state when
+    /// the notification is not supported, before card_power_cycling cuts the power
+    /// Without VoltageOps, the eMMC is put into sleep state through CMD5 and keeps its power
+    /// resume() must be called before sending any other request to the card
+    pub fn suspend(&mut self) -> Result<(), SdmmcError> {
+        if self.mmc_device.is_none() {
+            return Err(SdmmcError::ENOCARD);
+        }
+        if self.powered_off {
+            return Ok(());
+        }
 
-        res
+        if self.voltage_ops.is_some() {
+            self.emmc_prepare_power_off(Some(EXT_CSD_POWER_OFF_SHORT))?;
+            self.card_power_off()?;
+        } else {
+            self.emmc_prepare_power_off(None)?;
+        }
+
+        Ok(())
     }
 
-    /// Change this function so that if a request report back error
-    /// MMC_CMD_STOP_TRANSMISSION must be sent in the end so the card
-    /// stop transmission correctly
-    async fn sdmmc_multi_blocks_io(
-        hardware: &mut T,
-        request_cmd: &SdmmcCmd,
-        data: &MmcData,
-        resp: &mut [u32; 4],
-        transmission_mode: BlockTransmissionMode,
+    /// Prepare the card for the system to power off
+    /// The eMMC is notified through POWER_OFF_LONG, or put into sleep state when the notification is not supported,
+    /// and the power is cut through VoltageOps if provided
+    /// The card needs to be set up again through setup_card afterwards
+    pub fn shutdown(&mut self) -> Result<(), SdmmcError> {
+        if self.mmc_device.is_none() {
+            return Err(SdmmcError::ENOCARD);
+        }
+
+        if !self.powered_off {
+            self.emmc_prepare_power_off(Some(EXT_CSD_POWER_OFF_LONG))?;
+            if self.voltage_ops.is_some() {
+                self.card_power_off()?;
+            }
+        }
+
+        self.mmc_device = None;
+        self.tuning = None;
+        self.powered_off = false;
+        self.cmdq = CmdqQueue::new(1);
+
+        Ok(())
+    }
+
+    /// Resume the card suspended by suspend()
+    /// A sleeping eMMC is woken up through CMD5 and keeps all its state
+    /// A card that has lost its power is set up and tuned again, then the partition selection,
+    /// cache, HPI and automatic background operations of the eMMC are restored
+    ///
+    /// # Safety
+    /// Same as tune_performance, the memory is only used if the card has lost its power
+    pub unsafe fn resume(
+        &mut self,
+        memory: *mut [u8; EXT_CSD_SIZE],
+        cache_invalidate_function: fn(),
+        physical_memory_addr: u64,
     ) -> Result<(), SdmmcError> {
-        // Trasmission stage
-        match transmission_mode {
-            BlockTransmissionMode::SetBlockCount => {
-                let cmd: SdmmcCmd = SdmmcCmd {
-                    cmdidx: MMC_CMD_SET_BLOCK_COUNT,
-                    resp_type: MMC_RSP_R1,
-                    cmdarg: data.blockcnt, // block count for the upcoming CMD25 operation
-                };
+        if !self.powered_off {
+            let (rca, timeout_ms) = match self.mmc_device {
+                Some(MmcDevice::EMmc(ref emmc)) if emmc.sleeping => (
+                    emmc.relative_card_addr,
+                    emmc.ext_csd
+                        .as_ref()
+                        .map_or(0, |ext_csd| ext_csd.sleep_awake_timeout_ms),
+                ),
+                Some(_) => return Ok(()),
+                None => return Err(SdmmcError::ENOCARD),
+            };
 
-                Self::sdmmc_async_request(hardware, &cmd, None, resp).await?;
+            EMmc::emmc_sleep_awake(&mut self.hardware, &mut self.sleep, rca, false, timeout_ms)?;
 
-                Self::sdmmc_async_request(hardware, &request_cmd, Some(&data), resp).await
+            if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+                emmc.sleeping = false;
             }
-            BlockTransmissionMode::StopTransmission => {
-                let temp_res: Result<(), SdmmcError> =
-                    Self::sdmmc_async_request(hardware, &request_cmd, Some(&data), resp).await;
 
-                // Dependent on whether the card is mmc or not, the response type is different
-                // TODO: Add mmc checks here
-                let cmd: SdmmcCmd = SdmmcCmd {
-                    cmdidx: MMC_CMD_STOP_TRANSMISSION,
-                    resp_type: MMC_RSP_R1B,
-                    cmdarg: 0,
-                };
+            return Ok(());
+        }
 
-                // Technically this command has very little possibility to fail
-                // Unless for hardware or environment issue
-                let _ = Self::sdmmc_async_request(hardware, &cmd, None, resp).await;
+        // The volatile settings that have to be written again after the power is restored
+        let restore: Option<(EmmcPartition, bool, bool, bool, bool)> = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) => Some((
+                emmc.current_partition,
+                emmc.cache_enabled,
+                emmc.hpi_enabled,
+                emmc.cmdq_enabled,
+                emmc.ext_csd
+                    .as_ref()
+                    .is_some_and(|ext_csd| ext_csd.bkops_en & EXT_CSD_BKOPS_AUTO_EN != 0),
+            )),
+            _ => None,
+        };
 
-                temp_res
+        self.mmc_ios = self.hardware.sdmmc_host_reset()?;
+        self.mmc_device = None;
+        self.cmdq = CmdqQueue::new(1);
+        self.setup_card()?;
+        unsafe { self.tune_performance(memory, cache_invalidate_function, physical_memory_addr)? };
+        self.powered_off = false;
+
+        if let Some((partition, cache_enabled, hpi_enabled, cmdq_enabled, auto_bkops)) = restore {
+            if hpi_enabled {
+                self.emmc_enable_hpi()?;
             }
-            BlockTransmissionMode::AutoStop => {
-                Self::sdmmc_async_request(hardware, &request_cmd, Some(&data), resp).await
+            if auto_bkops {
+                self.emmc_enable_bkops(BkopsMode::Auto)?;
+            }
+            if cache_enabled {
+                self.emmc_enable_cache(true)?;
+            }
+            self.emmc_select_partition(partition)?;
+            if cmdq_enabled {
+                self.emmc_enable_cmdq(true)?;
             }
         }
+
+        Ok(())
     }
 
-    pub fn print_card_info(&self) {
-        if let Some(ref device) = self.mmc_device {
-            match device {
-                MmcDevice::Sdcard(sdcard) => {
-                    sdcard.print_info();
-                }
-                MmcDevice::EMmc(_emmc) => {
-                    dev_log!("eMMC card support is not available yet!\n");
+    /// Flush the cache of the eMMC before it loses power, then either send `notification` through
+    /// POWER_OFF_NOTIFICATION or put the eMMC into sleep state if the notification is not enabled
+    fn emmc_prepare_power_off(&mut self, notification: Option<u8>) -> Result<(), SdmmcError> {
+        let (rca, cache_enabled, power_off_notify) = match self.mmc_device {
+            Some(MmcDevice::EMmc(ref emmc)) if !emmc.sleeping => (
+                emmc.relative_card_addr,
+                emmc.cache_enabled,
+                emmc.power_off_notify,
+            ),
+            _ => return Ok(()),
+        };
+        let (generic_cmd6_time_ms, power_off_long_time_ms, sleep_awake_timeout_ms) =
+            match self.mmc_device {
+                Some(MmcDevice::EMmc(EMmc {
+                    ext_csd: Some(ref ext_csd),
+                    ..
+                })) => (
+                    ext_csd.generic_cmd6_time_ms,
+                    ext_csd.power_off_long_time_ms,
+                    ext_csd.sleep_awake_timeout_ms,
+                ),
+                _ => (EMMC_DEFAULT_CMD6_TIMEOUT_MS, 0, 0),
+            };
+
+        self.emmc_stop_bkops()?;
+        self.cmdq_check_idle()?;
+
+        if cache_enabled {
+            EMmc::emmc_switch(
+                &mut self.hardware,
+                &mut self.sleep,
+                rca,
+                EXT_CSD_FLUSH_CACHE,
+                1,
+                EMMC_CACHE_FLUSH_TIMEOUT_MS,
+            )?;
+        }
+
+        match notification {
+            Some(notification) if power_off_notify => {
+                let timeout_ms: u32 =
+                    if notification == EXT_CSD_POWER_OFF_LONG && power_off_long_time_ms != 0 {
+                        power_off_long_time_ms
+                    } else {
+                        generic_cmd6_time_ms
+                    };
+                EMmc::emmc_switch(
+                    &mut self.hardware,
+                    &mut self.sleep,
+                    rca,
+                    EXT_CSD_POWER_OFF_NOTIFICATION,
+                    notification,
+                    timeout_ms,
+                )?;
+                if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+                    emmc.power_off_notify = false;
                 }
-                MmcDevice::Unknown => {
-                    dev_log!("Unknown card!\n");
+            }
+            _ => {
+                EMmc::emmc_sleep_awake(
+                    &mut self.hardware,
+                    &mut self.sleep,
+                    rca,
+                    true,
+                    sleep_awake_timeout_ms,
+                )?;
+                if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+                    emmc.sleeping = true;
                 }
             }
-        } else {
-            dev_log!("No available device! Try initialize the card first\n");
         }
+
+        Ok(())
     }
 
-    pub fn card_info(&self) -> Result<CardInfo, SdmmcError> {
-        let res: Result<CardInfo, SdmmcError>;
-        if let Some(ref device) = self.mmc_device {
-            match device {
-                MmcDevice::Sdcard(sdcard) => {
-                    res = Ok(sdcard.sdcard_info());
-                }
-                MmcDevice::EMmc(_emmc) => {
-                    res = Err(SdmmcError::ENOTIMPLEMENTED);
-                }
-                MmcDevice::Unknown => {
-                    res = Err(SdmmcError::ENOTIMPLEMENTED);
-                }
-            }
-        } else {
-            res = Err(SdmmcError::ENOCARD);
+    /// Cut the power of the card through VoltageOps, the card loses all its state
+    fn card_power_off(&mut self) -> Result<(), SdmmcError> {
+        if let Some(ref mut voltage_ops) = self.voltage_ops {
+            // Reset the signaling voltage back to 3.3V
+            voltage_ops.card_voltage_switch(MmcSignalVoltage::Voltage330)?;
+            self.sleep.usleep(1_000);
+
+            voltage_ops.card_power_cycling()?;
         }
-        res
+
+        self.powered_off = true;
+        self.tuning = None;
+
+        Ok(())
+    }
+
+    /// The eMMC only expects a notification before power off once POWER_OFF_NOTIFICATION is set
+    /// to POWERED_ON, check mmc_init_card() in Linux
+    fn emmc_enable_power_off_notify(&mut self) -> Result<(), SdmmcError> {
+        let (rca, generic_cmd6_time_ms) = match self.mmc_device {
+            Some(MmcDevice::EMmc(EMmc {
+                ext_csd: Some(ref ext_csd),
+                relative_card_addr,
+                ..
+            })) if ext_csd.revision >= 6 => (relative_card_addr, ext_csd.generic_cmd6_time_ms),
+            _ => return Ok(()),
+        };
+
+        EMmc::emmc_switch(
+            &mut self.hardware,
+            &mut self.sleep,
+            rca,
+            EXT_CSD_POWER_OFF_NOTIFICATION,
+            EXT_CSD_POWER_ON,
+            generic_cmd6_time_ms,
+        )?;
+
+        if let Some(MmcDevice::EMmc(ref mut emmc)) = self.mmc_device {
+            emmc.power_off_notify = true;
+        }
+
+        Ok(())
     }
 }
 
@@ -1400,6 +4782,7 @@ pub struct SdmmcCmdFuture<'a, 'b, 'c> {
     waker: Option<Waker>,
     state: CmdState,
     response: &'c mut [u32; 4],
+    interrupt: Option<&'static AtomicBool>,
 }
 
 impl<'a, 'b, 'c> SdmmcCmdFuture<'a, 'b, 'c> {
@@ -1414,8 +4797,16 @@ impl<'a, 'b, 'c> SdmmcCmdFuture<'a, 'b, 'c> {
             waker: None,
             state: CmdState::WaitingForResponse,
             response,
+            interrupt: None,
         }
     }
+
+    /// Resolve with EINTERRUPTED once `interrupt` is set while the request is still in flight,
+    /// the flag is cleared when it is taken. The request is not aborted, the caller has to do it
+    pub fn with_interrupt(mut self, interrupt: &'static AtomicBool) -> SdmmcCmdFuture<'a, 'b, 'c> {
+        self.interrupt = Some(interrupt);
+        self
+    }
 }
 
 /// SdmmcCmdFuture serves as the basic building block for async fn above
@@ -1444,6 +4835,13 @@ impl<'a, 'b, 'c> Future for SdmmcCmdFuture<'a, 'b, 'c> {
                     res = hardware.sdmmc_receive_response(cmd, response);
                 }
                 if let Err(SdmmcError::EBUSY) = res {
+                    if self
+                        .interrupt
+                        .is_some_and(|interrupt| interrupt.swap(false, Ordering::AcqRel))
+                    {
+                        self.state = CmdState::Finished;
+                        return Poll::Ready(Err(SdmmcError::EINTERRUPTED));
+                    }
                     return Poll::Pending;
                 } else {
                     self.state = CmdState::Finished;
@@ -1456,6 +4854,115 @@ impl<'a, 'b, 'c> Future for SdmmcCmdFuture<'a, 'b, 'c> {
     }
 }
 
+/// Resolves on the second poll, so a busy wait hands the CPU back to the executor between polls
+/// The waker is woken straight away, as there is no interrupt telling when to poll again
+struct YieldNow {
+    yielded: bool,
+}
+
+impl YieldNow {
+    fn new() -> YieldNow {
+        YieldNow { yielded: false }
+    }
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Timeout of the async busy waits
+/// Measured with Sleep::time_us when the OS provides a clock, otherwise every poll
+/// sleeps for a millisecond and counts towards the timeout like the sync busy waits do
+struct PollDeadline {
+    deadline_us: Option<u64>,
+    polls_left: u32,
+}
+
+impl PollDeadline {
+    fn new(sleep: &mut dyn Sleep, timeout_ms: u32) -> PollDeadline {
+        PollDeadline {
+            deadline_us: sleep
+                .time_us()
+                .map(|now_us| now_us.saturating_add(timeout_ms as u64 * 1_000)),
+            polls_left: timeout_ms,
+        }
+    }
+
+    /// Wait before polling the card again, ETIMEDOUT once the timeout has passed
+    async fn next_poll(&mut self, sleep: &mut dyn Sleep) -> Result<(), SdmmcError> {
+        match self.deadline_us {
+            Some(deadline_us) => {
+                if sleep.time_us().is_none_or(|now_us| now_us >= deadline_us) {
+                    return Err(SdmmcError::ETIMEDOUT);
+                }
+            }
+            None => {
+                if self.polls_left == 0 {
+                    return Err(SdmmcError::ETIMEDOUT);
+                }
+                self.polls_left -= 1;
+                sleep.usleep(1_000);
+            }
+        }
+
+        YieldNow::new().await;
+
+        Ok(())
+    }
+}
+
+/// Helper function to print out the card capacity line of the card info table
+pub(crate) fn print_card_capacity(capacity_bytes: u64, label_width: usize, data_width: usize) {
+    const KB: u64 = 1024;
+    const MB: u64 = 1024 * KB;
+    const GB: u64 = 1024 * MB;
+    const TB: u64 = 1024 * GB;
+
+    let (val, fraction, unit) = match capacity_bytes {
+        c if c >= TB => (c / TB, (c % TB) / GB, "TB"),
+        c if c >= GB => (c / GB, (c % GB) / MB, "GB"),
+        c if c >= MB => (c / MB, (c % MB) / KB, "MB"),
+        c if c >= KB => (c / KB, c % KB, "KB"),
+        c => (c, 0, "Bytes"),
+    };
+    // The capacity of eMMC bigger than 2GB is unknown until EXT_CSD is read
+    let digits: usize = val.checked_ilog10().unwrap_or(0) as usize;
+    if fraction == 0 {
+        info!(
+            "║ {:<label_width$}: {:<data_width$} ║",
+            "Card Capacity",
+            &format_args!(
+                "{} {}{:padding$}",
+                val,
+                unit,
+                "",
+                padding = data_width - 4 - digits
+            ),
+            label_width = label_width,
+            data_width = data_width
+        );
+    } else {
+        info!(
+            "║ {:<label_width$}: {}{:padding$} ║",
+            "Card Capacity",
+            &format_args!("{}.{:0<3} {}", val, (fraction * 1000) / 1024, unit),
+            "",
+            label_width = label_width,
+            padding = data_width - digits - 8
+        );
+    }
+}
+
 /// Helper function to print out the content of one block
 #[allow(dead_code)]
 unsafe fn print_one_block(ptr: *const u8, num: usize) {
@@ -61,6 +61,27 @@ bitflags! {
     }
 }
 
+#[derive(Debug, Clone)]
+pub(crate) struct EMmcCapability(pub u128);
+
+bitflags! {
+    /// Represents the capabilities of eMMC devices
+    impl EMmcCapability: u128 {
+        // Timing modes
+        const MMC_TIMING_LEGACY       = MMC_TIMING_LEGACY;
+        const MMC_TIMING_MMC_HS       = MMC_TIMING_MMC_HS;
+        const MMC_TIMING_MMC_DDR52    = MMC_TIMING_MMC_DDR52;
+        const MMC_TIMING_MMC_HS200    = MMC_TIMING_MMC_HS200;
+        const MMC_TIMING_MMC_HS400    = MMC_TIMING_MMC_HS400;
+
+        // Capabilities
+        const MMC_CAP_4_BIT_DATA      = MMC_CAP_4_BIT_DATA;
+        const MMC_CAP_8_BIT_DATA      = MMC_CAP_8_BIT_DATA;
+
+        const MMC_CAP_CMD23           = MMC_CAP_CMD23;
+    }
+}
+
 pub const MMC_EMPTY_CAP: u128 = 0;
 
 // Timing modes (starting from bit 0)
@@ -0,0 +1,192 @@
+// Copyright 2025, UNSW
+// SPDX-License-Identifier: BSD-2-Clause
+
+use super::{MmcDataFlag, SdmmcError, mmc_struct::WriteFlags};
+
+/// The eMMC command queue holds at most 32 tasks
+pub const CMDQ_MAX_DEPTH: usize = 32;
+
+/// How long the queue status is polled without any task getting ready before giving up
+pub(crate) const CMDQ_QUEUE_STATUS_TIMEOUT_MS: u32 = 1_000;
+
+// Fields of the CMD44 argument, check JESD84-B51 section 6.6.39.1
+const CMDQ_TASK_RELIABLE_WRITE: u32 = 1 << 31;
+const CMDQ_TASK_DATA_DIR_READ: u32 = 1 << 30;
+const CMDQ_TASK_FORCED_PRG: u32 = 1 << 24;
+const CMDQ_TASK_ID_SHIFT: u32 = 16;
+const CMDQ_TASK_MAX_BLOCKS: u32 = 0xFFFF;
+
+/// One read or write request submitted to the command queue
+#[derive(Debug, Clone, Copy)]
+pub struct CmdqRequest {
+    pub flags: MmcDataFlag,
+    pub start_idx: u64,
+    pub blockcnt: u32,
+    /// Physical address of the buffer the host DMA into or from
+    pub addr: u64,
+    /// Only used by write requests
+    pub write_flags: WriteFlags,
+}
+
+impl CmdqRequest {
+    /// Argument of CMD44 queuing this request with task id `tag`
+    pub(crate) fn task_params(&self, tag: u8) -> Result<u32, SdmmcError> {
+        if self.blockcnt == 0 || self.blockcnt > CMDQ_TASK_MAX_BLOCKS {
+            return Err(SdmmcError::EINVAL);
+        }
+
+        let mut arg: u32 = self.blockcnt | ((tag as u32) << CMDQ_TASK_ID_SHIFT);
+        match self.flags {
+            MmcDataFlag::SdmmcDataRead => arg |= CMDQ_TASK_DATA_DIR_READ,
+            MmcDataFlag::SdmmcDataWrite => {
+                if self.write_flags.force_unit_access {
+                    arg |= CMDQ_TASK_FORCED_PRG;
+                }
+                if self.write_flags.reliable_write {
+                    arg |= CMDQ_TASK_RELIABLE_WRITE;
+                }
+            }
+        }
+
+        Ok(arg)
+    }
+}
+
+/// Tasks tracked by the driver, indexed by the task tag
+/// A tag stays in use until its completion is reported to the caller
+pub(crate) struct CmdqQueue {
+    /// Number of tasks the device accepts, one when falling back to the legacy path
+    depth: u8,
+    tasks: [Option<CmdqRequest>; CMDQ_MAX_DEPTH],
+    /// Tasks queued in the device but not executed yet
+    pub(crate) pending: u32,
+    /// Tasks finished but not reported to the caller yet
+    completed: u32,
+}
+
+impl CmdqQueue {
+    pub(crate) const fn new(depth: u8) -> CmdqQueue {
+        CmdqQueue {
+            depth,
+            tasks: [None; CMDQ_MAX_DEPTH],
+            pending: 0,
+            completed: 0,
+        }
+    }
+
+    /// The lowest free tag, EBUSY if every tag is in use
+    pub(crate) fn alloc_tag(&self) -> Result<u8, SdmmcError> {
+        let in_use: u32 = self.pending | self.completed;
+
+        (0..self.depth)
+            .find(|tag| in_use & (1 << tag) == 0)
+            .ok_or(SdmmcError::EBUSY)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending == 0 && self.completed == 0
+    }
+
+    pub(crate) fn insert(&mut self, tag: u8, request: CmdqRequest) {
+        self.tasks[tag as usize] = Some(request);
+        self.pending |= 1 << tag;
+    }
+
+    pub(crate) fn request(&self, tag: u8) -> Option<&CmdqRequest> {
+        self.tasks[tag as usize].as_ref()
+    }
+
+    pub(crate) fn complete(&mut self, tag: u8) {
+        self.tasks[tag as usize] = None;
+        self.pending &= !(1 << tag);
+        self.completed |= 1 << tag;
+    }
+
+    /// Report the completed tasks, their tags can be reused afterwards
+    pub(crate) fn take_completed(&mut self, mask: u32) -> u32 {
+        let completed: u32 = self.completed & mask;
+        self.completed &= !mask;
+        completed
+    }
+
+    /// Forget the tasks discarded by the device, the completed tasks are still reported
+    pub(crate) fn discard(&mut self) {
+        self.tasks = [None; CMDQ_MAX_DEPTH];
+        self.pending = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(flags: MmcDataFlag, blockcnt: u32, write_flags: WriteFlags) -> CmdqRequest {
+        CmdqRequest {
+            flags,
+            start_idx: 0,
+            blockcnt,
+            addr: 0,
+            write_flags,
+        }
+    }
+
+    #[test]
+    fn task_params() {
+        let read: CmdqRequest = request(MmcDataFlag::SdmmcDataRead, 8, WriteFlags::default());
+        assert_eq!(
+            read.task_params(3).unwrap(),
+            8 | (3 << CMDQ_TASK_ID_SHIFT) | CMDQ_TASK_DATA_DIR_READ
+        );
+
+        let write: CmdqRequest = request(
+            MmcDataFlag::SdmmcDataWrite,
+            CMDQ_TASK_MAX_BLOCKS,
+            WriteFlags {
+                force_unit_access: true,
+                reliable_write: true,
+            },
+        );
+        assert_eq!(
+            write.task_params(31).unwrap(),
+            CMDQ_TASK_MAX_BLOCKS
+                | (31 << CMDQ_TASK_ID_SHIFT)
+                | CMDQ_TASK_FORCED_PRG
+                | CMDQ_TASK_RELIABLE_WRITE
+        );
+    }
+
+    #[test]
+    fn task_params_rejects_block_count() {
+        let empty: CmdqRequest = request(MmcDataFlag::SdmmcDataRead, 0, WriteFlags::default());
+        assert!(matches!(empty.task_params(0), Err(SdmmcError::EINVAL)));
+
+        let oversized: CmdqRequest = request(
+            MmcDataFlag::SdmmcDataRead,
+            CMDQ_TASK_MAX_BLOCKS + 1,
+            WriteFlags::default(),
+        );
+        assert!(matches!(oversized.task_params(0), Err(SdmmcError::EINVAL)));
+    }
+
+    #[test]
+    fn tag_allocation() {
+        let mut queue: CmdqQueue = CmdqQueue::new(2);
+        let read: CmdqRequest = request(MmcDataFlag::SdmmcDataRead, 1, WriteFlags::default());
+
+        assert_eq!(queue.alloc_tag().unwrap(), 0);
+        queue.insert(0, read);
+        assert_eq!(queue.alloc_tag().unwrap(), 1);
+        queue.insert(1, read);
+        assert!(matches!(queue.alloc_tag(), Err(SdmmcError::EBUSY)));
+
+        // A completed tag is only free again after the completion is reported
+        queue.complete(0);
+        assert!(queue.request(0).is_none());
+        assert!(matches!(queue.alloc_tag(), Err(SdmmcError::EBUSY)));
+        assert_eq!(queue.take_completed(u32::MAX), 1 << 0);
+        assert_eq!(queue.alloc_tag().unwrap(), 0);
+
+        queue.discard();
+        assert!(queue.is_empty());
+    }
+}
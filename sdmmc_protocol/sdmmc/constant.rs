@@ -13,6 +13,7 @@ pub const MMC_CMD_SEND_OP_COND: u32 = 1;
 pub const MMC_CMD_ALL_SEND_CID: u32 = 2;
 pub const MMC_CMD_SET_RELATIVE_ADDR: u32 = 3;
 pub const MMC_CMD_SET_DSR: u32 = 4;
+pub const MMC_CMD_SLEEP_AWAKE: u32 = 5;
 pub const MMC_CMD_SWITCH: u32 = 6;
 pub const MMC_CMD_SELECT_CARD: u32 = 7;
 pub const MMC_CMD_SEND_EXT_CSD: u32 = 8;
@@ -20,31 +21,103 @@ pub const MMC_CMD_SEND_CSD: u32 = 9;
 pub const MMC_CMD_SEND_CID: u32 = 10;
 pub const MMC_CMD_STOP_TRANSMISSION: u32 = 12;
 pub const MMC_CMD_SEND_STATUS: u32 = 13;
+pub const MMC_CMD_BUS_TEST_R: u32 = 14;
 pub const MMC_CMD_SET_BLOCKLEN: u32 = 16;
 pub const MMC_CMD_READ_SINGLE_BLOCK: u32 = 17;
 pub const MMC_CMD_READ_MULTIPLE_BLOCK: u32 = 18;
 pub const MMC_CMD_SEND_TUNING_BLOCK: u32 = 19;
+pub const MMC_CMD_BUS_TEST_W: u32 = 19;
 pub const MMC_CMD_SEND_TUNING_BLOCK_HS200: u32 = 21;
 pub const MMC_CMD_SET_BLOCK_COUNT: u32 = 23;
 pub const MMC_CMD_WRITE_SINGLE_BLOCK: u32 = 24;
 pub const MMC_CMD_WRITE_MULTIPLE_BLOCK: u32 = 25;
+pub const MMC_CMD_SET_WRITE_PROT: u32 = 28;
+pub const MMC_CMD_CLR_WRITE_PROT: u32 = 29;
+pub const MMC_CMD_SEND_WRITE_PROT: u32 = 30;
+pub const MMC_CMD_SEND_WRITE_PROT_TYPE: u32 = 31;
 pub const MMC_CMD_ERASE_GROUP_START: u32 = 35;
 pub const MMC_CMD_ERASE_GROUP_END: u32 = 36;
 pub const MMC_CMD_ERASE: u32 = 38;
+pub const MMC_CMD_QUEUED_TASK_PARAMS: u32 = 44;
+pub const MMC_CMD_QUEUED_TASK_ADDRESS: u32 = 45;
+pub const MMC_CMD_EXECUTE_READ_TASK: u32 = 46;
+pub const MMC_CMD_EXECUTE_WRITE_TASK: u32 = 47;
+pub const MMC_CMD_CMDQ_TASK_MGMT: u32 = 48;
 pub const MMC_CMD_APP_CMD: u32 = 55;
 pub const MMC_CMD_SPI_READ_OCR: u32 = 58;
 pub const MMC_CMD_SPI_CRC_ON_OFF: u32 = 59;
 pub const MMC_CMD_RES_MAN: u32 = 62;
 
+// Define constants for MMC switch cmd (CMD6) access mode
+pub const MMC_SWITCH_MODE_CMD_SET: u32 = 0x00; // Change the command set
+pub const MMC_SWITCH_MODE_SET_BITS: u32 = 0x01; // Set bits which are 1 in value
+pub const MMC_SWITCH_MODE_CLEAR_BITS: u32 = 0x02; // Clear bits which are 1 in value
+pub const MMC_SWITCH_MODE_WRITE_BYTE: u32 = 0x03; // Set target to value
+
+// Define constants for the card status returned in R1 response
+pub const MMC_STATUS_SWITCH_ERROR: u32 = 1 << 7;
+pub const MMC_STATUS_RDY_FOR_DATA: u32 = 1 << 8;
+pub const MMC_STATUS_CURR_STATE: u32 = 0xF << 9;
+pub const MMC_STATUS_WP_ERASE_SKIP: u32 = 1 << 15;
+pub const MMC_STATUS_ERROR: u32 = 1 << 19;
+pub const MMC_STATUS_WP_VIOLATION: u32 = 1 << 26;
+pub const MMC_STATUS_ERASE_PARAM: u32 = 1 << 27;
+pub const MMC_STATUS_ERASE_SEQ_ERROR: u32 = 1 << 28;
+pub const MMC_STATUS_OUT_OF_RANGE: u32 = 1 << 31;
+// Status bits reporting the erase has failed or skipped write protected blocks
+pub const MMC_STATUS_ERASE_ERRORS: u32 = MMC_STATUS_OUT_OF_RANGE
+    | MMC_STATUS_ERASE_SEQ_ERROR
+    | MMC_STATUS_ERASE_PARAM
+    | MMC_STATUS_ERROR
+    | MMC_STATUS_WP_ERASE_SKIP;
+// Status bits reporting the write protection of a group could not be changed
+pub const MMC_STATUS_WP_ERRORS: u32 =
+    MMC_STATUS_OUT_OF_RANGE | MMC_STATUS_WP_VIOLATION | MMC_STATUS_ERROR;
+pub const MMC_STATE_TRAN: u32 = 4 << 9;
+pub const MMC_STATE_RCV: u32 = 6 << 9;
+pub const MMC_STATE_PRG: u32 = 7 << 9;
+
+// Request bits in the CMD23 argument
+pub const MMC_CMD23_ARG_REL_WR: u32 = 1 << 31; // Reliable write
+pub const MMC_CMD23_ARG_PACKED: u32 = 1 << 30; // The first block is the packed header
+pub const MMC_CMD23_ARG_FORCED_PRG: u32 = 1 << 24; // Write the data to the non-volatile storage bypassing the cache
+
+// Sleep bit in the argument of CMD5, cleared to wake the eMMC up
+pub const MMC_CMD_ARG_SLEEP: u32 = 1 << 15;
+
+// SQS bit in the argument of CMD13, the queue status register is returned instead of the card status
+pub const MMC_CMD_ARG_SQS: u32 = 1 << 15;
+
+// Operation code in the argument of CMD48
+pub const MMC_CMDQ_DISCARD_QUEUE: u32 = 1;
+
+// HPI bit in the argument of CMD12/CMD13
+pub const MMC_CMD_ARG_HPI: u32 = 1 << 0;
+
+// Arguments of the eMMC erase cmd (CMD38)
+pub const MMC_ERASE_ARG: u32 = 0x00000000;
+pub const MMC_TRIM_ARG: u32 = 0x00000001;
+pub const MMC_DISCARD_ARG: u32 = 0x00000003;
+pub const MMC_SECURE_ERASE_ARG: u32 = 0x80000000;
+pub const MMC_SECURE_TRIM1_ARG: u32 = 0x80000001;
+pub const MMC_SECURE_TRIM2_ARG: u32 = 0x80008000;
+
+// Arguments of CMD0 other than GO_IDLE_STATE
+pub const MMC_CMD0_ARG_GO_PRE_IDLE: u32 = 0xF0F0F0F0;
+pub const MMC_CMD0_ARG_BOOT_INITIATION: u32 = 0xFFFFFFFA; // Alternative boot operation
+
 // Define constants for MMC command 62 arguments
 pub const MMC_CMD62_ARG1: u32 = 0xefac62ec;
 pub const MMC_CMD62_ARG2: u32 = 0xcbaea7;
+// Sent after MMC_CMD62_ARG1 to leave the vendor mode
+pub const MMC_CMD62_ARG_EXIT: u32 = 0xdeccee;
 
 // Define constants for SD commands
 pub const SD_CMD_SEND_RELATIVE_ADDR: u32 = 3;
 pub const SD_CMD_SWITCH_FUNC: u32 = 6;
 pub const SD_CMD_SEND_IF_COND: u32 = 8;
 pub const SD_CMD_SWITCH_UHS18V: u32 = 11;
+pub const SD_CMD_ADDRESS_EXTENSION: u32 = 22;
 
 pub const SD_CMD_APP_SET_BUS_WIDTH: u32 = 6;
 pub const SD_CMD_APP_SD_STATUS: u32 = 13;
@@ -63,10 +136,14 @@ pub const SD_CMD_APP_SEND_SCR: u32 = 51;
 
 pub const OCR_BUSY: u32 = 0x8000_0000;
 pub const OCR_XPC: u32 = 0x1000_0000;
+// HO2T in ACMD41 argument, CO2T in the response of SDUC cards
+pub const OCR_2T: u32 = 0x0800_0000;
 pub const OCR_HCS: u32 = 0x4000_0000;
 pub const OCR_S18R: u32 = 0x0100_0000;
 pub const OCR_VOLTAGE_MASK: u32 = 0x007F_FF80;
 pub const OCR_ACCESS_MODE: u32 = 0x6000_0000;
+// eMMC reuses the HCS bit position for sector access mode
+pub const OCR_SECTOR_MODE: u32 = 0x4000_0000;
 
 // The index to get the speed class information from SD switch function cmd
 // Check Part 1 Physical Layer Simplified Specification Ver9.10 table 4-11 to see if I am wrong
@@ -94,3 +171,189 @@ pub const SD_SWITCH_FUNCTION_GROUP_ONE_CHECK_UHS_DDR50: u8 =
     1 << SD_SWITCH_FUNCTION_GROUP_ONE_SET_UHS_DDR50;
 
 pub const SD_SWITCH_FUNCTION_SELECTION_GROUP_ONE: usize = 16;
+
+// EXT_CSD register is 512 bytes long
+pub const EXT_CSD_SIZE: usize = 512;
+
+// Byte index of the EXT_CSD fields, check JESD84-B51 section 7.4 for the full list
+pub const EXT_CSD_CMDQ_MODE_EN: usize = 15;
+pub const EXT_CSD_FFU_STATUS: usize = 26;
+pub const EXT_CSD_MODE_OPERATION_CODES: usize = 29;
+pub const EXT_CSD_MODE_CONFIG: usize = 30;
+pub const EXT_CSD_FLUSH_CACHE: usize = 32;
+pub const EXT_CSD_CACHE_CTRL: usize = 33;
+pub const EXT_CSD_POWER_OFF_NOTIFICATION: usize = 34;
+pub const EXT_CSD_DATA_SECTOR_SIZE: usize = 61;
+pub const EXT_CSD_ENH_START_ADDR: usize = 136; // 4 bytes
+pub const EXT_CSD_ENH_SIZE_MULT: usize = 140; // 3 bytes
+pub const EXT_CSD_GP_SIZE_MULT: usize = 143; // 12 bytes, 3 for each general purpose partition
+pub const EXT_CSD_PARTITION_SETTING_COMPLETED: usize = 155;
+pub const EXT_CSD_PARTITIONS_ATTRIBUTE: usize = 156;
+pub const EXT_CSD_MAX_ENH_SIZE_MULT: usize = 157; // 3 bytes
+pub const EXT_CSD_PARTITION_SUPPORT: usize = 160;
+pub const EXT_CSD_HPI_MGMT: usize = 161;
+pub const EXT_CSD_BKOPS_EN: usize = 163;
+pub const EXT_CSD_BKOPS_START: usize = 164;
+pub const EXT_CSD_SANITIZE_START: usize = 165;
+pub const EXT_CSD_WR_REL_PARAM: usize = 166;
+pub const EXT_CSD_RPMB_MULT: usize = 168;
+pub const EXT_CSD_FW_CONFIG: usize = 169;
+pub const EXT_CSD_USER_WP: usize = 171;
+pub const EXT_CSD_BOOT_WP: usize = 173;
+pub const EXT_CSD_BOOT_WP_STATUS: usize = 174;
+pub const EXT_CSD_ERASE_GROUP_DEF: usize = 175;
+pub const EXT_CSD_BOOT_BUS_CONDITIONS: usize = 177;
+pub const EXT_CSD_PART_CONFIG: usize = 179;
+pub const EXT_CSD_BUS_WIDTH: usize = 183;
+pub const EXT_CSD_STROBE_SUPPORT: usize = 184;
+pub const EXT_CSD_HS_TIMING: usize = 185;
+pub const EXT_CSD_REV: usize = 192;
+pub const EXT_CSD_STRUCTURE: usize = 194;
+pub const EXT_CSD_CARD_TYPE: usize = 196;
+pub const EXT_CSD_OUT_OF_INTERRUPT_TIME: usize = 198;
+pub const EXT_CSD_PART_SWITCH_TIME: usize = 199;
+pub const EXT_CSD_SEC_CNT: usize = 212; // 4 bytes
+pub const EXT_CSD_S_A_TIMEOUT: usize = 217;
+pub const EXT_CSD_HC_WP_GRP_SIZE: usize = 221;
+pub const EXT_CSD_REL_WR_SEC_C: usize = 222;
+pub const EXT_CSD_ERASE_TIMEOUT_MULT: usize = 223;
+pub const EXT_CSD_HC_ERASE_GRP_SIZE: usize = 224;
+pub const EXT_CSD_BOOT_MULT: usize = 226;
+pub const EXT_CSD_SEC_TRIM_MULT: usize = 229;
+pub const EXT_CSD_SEC_ERASE_MULT: usize = 230;
+pub const EXT_CSD_SEC_FEATURE_SUPPORT: usize = 231;
+pub const EXT_CSD_TRIM_MULT: usize = 232;
+pub const EXT_CSD_CORRECTLY_PRG_SECTORS_NUM: usize = 242; // 4 bytes
+pub const EXT_CSD_GENERIC_CMD6_TIME: usize = 248;
+pub const EXT_CSD_BKOPS_STATUS: usize = 246;
+pub const EXT_CSD_POWER_OFF_LONG_TIME: usize = 247;
+pub const EXT_CSD_CACHE_SIZE: usize = 249; // 4 bytes
+pub const EXT_CSD_PRE_EOL_INFO: usize = 267;
+pub const EXT_CSD_DEVICE_LIFE_TIME_EST_TYP_A: usize = 268;
+pub const EXT_CSD_DEVICE_LIFE_TIME_EST_TYP_B: usize = 269;
+pub const EXT_CSD_NUM_OF_FW_SEC_PROG: usize = 302; // 4 bytes
+pub const EXT_CSD_CMDQ_DEPTH: usize = 307;
+pub const EXT_CSD_CMDQ_SUPPORT: usize = 308;
+pub const EXT_CSD_FFU_ARG: usize = 487; // 4 bytes
+pub const EXT_CSD_OPERATION_CODE_TIMEOUT: usize = 491;
+pub const EXT_CSD_FFU_FEATURES: usize = 492;
+pub const EXT_CSD_SUPPORTED_MODES: usize = 493;
+pub const EXT_CSD_MAX_PACKED_WRITES: usize = 500;
+pub const EXT_CSD_BKOPS_SUPPORT: usize = 502;
+pub const EXT_CSD_HPI_FEATURES: usize = 503;
+
+// Value of EXT_CSD_MODE_CONFIG
+pub const EXT_CSD_MODE_CONFIG_NORMAL: u8 = 0;
+pub const EXT_CSD_MODE_CONFIG_FFU: u8 = 1;
+
+// Value of EXT_CSD_MODE_OPERATION_CODES
+pub const EXT_CSD_FFU_INSTALL: u8 = 1;
+
+// Bits of EXT_CSD_SUPPORTED_MODES, EXT_CSD_FW_CONFIG and EXT_CSD_FFU_FEATURES
+pub const EXT_CSD_SUPPORTED_MODE_FFU: u8 = 1 << 0;
+pub const EXT_CSD_FW_UPDATE_DISABLE: u8 = 1 << 0;
+pub const EXT_CSD_FFU_MODE_OPERATION_CODES: u8 = 1 << 0; // FFU can be installed without a reset
+
+// Bits of EXT_CSD_WR_REL_PARAM
+pub const EXT_CSD_EN_REL_WR: u8 = 1 << 2; // Reliable write of any size
+
+// Bits of EXT_CSD_SEC_FEATURE_SUPPORT
+pub const EXT_CSD_SEC_ER_EN: u8 = 1 << 0; // Secure erase and secure trim
+pub const EXT_CSD_SEC_GB_CL_EN: u8 = 1 << 4; // Trim
+pub const EXT_CSD_SEC_SANITIZE: u8 = 1 << 6;
+
+// Value of EXT_CSD_POWER_OFF_NOTIFICATION
+pub const EXT_CSD_NO_POWER_NOTIFICATION: u8 = 0;
+pub const EXT_CSD_POWER_ON: u8 = 1;
+pub const EXT_CSD_POWER_OFF_SHORT: u8 = 2;
+pub const EXT_CSD_POWER_OFF_LONG: u8 = 3;
+
+// Bits of EXT_CSD_BKOPS_EN
+pub const EXT_CSD_BKOPS_MANUAL_EN: u8 = 1 << 0;
+pub const EXT_CSD_BKOPS_AUTO_EN: u8 = 1 << 1;
+
+// Bits of EXT_CSD_HPI_FEATURES
+pub const EXT_CSD_HPI_SUPPORT: u8 = 1 << 0;
+pub const EXT_CSD_HPI_IMPL_CMD12: u8 = 1 << 1; // HPI is sent through CMD12 instead of CMD13
+
+// Value of EXT_CSD_BUS_WIDTH
+pub const EXT_CSD_BUS_WIDTH_1: u8 = 0;
+pub const EXT_CSD_BUS_WIDTH_4: u8 = 1;
+pub const EXT_CSD_BUS_WIDTH_8: u8 = 2;
+pub const EXT_CSD_DDR_BUS_WIDTH_4: u8 = 5;
+pub const EXT_CSD_DDR_BUS_WIDTH_8: u8 = 6;
+pub const EXT_CSD_BUS_WIDTH_STROBE: u8 = 1 << 7; // Enhanced strobe for HS400ES
+
+// PARTITION_ACCESS field of EXT_CSD_PART_CONFIG
+pub const EXT_CSD_PART_CONFIG_ACC_MASK: u8 = 0x7;
+// Boot fields of EXT_CSD_PART_CONFIG
+pub const EXT_CSD_BOOT_PART_ENABLE_SHIFT: u8 = 3;
+pub const EXT_CSD_BOOT_ACK: u8 = 1 << 6;
+
+// Fields of EXT_CSD_BOOT_BUS_CONDITIONS
+pub const EXT_CSD_BOOT_BUS_WIDTH_1: u8 = 0;
+pub const EXT_CSD_BOOT_BUS_WIDTH_4: u8 = 1;
+pub const EXT_CSD_BOOT_BUS_WIDTH_8: u8 = 2;
+pub const EXT_CSD_BOOT_BUS_RETAIN: u8 = 1 << 2; // RESET_BOOT_BUS_CONDITIONS
+pub const EXT_CSD_BOOT_MODE_SHIFT: u8 = 3;
+
+// Bits of EXT_CSD_BOOT_WP
+pub const EXT_CSD_BOOT_WP_B_PWR_WP_EN: u8 = 1 << 0;
+pub const EXT_CSD_BOOT_WP_B_PWR_WP_SEC_SEL: u8 = 1 << 1; // Power on write protection applies to boot1 instead of boot0
+pub const EXT_CSD_BOOT_WP_B_PERM_WP_EN: u8 = 1 << 2;
+pub const EXT_CSD_BOOT_WP_B_PERM_WP_SEC_SEL: u8 = 1 << 3; // Permanent write protection applies to boot1 instead of boot0
+pub const EXT_CSD_BOOT_WP_B_PERM_WP_DIS: u8 = 1 << 4;
+pub const EXT_CSD_BOOT_WP_B_PWR_WP_DIS: u8 = 1 << 6;
+pub const EXT_CSD_BOOT_WP_B_SEC_WP_SEL: u8 = 1 << 7; // Protect only the boot partition chosen by the SEC_SEL bits
+
+// Bits of EXT_CSD_PARTITION_SUPPORT
+pub const EXT_CSD_PART_SUPPORT_PART_EN: u8 = 1 << 0;
+pub const EXT_CSD_PART_SUPPORT_ENH_ATTRIBUTE_EN: u8 = 1 << 1;
+
+// Bits of EXT_CSD_PARTITIONS_ATTRIBUTE, the enhanced bit of general purpose partition N is 1 << N
+pub const EXT_CSD_PART_ATTR_ENH_USR: u8 = 1 << 0;
+
+pub const EXT_CSD_PARTITION_SETTING_COMPLETE: u8 = 1 << 0;
+
+// Bits of EXT_CSD_USER_WP
+pub const EXT_CSD_US_PWR_WP_EN: u8 = 1 << 0; // CMD28 applies power on write protection
+pub const EXT_CSD_US_PERM_WP_EN: u8 = 1 << 2; // CMD28 applies permanent write protection
+pub const EXT_CSD_US_PWR_WP_DIS: u8 = 1 << 3;
+pub const EXT_CSD_US_PERM_WP_DIS: u8 = 1 << 4;
+
+// Value of EXT_CSD_HS_TIMING, the driver strength is placed in the upper nibble
+pub const EXT_CSD_TIMING_BC: u8 = 0; // Backwards compatible
+pub const EXT_CSD_TIMING_HS: u8 = 1;
+pub const EXT_CSD_TIMING_HS200: u8 = 2;
+pub const EXT_CSD_TIMING_HS400: u8 = 3;
+pub const EXT_CSD_DRV_STR_SHIFT: u8 = 4;
+
+// Bits of EXT_CSD_CARD_TYPE
+pub const EXT_CSD_CARD_TYPE_HS_26: u8 = 1 << 0;
+pub const EXT_CSD_CARD_TYPE_HS_52: u8 = 1 << 1;
+pub const EXT_CSD_CARD_TYPE_DDR_1_8V: u8 = 1 << 2;
+pub const EXT_CSD_CARD_TYPE_DDR_1_2V: u8 = 1 << 3;
+pub const EXT_CSD_CARD_TYPE_HS200_1_8V: u8 = 1 << 4;
+pub const EXT_CSD_CARD_TYPE_HS200_1_2V: u8 = 1 << 5;
+pub const EXT_CSD_CARD_TYPE_HS400_1_8V: u8 = 1 << 6;
+pub const EXT_CSD_CARD_TYPE_HS400_1_2V: u8 = 1 << 7;
+
+// Tuning block pattern returned by CMD19/CMD21, check tuning_blk_pattern_4bit in Linux
+pub const TUNING_BLK_PATTERN_4BIT: [u8; 64] = [
+    0xff, 0x0f, 0xff, 0x00, 0xff, 0xcc, 0xc3, 0xcc, 0xc3, 0x3c, 0xcc, 0xff, 0xfe, 0xff, 0xfe, 0xef,
+    0xff, 0xdf, 0xff, 0xdd, 0xff, 0xfb, 0xff, 0xfb, 0xbf, 0xff, 0x7f, 0xff, 0x77, 0xf7, 0xbd, 0xef,
+    0xff, 0xf0, 0xff, 0xf0, 0x0f, 0xfc, 0xcc, 0x3c, 0xcc, 0x33, 0xcc, 0xcf, 0xff, 0xef, 0xff, 0xee,
+    0xff, 0xfd, 0xff, 0xfd, 0xdf, 0xff, 0xbf, 0xff, 0xbb, 0xff, 0xf7, 0xff, 0xf7, 0x7f, 0x7b, 0xde,
+];
+
+// Tuning block pattern returned by CMD21 in 8 bit bus width, check tuning_blk_pattern_8bit in Linux
+pub const TUNING_BLK_PATTERN_8BIT: [u8; 128] = [
+    0xff, 0xff, 0x00, 0xff, 0xff, 0xff, 0x00, 0x00, 0xff, 0xff, 0xcc, 0xcc, 0xcc, 0x33, 0xcc, 0xcc,
+    0xcc, 0x33, 0x33, 0xcc, 0xcc, 0xcc, 0xff, 0xff, 0xff, 0xee, 0xff, 0xff, 0xff, 0xee, 0xee, 0xff,
+    0xff, 0xff, 0xdd, 0xff, 0xff, 0xff, 0xdd, 0xdd, 0xff, 0xff, 0xff, 0xbb, 0xff, 0xff, 0xff, 0xbb,
+    0xbb, 0xff, 0xff, 0xff, 0x77, 0xff, 0xff, 0xff, 0x77, 0x77, 0xff, 0x77, 0xbb, 0xdd, 0xee, 0xff,
+    0xff, 0xff, 0xff, 0x00, 0xff, 0xff, 0xff, 0x00, 0x00, 0xff, 0xff, 0xcc, 0xcc, 0xcc, 0x33, 0xcc,
+    0xcc, 0xcc, 0x33, 0x33, 0xcc, 0xcc, 0xcc, 0xff, 0xff, 0xff, 0xee, 0xff, 0xff, 0xff, 0xee, 0xee,
+    0xff, 0xff, 0xff, 0xdd, 0xff, 0xff, 0xff, 0xdd, 0xdd, 0xff, 0xff, 0xff, 0xbb, 0xff, 0xff, 0xff,
+    0xbb, 0xbb, 0xff, 0xff, 0xff, 0x77, 0xff, 0xff, 0xff, 0x77, 0x77, 0xff, 0x77, 0xbb, 0xdd, 0xee,
+];
@@ -0,0 +1,2342 @@
+// Copyright 2025, UNSW
+// SPDX-License-Identifier: BSD-2-Clause
+
+use core::sync::atomic::Ordering;
+
+use crate::{
+    dev_log, info,
+    sdmmc::{
+        MMC_RSP_NONE, MMC_RSP_R1, MMC_RSP_R1B, MmcData, MmcDataFlag, SDCARD_DEFAULT_SECTOR_SIZE,
+        SdmmcCmd,
+        constant::{
+            EXT_CSD_BKOPS_EN, EXT_CSD_BKOPS_STATUS, EXT_CSD_BKOPS_SUPPORT,
+            EXT_CSD_BOOT_BUS_CONDITIONS, EXT_CSD_BOOT_BUS_RETAIN, EXT_CSD_BOOT_BUS_WIDTH_1,
+            EXT_CSD_BOOT_BUS_WIDTH_4, EXT_CSD_BOOT_BUS_WIDTH_8, EXT_CSD_BOOT_MODE_SHIFT,
+            EXT_CSD_BOOT_MULT, EXT_CSD_BOOT_WP, EXT_CSD_BOOT_WP_STATUS, EXT_CSD_CACHE_SIZE,
+            EXT_CSD_CARD_TYPE, EXT_CSD_CARD_TYPE_DDR_1_8V, EXT_CSD_CARD_TYPE_HS_52,
+            EXT_CSD_CARD_TYPE_HS200_1_8V, EXT_CSD_CARD_TYPE_HS400_1_8V, EXT_CSD_CMDQ_DEPTH,
+            EXT_CSD_CMDQ_SUPPORT, EXT_CSD_CORRECTLY_PRG_SECTORS_NUM, EXT_CSD_DATA_SECTOR_SIZE,
+            EXT_CSD_DEVICE_LIFE_TIME_EST_TYP_A, EXT_CSD_DEVICE_LIFE_TIME_EST_TYP_B,
+            EXT_CSD_EN_REL_WR, EXT_CSD_ENH_SIZE_MULT, EXT_CSD_ENH_START_ADDR,
+            EXT_CSD_ERASE_GROUP_DEF, EXT_CSD_ERASE_TIMEOUT_MULT, EXT_CSD_FFU_ARG,
+            EXT_CSD_FFU_FEATURES, EXT_CSD_FFU_MODE_OPERATION_CODES, EXT_CSD_FFU_STATUS,
+            EXT_CSD_FW_CONFIG, EXT_CSD_FW_UPDATE_DISABLE, EXT_CSD_GENERIC_CMD6_TIME,
+            EXT_CSD_GP_SIZE_MULT, EXT_CSD_HC_ERASE_GRP_SIZE, EXT_CSD_HC_WP_GRP_SIZE,
+            EXT_CSD_HPI_FEATURES, EXT_CSD_HPI_IMPL_CMD12, EXT_CSD_MAX_ENH_SIZE_MULT,
+            EXT_CSD_MAX_PACKED_WRITES, EXT_CSD_NUM_OF_FW_SEC_PROG, EXT_CSD_OPERATION_CODE_TIMEOUT,
+            EXT_CSD_OUT_OF_INTERRUPT_TIME, EXT_CSD_PART_ATTR_ENH_USR, EXT_CSD_PART_CONFIG,
+            EXT_CSD_PART_CONFIG_ACC_MASK, EXT_CSD_PART_SUPPORT_ENH_ATTRIBUTE_EN,
+            EXT_CSD_PART_SUPPORT_PART_EN, EXT_CSD_PART_SWITCH_TIME,
+            EXT_CSD_PARTITION_SETTING_COMPLETE, EXT_CSD_PARTITION_SETTING_COMPLETED,
+            EXT_CSD_PARTITION_SUPPORT, EXT_CSD_PARTITIONS_ATTRIBUTE, EXT_CSD_POWER_OFF_LONG_TIME,
+            EXT_CSD_PRE_EOL_INFO, EXT_CSD_REL_WR_SEC_C, EXT_CSD_REV, EXT_CSD_RPMB_MULT,
+            EXT_CSD_S_A_TIMEOUT, EXT_CSD_SEC_CNT, EXT_CSD_SEC_ER_EN, EXT_CSD_SEC_ERASE_MULT,
+            EXT_CSD_SEC_FEATURE_SUPPORT, EXT_CSD_SEC_GB_CL_EN, EXT_CSD_SEC_TRIM_MULT, EXT_CSD_SIZE,
+            EXT_CSD_STROBE_SUPPORT, EXT_CSD_STRUCTURE, EXT_CSD_SUPPORTED_MODE_FFU,
+            EXT_CSD_SUPPORTED_MODES, EXT_CSD_TRIM_MULT, EXT_CSD_USER_WP, EXT_CSD_WR_REL_PARAM,
+            MMC_CMD_ARG_HPI, MMC_CMD_ARG_SLEEP, MMC_CMD_BUS_TEST_R, MMC_CMD_BUS_TEST_W,
+            MMC_CMD_READ_SINGLE_BLOCK, MMC_CMD_RES_MAN, MMC_CMD_SELECT_CARD, MMC_CMD_SEND_EXT_CSD,
+            MMC_CMD_SEND_STATUS, MMC_CMD_SEND_WRITE_PROT_TYPE, MMC_CMD_SLEEP_AWAKE,
+            MMC_CMD_STOP_TRANSMISSION, MMC_CMD_SWITCH, MMC_CMD23_ARG_FORCED_PRG,
+            MMC_CMD23_ARG_REL_WR, MMC_CMD62_ARG_EXIT, MMC_CMD62_ARG1, MMC_CMD62_ARG2,
+            MMC_STATE_PRG, MMC_STATUS_CURR_STATE, MMC_STATUS_RDY_FOR_DATA, MMC_STATUS_SWITCH_ERROR,
+            MMC_SWITCH_MODE_CMD_SET, MMC_SWITCH_MODE_WRITE_BYTE,
+        },
+        mmc_struct::CardInfo,
+        print_card_capacity,
+    },
+    sdmmc_os::Sleep,
+    sdmmc_traits::SdmmcHardware,
+};
+
+use super::{
+    SdmmcError,
+    capability::{
+        EMmcCapability, MMC_TIMING_LEGACY, MMC_TIMING_MMC_DDR52, MMC_TIMING_MMC_HS,
+        MMC_TIMING_MMC_HS200, MMC_TIMING_MMC_HS400,
+    },
+    mmc_struct::{BlockTransmissionMode, MmcBusWidth, MmcState, WriteFlags},
+};
+
+/// The relative card address the host assigns to the eMMC through CMD3
+/// Unlike sdcard, eMMC does not publish its own RCA, Linux and uboot both use 1 here
+pub(crate) const EMMC_DEFAULT_RELATIVE_ADDR: u16 = 1;
+
+/// Linux waits for this long for FLUSH_CACHE, the spec does not define a timeout for it
+pub(crate) const EMMC_CACHE_FLUSH_TIMEOUT_MS: u32 = 30_000;
+
+/// Linux waits for this long for SANITIZE_START, the spec does not define a timeout for it
+pub(crate) const EMMC_SANITIZE_TIMEOUT_MS: u32 = 240_000;
+
+/// The packed header takes one 512 bytes block in front of the data
+pub const PACKED_HEADER_SIZE: usize = 512;
+
+// Version and direction in the first line of the packed header
+const PACKED_CMD_VERSION: u8 = 0x01;
+const PACKED_CMD_WRITE: u8 = 0x02;
+
+/// MMC specification 3.1 is the first version that supports set block count cmd
+pub(crate) const EMMC_SPEC_VERSION_CMD23: u8 = 3;
+
+#[allow(dead_code)]
+pub struct EMmc {
+    pub(crate) card_id: u128,
+    pub(crate) manufacture_info: EmmcCid,
+    pub(crate) card_specific_data: EmmcCsd,
+    pub(crate) relative_card_addr: u16,
+    pub(crate) card_state: MmcState,
+    pub(crate) method: BlockTransmissionMode,
+    /// Sector mode devices (> 2GB) are addressed by 512 bytes sector instead of byte
+    pub(crate) sector_mode: bool,
+    /// EXT_CSD is read in tune_performance as it needs memory to DMA into
+    pub(crate) ext_csd: Option<ExtCsd>,
+    /// The hardware partition being accessed by read/write, selected through PARTITION_CONFIG
+    pub(crate) current_partition: EmmcPartition,
+    /// Whether the volatile cache is turned on through CACHE_CTRL
+    pub(crate) cache_enabled: bool,
+    /// Whether HPI is turned on through HPI_MGMT
+    pub(crate) hpi_enabled: bool,
+    /// Whether the manual background operations started by the driver may still be running
+    pub(crate) bkops_running: bool,
+    /// Whether the command queue is turned on through CMDQ_MODE_EN
+    pub(crate) cmdq_enabled: bool,
+    /// Whether POWER_OFF_NOTIFICATION is set to POWERED_ON, the eMMC expects a notification before power off
+    pub(crate) power_off_notify: bool,
+    /// Whether the eMMC is put into sleep state through CMD5
+    pub(crate) sleeping: bool,
+}
+
+/// One write carried by a packed write, the data of the entries follows the packed header in order
+#[derive(Debug, Copy, Clone)]
+pub struct PackedWriteEntry {
+    pub start_idx: u64,
+    pub blockcnt: u32,
+    pub reliable_write: bool,
+}
+
+/// Hardware partitions of the eMMC, the value is the PARTITION_ACCESS field of PARTITION_CONFIG
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EmmcPartition {
+    UserData = 0,
+    Boot0 = 1,
+    Boot1 = 2,
+    Rpmb = 3,
+    GeneralPurpose1 = 4,
+    GeneralPurpose2 = 5,
+    GeneralPurpose3 = 6,
+    GeneralPurpose4 = 7,
+}
+
+/// Erase operations of the eMMC, the value is the argument of CMD38
+/// Secure trim is issued as the two steps SECURE_TRIM1 and SECURE_TRIM2 sequence
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EmmcEraseType {
+    /// Erase whole erase groups
+    Erase,
+    /// Erase write blocks, the data is guaranteed to be zero or one afterwards
+    Trim,
+    /// Erase write blocks, the content of the blocks is undefined afterwards
+    Discard,
+    /// Erase whole erase groups and purge every copy of the data from the device
+    SecureErase,
+    /// Erase write blocks and purge every copy of the data from the device
+    SecureTrim,
+}
+
+impl EmmcPartition {
+    fn from_partition_access(access: u8) -> EmmcPartition {
+        match access & EXT_CSD_PART_CONFIG_ACC_MASK {
+            1 => EmmcPartition::Boot0,
+            2 => EmmcPartition::Boot1,
+            3 => EmmcPartition::Rpmb,
+            4 => EmmcPartition::GeneralPurpose1,
+            5 => EmmcPartition::GeneralPurpose2,
+            6 => EmmcPartition::GeneralPurpose3,
+            7 => EmmcPartition::GeneralPurpose4,
+            _ => EmmcPartition::UserData,
+        }
+    }
+}
+
+/// Sizes of the eMMC hardware partitions in bytes, a partition with size zero does not exist
+#[derive(Debug, Clone)]
+pub struct EmmcPartitionInfo {
+    pub current: EmmcPartition,
+    pub user_data_size: u64,
+    /// boot0 and boot1 always have the same size
+    pub boot_partition_size: u64,
+    pub rpmb_partition_size: u64,
+    pub gp_partition_size: [u64; 4],
+}
+
+impl EmmcPartitionInfo {
+    pub fn size(&self, partition: EmmcPartition) -> u64 {
+        match partition {
+            EmmcPartition::UserData => self.user_data_size,
+            EmmcPartition::Boot0 | EmmcPartition::Boot1 => self.boot_partition_size,
+            EmmcPartition::Rpmb => self.rpmb_partition_size,
+            EmmcPartition::GeneralPurpose1 => self.gp_partition_size[0],
+            EmmcPartition::GeneralPurpose2 => self.gp_partition_size[1],
+            EmmcPartition::GeneralPurpose3 => self.gp_partition_size[2],
+            EmmcPartition::GeneralPurpose4 => self.gp_partition_size[3],
+        }
+    }
+}
+
+/// Layout of the general purpose partitions and the enhanced user data area, which can only be written once
+/// Sizes are in number of partition units, one unit is HC_WP_GRP_SIZE * HC_ERASE_GRP_SIZE * 512KB,
+/// check EmmcPartitionPlan::unit_size
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EmmcPartitionLayout {
+    /// Size of general purpose partition 1 to 4, zero leaves the partition out
+    pub gp_size_mult: [u32; 4],
+    /// Whether each general purpose partition is enhanced, which usually means pSLC
+    pub gp_enhanced: [bool; 4],
+    /// Start of the enhanced user data area in 512 bytes sectors, aligned to the partition unit
+    pub enh_start_sector: u32,
+    /// Size of the enhanced user data area, zero leaves it out
+    pub enh_size_mult: u32,
+}
+
+/// Outcome of checking an EmmcPartitionLayout against the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmmcPartitionPlan {
+    /// Size of one unit of GP_SIZE_MULT and ENH_SIZE_MULT in bytes
+    pub unit_size: u64,
+    /// Units taken by the enhanced areas, including the enhanced general purpose partitions
+    pub enhanced_units: u32,
+    /// MAX_ENH_SIZE_MULT, the most units the enhanced areas can take together
+    pub max_enhanced_units: u32,
+    /// The layout only takes effect after the next power cycle
+    pub committed: bool,
+}
+
+/// Level of the background operations the eMMC needs, from BKOPS_STATUS
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BkopsStatus {
+    NotRequired = 0,
+    Outstanding = 1,
+    PerformanceImpacted = 2,
+    Critical = 3,
+}
+
+impl BkopsStatus {
+    fn new(value: u8) -> BkopsStatus {
+        match value & 0x3 {
+            0 => BkopsStatus::NotRequired,
+            1 => BkopsStatus::Outstanding,
+            2 => BkopsStatus::PerformanceImpacted,
+            _ => BkopsStatus::Critical,
+        }
+    }
+}
+
+/// How the background operations of the eMMC are started
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BkopsMode {
+    /// The driver starts the background operations when the host is idle and the policy agrees
+    Manual,
+    /// The eMMC starts the background operations by itself when idle, introduced in eMMC 5.1
+    Auto,
+}
+
+/// Estimated lifetime used by one type of memory, from DEVICE_LIFE_TIME_EST_TYP_A/B
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeviceLifeTime {
+    /// The device does not report the estimation
+    Undefined,
+    /// Up to this percentage of the lifetime is used, in step of 10%
+    Used(u8),
+    /// The device has exceeded its maximum estimated lifetime
+    Exceeded,
+}
+
+impl DeviceLifeTime {
+    fn new(value: u8) -> DeviceLifeTime {
+        match value {
+            0x01..=0x0A => DeviceLifeTime::Used(value * 10),
+            0x0B => DeviceLifeTime::Exceeded,
+            _ => DeviceLifeTime::Undefined,
+        }
+    }
+}
+
+/// Consumption of the reserved blocks, from PRE_EOL_INFO
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PreEolInfo {
+    Undefined,
+    Normal,
+    /// 80% of the reserved blocks are consumed
+    Warning,
+    /// 90% of the reserved blocks are consumed
+    Urgent,
+}
+
+impl PreEolInfo {
+    fn new(value: u8) -> PreEolInfo {
+        match value {
+            0x01 => PreEolInfo::Normal,
+            0x02 => PreEolInfo::Warning,
+            0x03 => PreEolInfo::Urgent,
+            _ => PreEolInfo::Undefined,
+        }
+    }
+}
+
+// Value of FFU_STATUS reported through SdmmcError::EFFU
+pub const FFU_STATUS_SUCCESS: u8 = 0x00;
+pub const FFU_STATUS_GENERAL_ERROR: u8 = 0x10;
+pub const FFU_STATUS_INSTALL_ERROR: u8 = 0x11;
+pub const FFU_STATUS_DOWNLOAD_ERROR: u8 = 0x12;
+
+/// Whether the firmware written by field firmware update is already running
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FfuInstallation {
+    Installed,
+    /// The new firmware takes effect after the next power cycle or hardware reset
+    PendingReset,
+}
+
+/// Partition the eMMC boots from, the value is the BOOT_PARTITION_ENABLE field of PARTITION_CONFIG
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EmmcBootPartition {
+    Disabled = 0,
+    Boot0 = 1,
+    Boot1 = 2,
+    UserData = 7,
+}
+
+/// Timing of the boot operation, the value is the BOOT_MODE field of BOOT_BUS_CONDITIONS
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EmmcBootMode {
+    SdrBackwardCompatible = 0,
+    SdrHighSpeed = 1,
+    Ddr = 2,
+}
+
+/// Bus used by the boot operation, written to BOOT_BUS_CONDITIONS
+#[derive(Debug, Copy, Clone)]
+pub struct EmmcBootBusConditions {
+    pub bus_width: MmcBusWidth,
+    pub mode: EmmcBootMode,
+    /// Keep the boot bus width after the boot operation instead of going back to 1 bit mode
+    pub retain_after_boot: bool,
+}
+
+impl EmmcBootBusConditions {
+    pub(crate) fn value(&self) -> Result<u8, SdmmcError> {
+        let bus_width: u8 = match self.bus_width {
+            MmcBusWidth::Width1 => EXT_CSD_BOOT_BUS_WIDTH_1,
+            MmcBusWidth::Width4 => EXT_CSD_BOOT_BUS_WIDTH_4,
+            MmcBusWidth::Width8 => EXT_CSD_BOOT_BUS_WIDTH_8,
+        };
+
+        // DDR boot is only defined for 4 and 8 bits bus
+        if self.mode == EmmcBootMode::Ddr && self.bus_width == MmcBusWidth::Width1 {
+            return Err(SdmmcError::EINVAL);
+        }
+
+        let mut value: u8 = bus_width | ((self.mode as u8) << EXT_CSD_BOOT_MODE_SHIFT);
+        if self.retain_after_boot {
+            value |= EXT_CSD_BOOT_BUS_RETAIN;
+        }
+
+        Ok(value)
+    }
+}
+
+/// Write protection of the boot partitions through BOOT_WP
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EmmcBootWriteProtect {
+    /// Cleared by the next power cycle or hardware reset
+    PowerOn,
+    /// Cannot be cleared
+    Permanent,
+}
+
+/// Write protection of a write protect group, the value is the encoding used by SEND_WRITE_PROT_TYPE
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WriteProtectType {
+    None = 0,
+    /// Cleared by CLR_WRITE_PROT
+    Temporary = 1,
+    /// Cleared by the next power cycle or hardware reset
+    PowerOn = 2,
+    /// Cannot be cleared
+    Permanent = 3,
+}
+
+impl WriteProtectType {
+    pub(crate) fn new(value: u8) -> WriteProtectType {
+        match value & 0x3 {
+            1 => WriteProtectType::Temporary,
+            2 => WriteProtectType::PowerOn,
+            3 => WriteProtectType::Permanent,
+            _ => WriteProtectType::None,
+        }
+    }
+}
+
+/// Health of the eMMC reported in EXT_CSD, introduced in eMMC 5.0
+/// Older devices report everything as undefined
+#[derive(Debug, Clone)]
+pub struct HealthReport {
+    /// Usually the SLC memory like the boot and enhanced partitions
+    pub life_time_est_a: DeviceLifeTime,
+    /// Usually the MLC memory like the user data area
+    pub life_time_est_b: DeviceLifeTime,
+    pub pre_eol_info: PreEolInfo,
+}
+
+impl EMmc {
+    /// Unsafe because dereference raw pointer
+    pub(crate) unsafe fn emmc_get_ext_csd<T: SdmmcHardware>(
+        hardware: &mut T,
+        sleep: &mut dyn Sleep,
+        physical_memory: u64,
+        raw_memory: *mut [u8; EXT_CSD_SIZE],
+        invalidate_cache_fn: fn(),
+    ) -> Result<ExtCsd, SdmmcError> {
+        let mut resp: [u32; 4] = [0; 4];
+        let cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_SEND_EXT_CSD,
+            resp_type: MMC_RSP_R1,
+            cmdarg: 0,
+        };
+        let data: MmcData = MmcData {
+            blocksize: EXT_CSD_SIZE as u32,
+            blockcnt: 1,
+            flags: MmcDataFlag::SdmmcDataRead,
+            addr: physical_memory,
+        };
+
+        hardware.sdmmc_do_request(sleep, &cmd, Some(&data), &mut resp, 0)?;
+
+        core::sync::atomic::fence(Ordering::Acquire);
+
+        invalidate_cache_fn();
+
+        let ext_csd: ExtCsd = ExtCsd::new(unsafe { &*raw_memory })?;
+
+        dev_log!("EXT_CSD: {:?}\n", ext_csd);
+
+        Ok(ext_csd)
+    }
+
+    /// Deselect the eMMC and put it into sleep state through CMD5, or wake it up and select it again
+    /// Only CMD0 and CMD5 are accepted while the eMMC is asleep
+    pub(crate) fn emmc_sleep_awake<T: SdmmcHardware>(
+        hardware: &mut T,
+        sleep: &mut dyn Sleep,
+        rca: u16,
+        enter_sleep: bool,
+        timeout_ms: u32,
+    ) -> Result<(), SdmmcError> {
+        let mut resp: [u32; 4] = [0; 4];
+
+        // CMD5 is only accepted in standby state, the card does not respond to the deselect cmd
+        if enter_sleep {
+            let cmd: SdmmcCmd = SdmmcCmd {
+                cmdidx: MMC_CMD_SELECT_CARD,
+                resp_type: MMC_RSP_NONE,
+                cmdarg: 0,
+            };
+            hardware.sdmmc_do_request(sleep, &cmd, None, &mut resp, 0)?;
+        }
+
+        let mut cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_SLEEP_AWAKE,
+            resp_type: MMC_RSP_R1B,
+            cmdarg: (rca as u32) << 16,
+        };
+        if enter_sleep {
+            cmd.cmdarg |= MMC_CMD_ARG_SLEEP;
+        }
+        hardware.sdmmc_do_request(sleep, &cmd, None, &mut resp, 0)?;
+
+        // The card status cannot be polled in sleep state, so wait for the whole timeout
+        // in case the host does not wait for the busy signal
+        sleep.usleep(timeout_ms * 1_000);
+
+        if !enter_sleep {
+            cmd = SdmmcCmd {
+                cmdidx: MMC_CMD_SELECT_CARD,
+                resp_type: MMC_RSP_R1,
+                cmdarg: (rca as u32) << 16,
+            };
+            hardware.sdmmc_do_request(sleep, &cmd, None, &mut resp, 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Send CMD13 to get the card status of the eMMC
+    pub(crate) fn emmc_send_status<T: SdmmcHardware>(
+        hardware: &mut T,
+        sleep: &mut dyn Sleep,
+        rca: u16,
+    ) -> Result<u32, SdmmcError> {
+        let mut resp: [u32; 4] = [0; 4];
+        let cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_SEND_STATUS,
+            resp_type: MMC_RSP_R1,
+            cmdarg: (rca as u32) << 16,
+        };
+
+        hardware.sdmmc_do_request(sleep, &cmd, None, &mut resp, 1)?;
+
+        Ok(resp[0])
+    }
+
+    /// Write one byte of the EXT_CSD through CMD6 and wait for the eMMC to leave programming state
+    /// `timeout_ms` should normally be GENERIC_CMD6_TIME, but some fields define their own timeout
+    pub(crate) fn emmc_switch<T: SdmmcHardware>(
+        hardware: &mut T,
+        sleep: &mut dyn Sleep,
+        rca: u16,
+        index: usize,
+        value: u8,
+        timeout_ms: u32,
+    ) -> Result<(), SdmmcError> {
+        Self::emmc_send_switch(hardware, sleep, index, value)?;
+
+        Self::emmc_wait_switch_done(hardware, sleep, rca, timeout_ms).inspect_err(|_| {
+            dev_log!(
+                "eMMC switch failed, index: {}, value: 0x{:02x}\n",
+                index,
+                value
+            );
+        })
+    }
+
+    /// Only send the CMD6 without checking the card status afterwards
+    /// Used when the switch changes the bus timing, so the host timing needs to be changed
+    /// before the card status could be read reliably
+    pub(crate) fn emmc_send_switch<T: SdmmcHardware>(
+        hardware: &mut T,
+        sleep: &mut dyn Sleep,
+        index: usize,
+        value: u8,
+    ) -> Result<(), SdmmcError> {
+        let mut resp: [u32; 4] = [0; 4];
+        let cmd: SdmmcCmd = Self::switch_cmd(index, value);
+
+        hardware.sdmmc_do_request(sleep, &cmd, None, &mut resp, 0)
+    }
+
+    /// CMD6 writing `value` into the EXT_CSD byte at `index`
+    pub(crate) fn switch_cmd(index: usize, value: u8) -> SdmmcCmd {
+        SdmmcCmd {
+            cmdidx: MMC_CMD_SWITCH,
+            resp_type: MMC_RSP_R1B,
+            cmdarg: (MMC_SWITCH_MODE_WRITE_BYTE << 24)
+                | ((index as u32) << 16)
+                | ((value as u32) << 8)
+                | MMC_SWITCH_MODE_CMD_SET,
+        }
+    }
+
+    /// The high priority interrupt, which stops the ongoing operation of the eMMC
+    /// Depending on HPI_FEATURES, HPI is either CMD12 or CMD13 with the HPI bit set
+    pub(crate) fn hpi_cmd(rca: u16, hpi_features: u8) -> SdmmcCmd {
+        if hpi_features & EXT_CSD_HPI_IMPL_CMD12 != 0 {
+            SdmmcCmd {
+                cmdidx: MMC_CMD_STOP_TRANSMISSION,
+                resp_type: MMC_RSP_R1B,
+                cmdarg: ((rca as u32) << 16) | MMC_CMD_ARG_HPI,
+            }
+        } else {
+            SdmmcCmd {
+                cmdidx: MMC_CMD_SEND_STATUS,
+                resp_type: MMC_RSP_R1,
+                cmdarg: ((rca as u32) << 16) | MMC_CMD_ARG_HPI,
+            }
+        }
+    }
+
+    /// Send the high priority interrupt, check hpi_cmd
+    pub(crate) fn emmc_send_hpi<T: SdmmcHardware>(
+        hardware: &mut T,
+        sleep: &mut dyn Sleep,
+        rca: u16,
+        hpi_features: u8,
+    ) -> Result<(), SdmmcError> {
+        let mut resp: [u32; 4] = [0; 4];
+        let cmd: SdmmcCmd = Self::hpi_cmd(rca, hpi_features);
+
+        hardware.sdmmc_do_request(sleep, &cmd, None, &mut resp, 0)
+    }
+
+    /// Read the write protection of the groups starting from `address` through CMD30 or CMD31
+    /// `address` is the one resolved by card_address
+    /// CMD30 returns 4 bytes and CMD31 returns 8 bytes, both in big endian
+    ///
+    /// # Safety
+    /// `raw_memory` and `physical_memory` must point to the same region that the host can DMA into
+    pub(crate) unsafe fn emmc_send_write_protect<T: SdmmcHardware>(
+        hardware: &mut T,
+        sleep: &mut dyn Sleep,
+        cmdidx: u32,
+        address: u32,
+        physical_memory: u64,
+        raw_memory: *mut [u8; 64],
+        invalidate_cache_fn: fn(),
+    ) -> Result<u64, SdmmcError> {
+        let len: usize = if cmdidx == MMC_CMD_SEND_WRITE_PROT_TYPE {
+            8
+        } else {
+            4
+        };
+
+        let mut resp: [u32; 4] = [0; 4];
+        let cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx,
+            resp_type: MMC_RSP_R1,
+            cmdarg: address,
+        };
+        let data: MmcData = MmcData {
+            blocksize: len as u32,
+            blockcnt: 1,
+            flags: MmcDataFlag::SdmmcDataRead,
+            addr: physical_memory,
+        };
+
+        hardware.sdmmc_do_request(sleep, &cmd, Some(&data), &mut resp, 0)?;
+
+        core::sync::atomic::fence(Ordering::Acquire);
+
+        invalidate_cache_fn();
+
+        let raw: &[u8; 64] = unsafe { &*raw_memory };
+
+        Ok(raw[..len]
+            .iter()
+            .fold(0u64, |value, byte| (value << 8) | *byte as u64))
+    }
+
+    /// Poll the card status until the eMMC leaves programming state after CMD6
+    pub(crate) fn emmc_wait_switch_done<T: SdmmcHardware>(
+        hardware: &mut T,
+        sleep: &mut dyn Sleep,
+        rca: u16,
+        timeout_ms: u32,
+    ) -> Result<(), SdmmcError> {
+        Self::emmc_wait_busy(hardware, sleep, rca, timeout_ms, MMC_STATUS_SWITCH_ERROR)
+    }
+
+    /// Poll the card status until the eMMC leaves programming state, any bit in `error_mask`
+    /// being set in the card status fails the operation
+    pub(crate) fn emmc_wait_busy<T: SdmmcHardware>(
+        hardware: &mut T,
+        sleep: &mut dyn Sleep,
+        rca: u16,
+        timeout_ms: u32,
+        error_mask: u32,
+    ) -> Result<(), SdmmcError> {
+        // The host is supposed to wait for the busy signal to be released
+        // But polling the card status is the only way to know if the operation has succeeded
+        for _ in 0..=timeout_ms {
+            let status: u32 = Self::emmc_send_status(hardware, sleep, rca)?;
+
+            if status & error_mask != 0 {
+                dev_log!("eMMC operation error, status: 0x{:08x}\n", status);
+                return Err(SdmmcError::EINVAL);
+            }
+
+            if status & MMC_STATUS_RDY_FOR_DATA != 0
+                && status & MMC_STATUS_CURR_STATE != MMC_STATE_PRG
+            {
+                return Ok(());
+            }
+
+            sleep.usleep(1_000);
+        }
+
+        Err(SdmmcError::ETIMEDOUT)
+    }
+
+    /// Run the bus test procedure (CMD19 followed by CMD14) to check if all data lanes are connected
+    /// The eMMC returns the inverted pattern of the data being written
+    /// Unsafe because dereference raw pointer
+    pub(crate) unsafe fn emmc_bus_test<T: SdmmcHardware>(
+        hardware: &mut T,
+        sleep: &mut dyn Sleep,
+        bus_width: MmcBusWidth,
+        physical_memory: u64,
+        raw_memory: *mut [u8; EXT_CSD_SIZE],
+        invalidate_cache_fn: fn(),
+    ) -> Result<(), SdmmcError> {
+        const BUS_TEST_PATTERN_8BIT: [u8; 8] = [0x55, 0xAA, 0, 0, 0, 0, 0, 0];
+        const BUS_TEST_PATTERN_4BIT: [u8; 4] = [0x5A, 0, 0, 0];
+
+        let pattern: &[u8] = match bus_width {
+            MmcBusWidth::Width8 => &BUS_TEST_PATTERN_8BIT,
+            MmcBusWidth::Width4 => &BUS_TEST_PATTERN_4BIT,
+            MmcBusWidth::Width1 => return Err(SdmmcError::EINVAL),
+        };
+
+        let mut resp: [u32; 4] = [0; 4];
+
+        unsafe { (&mut *raw_memory)[..pattern.len()].copy_from_slice(pattern) };
+
+        core::sync::atomic::fence(Ordering::Release);
+
+        // Let the cache function write the pattern back to memory before the host read it
+        invalidate_cache_fn();
+
+        let mut cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_BUS_TEST_W,
+            resp_type: MMC_RSP_R1,
+            cmdarg: 0,
+        };
+        let mut data: MmcData = MmcData {
+            blocksize: pattern.len() as u32,
+            blockcnt: 1,
+            flags: MmcDataFlag::SdmmcDataWrite,
+            addr: physical_memory,
+        };
+
+        hardware.sdmmc_do_request(sleep, &cmd, Some(&data), &mut resp, 0)?;
+
+        cmd = SdmmcCmd {
+            cmdidx: MMC_CMD_BUS_TEST_R,
+            resp_type: MMC_RSP_R1,
+            cmdarg: 0,
+        };
+        data.flags = MmcDataFlag::SdmmcDataRead;
+
+        // The data after the first byte of each lane is undefined, so ignore the crc error
+        // reported by some hosts as Linux does
+        match hardware.sdmmc_do_request(sleep, &cmd, Some(&data), &mut resp, 0) {
+            Ok(_) | Err(SdmmcError::EIO) => {}
+            Err(e) => return Err(e),
+        }
+
+        core::sync::atomic::fence(Ordering::Acquire);
+
+        invalidate_cache_fn();
+
+        // Only the first byte of each data lane is meaningful
+        for (i, expected) in pattern.iter().take(pattern.len() / 4).enumerate() {
+            let received: u8 = unsafe { (*raw_memory)[i] };
+            if received ^ expected != 0xFF {
+                dev_log!(
+                    "eMMC bus test failed, byte {}: received 0x{:02x}\n",
+                    i,
+                    received
+                );
+                return Err(SdmmcError::EIO);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read the vendor health report, CMD62 with MMC_CMD62_ARG1 and MMC_CMD62_ARG2 puts the eMMC
+    /// into the vendor mode where the next single block read returns the report instead of user data
+    /// CMD62 with MMC_CMD62_ARG1 and MMC_CMD62_ARG_EXIT brings the eMMC back to normal reads afterwards,
+    /// even if the report could not be read
+    /// The layout of the report is vendor specific, only query devices known to implement this sequence
+    /// Unsafe because dereference raw pointer
+    pub(crate) unsafe fn emmc_vendor_health_report<T: SdmmcHardware>(
+        hardware: &mut T,
+        sleep: &mut dyn Sleep,
+        physical_memory: u64,
+        raw_memory: *mut [u8; EXT_CSD_SIZE],
+        invalidate_cache_fn: fn(),
+    ) -> Result<[u8; EXT_CSD_SIZE], SdmmcError> {
+        let mut resp: [u32; 4] = [0; 4];
+
+        for arg in [MMC_CMD62_ARG1, MMC_CMD62_ARG2] {
+            let cmd: SdmmcCmd = SdmmcCmd {
+                cmdidx: MMC_CMD_RES_MAN,
+                resp_type: MMC_RSP_R1B,
+                cmdarg: arg,
+            };
+            hardware.sdmmc_do_request(sleep, &cmd, None, &mut resp, 0)?;
+        }
+
+        let cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: MMC_CMD_READ_SINGLE_BLOCK,
+            resp_type: MMC_RSP_R1,
+            cmdarg: 0,
+        };
+        let data: MmcData = MmcData {
+            blocksize: EXT_CSD_SIZE as u32,
+            blockcnt: 1,
+            flags: MmcDataFlag::SdmmcDataRead,
+            addr: physical_memory,
+        };
+
+        let res: Result<(), SdmmcError> =
+            hardware.sdmmc_do_request(sleep, &cmd, Some(&data), &mut resp, 0);
+
+        for arg in [MMC_CMD62_ARG1, MMC_CMD62_ARG_EXIT] {
+            let cmd: SdmmcCmd = SdmmcCmd {
+                cmdidx: MMC_CMD_RES_MAN,
+                resp_type: MMC_RSP_R1B,
+                cmdarg: arg,
+            };
+            hardware.sdmmc_do_request(sleep, &cmd, None, &mut resp, 0)?;
+        }
+
+        res?;
+
+        core::sync::atomic::fence(Ordering::Acquire);
+
+        invalidate_cache_fn();
+
+        Ok(unsafe { *raw_memory })
+    }
+
+    /// Store the EXT_CSD read from the device and fix up the fields that depend on it
+    pub(crate) fn update_ext_csd(&mut self, ext_csd: ExtCsd) {
+        self.manufacture_info
+            .adjust_manufacturing_year(ext_csd.revision);
+        self.current_partition = EmmcPartition::from_partition_access(ext_csd.partition_config);
+        self.ext_csd = Some(ext_csd);
+    }
+
+    /// The partition sizes are only known after the EXT_CSD is read
+    pub fn partition_info(&self) -> Result<EmmcPartitionInfo, SdmmcError> {
+        let ext_csd: &ExtCsd = self.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+
+        Ok(EmmcPartitionInfo {
+            current: self.current_partition,
+            user_data_size: self.capacity(),
+            boot_partition_size: ext_csd.boot_partition_size,
+            rpmb_partition_size: ext_csd.rpmb_partition_size,
+            gp_partition_size: ext_csd.gp_partition_size,
+        })
+    }
+
+    /// Check the partition layout against PARTITION_SUPPORT, MAX_ENH_SIZE_MULT and HC_WP_GRP_SIZE,
+    /// check JESD84-B51 section 6.2.3
+    pub(crate) fn check_partition_layout(
+        &self,
+        layout: &EmmcPartitionLayout,
+    ) -> Result<EmmcPartitionPlan, SdmmcError> {
+        let ext_csd: &ExtCsd = self.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+
+        if ext_csd.partition_support & EXT_CSD_PART_SUPPORT_PART_EN == 0 {
+            return Err(SdmmcError::EUNSUPPORTEDCARD);
+        }
+        if ext_csd.partition_setting_completed {
+            dev_log!("The partitions of the eMMC have been configured already\n");
+            return Err(SdmmcError::EINVAL);
+        }
+
+        // Partition units are in high capacity write protect groups
+        let unit_sectors: u64 =
+            ext_csd.hc_erase_group_size as u64 * ext_csd.hc_wp_group_size as u64;
+        if unit_sectors == 0 {
+            return Err(SdmmcError::EUNSUPPORTEDCARD);
+        }
+
+        // Every MULT field is 3 bytes wide
+        const MULT_MAX: u32 = 0xFF_FFFF;
+        if layout.enh_size_mult > MULT_MAX
+            || layout.gp_size_mult.iter().any(|mult| *mult > MULT_MAX)
+        {
+            return Err(SdmmcError::EINVAL);
+        }
+
+        let mut enhanced_units: u32 = layout.enh_size_mult;
+        let mut gp_units: u64 = 0;
+        for (mult, enhanced) in layout.gp_size_mult.iter().zip(layout.gp_enhanced.iter()) {
+            if *enhanced {
+                if *mult == 0 {
+                    return Err(SdmmcError::EINVAL);
+                }
+                enhanced_units = enhanced_units.saturating_add(*mult);
+            }
+            gp_units += *mult as u64;
+        }
+
+        if gp_units == 0 && layout.enh_size_mult == 0 {
+            return Err(SdmmcError::EINVAL);
+        }
+        if enhanced_units != 0
+            && ext_csd.partition_support & EXT_CSD_PART_SUPPORT_ENH_ATTRIBUTE_EN == 0
+        {
+            return Err(SdmmcError::EUNSUPPORTEDCARD);
+        }
+        if enhanced_units > ext_csd.max_enh_size_mult {
+            dev_log!(
+                "Enhanced areas of {} units exceed MAX_ENH_SIZE_MULT {}\n",
+                enhanced_units,
+                ext_csd.max_enh_size_mult
+            );
+            return Err(SdmmcError::EINVAL);
+        }
+
+        // The general purpose partitions are taken from the user data area
+        let user_sectors: u64 = self.capacity() / 512;
+        let gp_sectors: u64 = gp_units * unit_sectors;
+        if gp_sectors > user_sectors {
+            return Err(SdmmcError::EINVAL);
+        }
+        if layout.enh_size_mult != 0 {
+            // ENH_START_ADDR is one time programmable, so a start address not fitting into it is refused here
+            self.card_address(layout.enh_start_sector as u64)?;
+            let enh_start: u64 = layout.enh_start_sector as u64;
+            if !enh_start.is_multiple_of(unit_sectors)
+                || enh_start + layout.enh_size_mult as u64 * unit_sectors
+                    > user_sectors - gp_sectors
+            {
+                return Err(SdmmcError::EINVAL);
+            }
+        }
+
+        Ok(EmmcPartitionPlan {
+            unit_size: unit_sectors * 512,
+            enhanced_units,
+            max_enhanced_units: ext_csd.max_enh_size_mult,
+            committed: false,
+        })
+    }
+
+    /// The EXT_CSD bytes written for the partition layout, PARTITION_SETTING_COMPLETED is not included
+    pub(crate) fn partition_layout_registers(
+        &self,
+        layout: &EmmcPartitionLayout,
+    ) -> Result<[(usize, u8); 20], SdmmcError> {
+        let mut registers: [(usize, u8); 20] = [(0, 0); 20];
+
+        // ENH_START_ADDR is in bytes for byte addressed devices
+        let enh_start_addr: u32 = self.card_address(layout.enh_start_sector as u64)?;
+        for (i, byte) in enh_start_addr.to_le_bytes().iter().enumerate() {
+            registers[i] = (EXT_CSD_ENH_START_ADDR + i, *byte);
+        }
+        for i in 0..3 {
+            registers[4 + i] = (
+                EXT_CSD_ENH_SIZE_MULT + i,
+                (layout.enh_size_mult >> (i * 8)) as u8,
+            );
+        }
+
+        let mut attribute: u8 = 0;
+        if layout.enh_size_mult != 0 {
+            attribute |= EXT_CSD_PART_ATTR_ENH_USR;
+        }
+        for (n, mult) in layout.gp_size_mult.iter().enumerate() {
+            for i in 0..3 {
+                registers[7 + n * 3 + i] =
+                    (EXT_CSD_GP_SIZE_MULT + n * 3 + i, (*mult >> (i * 8)) as u8);
+            }
+            if layout.gp_enhanced[n] {
+                attribute |= 1 << (n + 1);
+            }
+        }
+        registers[19] = (EXT_CSD_PARTITIONS_ATTRIBUTE, attribute);
+
+        Ok(registers)
+    }
+
+    pub fn bkops_status(&self) -> Result<BkopsStatus, SdmmcError> {
+        let ext_csd: &ExtCsd = self.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+
+        Ok(BkopsStatus::new(ext_csd.bkops_status))
+    }
+
+    pub fn health_report(&self) -> Result<HealthReport, SdmmcError> {
+        let ext_csd: &ExtCsd = self.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+
+        Ok(HealthReport {
+            life_time_est_a: DeviceLifeTime::new(ext_csd.life_time_est_typ_a),
+            life_time_est_b: DeviceLifeTime::new(ext_csd.life_time_est_typ_b),
+            pre_eol_info: PreEolInfo::new(ext_csd.pre_eol_info),
+        })
+    }
+
+    /// Address argument of the data cmds for block `start_idx`, eMMC of 2GB or less are byte addressed
+    /// Addresses that do not fit into the argument are rejected instead of being truncated
+    pub(crate) fn card_address(&self, start_idx: u64) -> Result<u32, SdmmcError> {
+        let address: u64 = if self.sector_mode {
+            start_idx
+        } else {
+            start_idx
+                .checked_mul(SDCARD_DEFAULT_SECTOR_SIZE as u64)
+                .ok_or(SdmmcError::EINVAL)?
+        };
+
+        u32::try_from(address).map_err(|_| SdmmcError::EINVAL)
+    }
+
+    /// Check that the blocks being accessed are within the selected partition
+    /// Without the EXT_CSD, only the user data area could be selected, so it is checked against the CSD capacity.
+    /// The CSD does not hold the capacity of a sector addressed eMMC, so it needs tune_performance to read
+    /// the EXT_CSD first. The RPMB partition is only accessed through the RPMB frames
+    pub(crate) fn check_block_range(
+        &self,
+        start_idx: u64,
+        blockcnt: u32,
+    ) -> Result<(), SdmmcError> {
+        if self.current_partition == EmmcPartition::Rpmb {
+            dev_log!("The RPMB partition cannot be accessed through block reads and writes\n");
+            return Err(SdmmcError::EINVAL);
+        }
+
+        let partition_size: u64 = match self.partition_info() {
+            Ok(info) => info.size(self.current_partition),
+            Err(_) if self.sector_mode => return Err(SdmmcError::EUNDEFINED),
+            Err(_) => self.capacity(),
+        };
+
+        let end_idx: u64 = start_idx
+            .checked_add(blockcnt as u64)
+            .ok_or(SdmmcError::EINVAL)?;
+
+        if end_idx > partition_size / 512 {
+            dev_log!(
+                "Access to block {} - {} is out of {:?}\n",
+                start_idx,
+                end_idx,
+                self.current_partition
+            );
+            return Err(SdmmcError::EINVAL);
+        }
+
+        Ok(())
+    }
+
+    /// CMD23 request bits of a write with `flags`, reliable write is checked against REL_WR_SEC_C
+    pub(crate) fn write_request_bits(
+        &self,
+        start_idx: u64,
+        blockcnt: u32,
+        flags: WriteFlags,
+    ) -> Result<u32, SdmmcError> {
+        self.check_write_protect()?;
+
+        let mut cmd23_flags: u32 = 0;
+
+        // Without the volatile cache, every write goes to the non-volatile storage anyway
+        if flags.force_unit_access && self.cache_enabled {
+            cmd23_flags |= MMC_CMD23_ARG_FORCED_PRG;
+        }
+        if flags.reliable_write {
+            self.check_reliable_write(start_idx, blockcnt)?;
+            cmd23_flags |= MMC_CMD23_ARG_REL_WR;
+        }
+
+        Ok(cmd23_flags)
+    }
+
+    /// Devices without EN_REL_WR in WR_REL_PARAM only guarantee the reliable write of a single sector
+    /// or of REL_WR_SEC_C sectors aligned to REL_WR_SEC_C, check mmc_apply_rel_rw() in Linux
+    fn check_reliable_write(&self, start_idx: u64, blockcnt: u32) -> Result<(), SdmmcError> {
+        let ext_csd: &ExtCsd = self.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+
+        if ext_csd.enhanced_reliable_write || blockcnt == 1 {
+            return Ok(());
+        }
+
+        let rel_wr_sec_c: u32 = ext_csd.rel_wr_sec_c as u32;
+        if rel_wr_sec_c == 0 {
+            return Err(SdmmcError::EUNSUPPORTEDCARD);
+        }
+        if blockcnt != rel_wr_sec_c || !start_idx.is_multiple_of(rel_wr_sec_c as u64) {
+            dev_log!(
+                "Reliable write of {} blocks at {} does not match REL_WR_SEC_C {}\n",
+                blockcnt,
+                start_idx,
+                rel_wr_sec_c
+            );
+            return Err(SdmmcError::EINVAL);
+        }
+
+        Ok(())
+    }
+
+    /// Encode the packed write header, check JESD84-B51 section 6.6.29.1
+    /// Returns the number of blocks in the whole transaction including the header
+    pub(crate) fn packed_write_header(
+        &self,
+        entries: &[PackedWriteEntry],
+        header: &mut [u8; PACKED_HEADER_SIZE],
+    ) -> Result<u32, SdmmcError> {
+        let ext_csd: &ExtCsd = self.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+
+        if ext_csd.max_packed_writes == 0 {
+            return Err(SdmmcError::EUNSUPPORTEDCARD);
+        }
+        self.check_write_protect()?;
+        // Every entry takes 8 bytes of the header after the 8 bytes of the first line
+        if entries.is_empty()
+            || entries.len() > ext_csd.max_packed_writes as usize
+            || entries.len() >= PACKED_HEADER_SIZE / 8
+        {
+            return Err(SdmmcError::EINVAL);
+        }
+
+        header.fill(0);
+        header[0] = PACKED_CMD_VERSION;
+        header[1] = PACKED_CMD_WRITE;
+        header[2] = entries.len() as u8;
+
+        let mut blockcnt: u32 = 1;
+        for (i, entry) in entries.iter().enumerate() {
+            if entry.blockcnt == 0 {
+                return Err(SdmmcError::EINVAL);
+            }
+            self.check_block_range(entry.start_idx, entry.blockcnt)?;
+
+            let mut cmd23_arg: u32 = entry.blockcnt;
+            if entry.reliable_write {
+                self.check_reliable_write(entry.start_idx, entry.blockcnt)?;
+                cmd23_arg |= MMC_CMD23_ARG_REL_WR;
+            }
+
+            let offset: usize = (i + 1) * 8;
+            header[offset..offset + 4].copy_from_slice(&cmd23_arg.to_le_bytes());
+            header[offset + 4..offset + 8]
+                .copy_from_slice(&self.card_address(entry.start_idx)?.to_le_bytes());
+
+            blockcnt = blockcnt
+                .checked_add(entry.blockcnt)
+                .ok_or(SdmmcError::EINVAL)?;
+        }
+
+        // The block count field of CMD23 is 16 bits wide
+        if blockcnt > 0xFFFF {
+            return Err(SdmmcError::EINVAL);
+        }
+
+        Ok(blockcnt)
+    }
+
+    /// Write protect group size in number of 512 bytes sectors, zero if write protect groups are not supported
+    /// The high capacity write protect group size only applies after ERASE_GROUP_DEF is set
+    pub(crate) fn write_protect_group_size(&self) -> u32 {
+        if !self.card_specific_data.write_protect_group_enable {
+            return 0;
+        }
+        match self.ext_csd {
+            Some(ref ext_csd) if ext_csd.erase_group_def && ext_csd.hc_erase_group_size != 0 => {
+                ext_csd.hc_erase_group_size * ext_csd.hc_wp_group_size
+            }
+            _ => self.erase_group_size() * self.card_specific_data.write_protect_group_size,
+        }
+    }
+
+    /// Refuse writes to the whole device protected through the CSD, or to a write protected boot partition
+    /// Write protect groups are checked by the eMMC itself, which reports WP_VIOLATION
+    pub(crate) fn check_write_protect(&self) -> Result<(), SdmmcError> {
+        if self.card_specific_data.write_protected() {
+            return Err(SdmmcError::EWRITEPROTECTED);
+        }
+
+        let boot_wp_status: u8 = match self.ext_csd {
+            Some(ref ext_csd) => ext_csd.boot_wp_status,
+            None => return Ok(()),
+        };
+        let protected: bool = match self.current_partition {
+            EmmcPartition::Boot0 => boot_wp_status & 0x3 != 0,
+            EmmcPartition::Boot1 => (boot_wp_status >> 2) & 0x3 != 0,
+            _ => false,
+        };
+        if protected {
+            return Err(SdmmcError::EWRITEPROTECTED);
+        }
+
+        Ok(())
+    }
+
+    /// Check that `groups` write protect groups from `start_idx` are inside the current partition,
+    /// and return the write protect group size
+    pub(crate) fn check_write_protect_range(
+        &self,
+        start_idx: u64,
+        groups: u32,
+    ) -> Result<u32, SdmmcError> {
+        let group_size: u32 = self.write_protect_group_size();
+        if group_size == 0 {
+            return Err(SdmmcError::EUNSUPPORTEDCARD);
+        }
+        if groups == 0 || !start_idx.is_multiple_of(group_size as u64) {
+            return Err(SdmmcError::EINVAL);
+        }
+
+        let blockcnt: u32 = groups.checked_mul(group_size).ok_or(SdmmcError::EINVAL)?;
+        self.check_block_range(start_idx, blockcnt)?;
+
+        Ok(group_size)
+    }
+
+    /// Erase group size in number of 512 bytes sectors
+    /// The high capacity erase group size only applies after ERASE_GROUP_DEF is set
+    pub(crate) fn erase_group_size(&self) -> u32 {
+        match self.ext_csd {
+            Some(ref ext_csd) if ext_csd.erase_group_def && ext_csd.hc_erase_group_size != 0 => {
+                ext_csd.hc_erase_group_size
+            }
+            _ => {
+                self.card_specific_data.erase_group_size
+                    * (self.card_specific_data.max_write_block_len as u32 / 512)
+            }
+        }
+    }
+
+    /// Check that the erase of blocks `start_idx` to `end_idx` (inclusive) is supported and aligned,
+    /// and return the busy timeout of the whole operation calculated from EXT_CSD, check mmc_mmc_erase_timeout() in Linux
+    pub(crate) fn erase_timeout_ms(
+        &self,
+        start_idx: u64,
+        end_idx: u64,
+        erase_type: EmmcEraseType,
+    ) -> Result<u32, SdmmcError> {
+        if end_idx < start_idx {
+            return Err(SdmmcError::EINVAL);
+        }
+        let blockcnt: u32 =
+            u32::try_from(end_idx - start_idx + 1).map_err(|_| SdmmcError::EINVAL)?;
+        self.check_block_range(start_idx, blockcnt)?;
+
+        let ext_csd: &ExtCsd = self.ext_csd.as_ref().ok_or(SdmmcError::EUNDEFINED)?;
+
+        let supported: bool = match erase_type {
+            EmmcEraseType::Erase => true,
+            EmmcEraseType::Trim => ext_csd.sec_feature_support & EXT_CSD_SEC_GB_CL_EN != 0,
+            // Discard is introduced in eMMC 4.5
+            EmmcEraseType::Discard => ext_csd.revision >= 6,
+            EmmcEraseType::SecureErase => ext_csd.sec_feature_support & EXT_CSD_SEC_ER_EN != 0,
+            EmmcEraseType::SecureTrim => {
+                ext_csd.sec_feature_support & (EXT_CSD_SEC_ER_EN | EXT_CSD_SEC_GB_CL_EN)
+                    == (EXT_CSD_SEC_ER_EN | EXT_CSD_SEC_GB_CL_EN)
+            }
+        };
+        if !supported {
+            return Err(SdmmcError::EUNSUPPORTEDCARD);
+        }
+
+        let erase_group_size: u64 = self.erase_group_size() as u64;
+        if erase_group_size == 0 {
+            return Err(SdmmcError::EUNDEFINED);
+        }
+
+        // Erase works on whole erase groups while the trim variants work on write blocks,
+        // the erase groups are still used to calculate the timeout for them
+        if matches!(
+            erase_type,
+            EmmcEraseType::Erase | EmmcEraseType::SecureErase
+        ) && (!start_idx.is_multiple_of(erase_group_size)
+            || !(end_idx + 1).is_multiple_of(erase_group_size))
+        {
+            dev_log!(
+                "Erase of block {} - {} is not aligned to the erase group size {}\n",
+                start_idx,
+                end_idx,
+                erase_group_size
+            );
+            return Err(SdmmcError::EINVAL);
+        }
+
+        let mut timeout_ms: u32 = match erase_type {
+            EmmcEraseType::Discard | EmmcEraseType::Trim | EmmcEraseType::SecureTrim
+                if ext_csd.erase_group_def || ext_csd.revision >= 6 =>
+            {
+                ext_csd.trim_timeout_ms
+            }
+            _ if ext_csd.erase_group_def => ext_csd.erase_timeout_ms,
+            _ => 0,
+        };
+        if timeout_ms == 0 {
+            timeout_ms = EMMC_DEFAULT_ERASE_TIMEOUT_MS;
+        }
+        match erase_type {
+            EmmcEraseType::SecureErase => timeout_ms *= (ext_csd.sec_erase_mult as u32).max(1),
+            EmmcEraseType::SecureTrim => timeout_ms *= (ext_csd.sec_trim_mult as u32).max(1),
+            _ => {}
+        }
+
+        let group_count: u64 = end_idx / erase_group_size - start_idx / erase_group_size + 1;
+
+        Ok((timeout_ms as u64 * group_count).min(u32::MAX as u64) as u32)
+    }
+
+    pub fn print_info(&self) {
+        const LABEL_WIDTH: usize = 20;
+        const DATA_WIDTH: usize = 25;
+
+        info!("\n\n╔═════════════════════════════════════════════════╗");
+        info!("║ EMMC INFORMATION                                ║");
+        info!("╠═════════════════════════════════════════════════╣");
+        info!(
+            "║ {:<label_width$}: {:<data_width$} ║",
+            "Manufacturer ID",
+            self.manufacture_info.manufacturer_id,
+            label_width = LABEL_WIDTH,
+            data_width = DATA_WIDTH,
+        );
+        info!(
+            "║ {:<label_width$}: {:<data_width$} ║",
+            "OEM ID",
+            self.manufacture_info.oem_id,
+            label_width = LABEL_WIDTH,
+            data_width = DATA_WIDTH,
+        );
+        info!(
+            "║ {:<label_width$}: {:<data_width$} ║",
+            "Product Name",
+            core::str::from_utf8(&self.manufacture_info.product_name).unwrap_or("??????"),
+            label_width = LABEL_WIDTH,
+            data_width = DATA_WIDTH,
+        );
+        info!(
+            "║ {:<label_width$}: {:<data_width$} ║",
+            "Product Revision",
+            self.manufacture_info.product_revision,
+            label_width = LABEL_WIDTH,
+            data_width = DATA_WIDTH,
+        );
+        info!(
+            "║ {:<label_width$}: {:<data_width$} ║",
+            "Serial Number",
+            self.manufacture_info.serial_number,
+            label_width = LABEL_WIDTH,
+            data_width = DATA_WIDTH,
+        );
+        info!(
+            "║ {:<label_width$}: {:<data_width$}{:padding$} ║",
+            "Manufacturing Date",
+            &format_args!(
+                "{:4}-{:02}",
+                self.manufacture_info.manufacturing_date.0,
+                self.manufacture_info.manufacturing_date.1
+            ),
+            "",
+            label_width = LABEL_WIDTH,
+            data_width = DATA_WIDTH,
+            padding = DATA_WIDTH - 7
+        );
+
+        print_card_capacity(self.capacity(), LABEL_WIDTH, DATA_WIDTH);
+
+        if let Some(ref ext_csd) = self.ext_csd {
+            info!(
+                "║ {:<label_width$}: {:<data_width$} ║",
+                "EXT_CSD Revision",
+                ext_csd.revision,
+                label_width = LABEL_WIDTH,
+                data_width = DATA_WIDTH,
+            );
+        }
+
+        info!("╚═════════════════════════════════════════════════╝\n");
+    }
+
+    /// Capacity in bytes
+    /// Sector mode devices report their capacity in EXT_CSD only, the CSD capacity is meaningless for them
+    pub(crate) fn capacity(&self) -> u64 {
+        match self.ext_csd {
+            Some(ref ext_csd) if self.sector_mode && ext_csd.sector_count != 0 => {
+                ext_csd.sector_count as u64 * 512
+            }
+            _ => self.card_specific_data.card_capacity,
+        }
+    }
+
+    pub fn emmc_info(&self) -> CardInfo {
+        CardInfo {
+            card_id: self.card_id,
+            card_capacity: self.capacity(),
+            card_state: self.card_state.clone(),
+        }
+    }
+}
+
+/// The eMMC CID register is slightly different from sdcard's one
+/// OEM id is only 8 bits and the product name is 6 bytes long
+#[allow(dead_code)]
+#[derive(Debug)]
+pub(crate) struct EmmcCid {
+    manufacturer_id: u8,
+    card_bga: u8,
+    oem_id: u8,
+    product_name: [u8; 6],
+    product_revision: u8,
+    serial_number: u32,
+    manufacturing_date: (u32, u8), // (year, month)
+}
+
+impl EmmcCid {
+    pub fn new(cid: [u32; 4]) -> EmmcCid {
+        let cid_combined: u128 = ((cid[0] as u128) << 96)
+            | ((cid[1] as u128) << 64)
+            | ((cid[2] as u128) << 32)
+            | (cid[3] as u128);
+
+        let manufacturer_id = ((cid_combined >> 120) & 0xFF) as u8;
+        // 0x0: removable device, 0x1: BGA (soldered), 0x2: POP
+        let card_bga = ((cid_combined >> 112) & 0x3) as u8;
+        let oem_id = ((cid_combined >> 104) & 0xFF) as u8;
+
+        let mut product_name: [u8; 6] = [0; 6];
+        for (i, byte) in product_name.iter_mut().enumerate() {
+            *byte = ((cid_combined >> (96 - 8 * i)) & 0xFF) as u8;
+        }
+
+        let product_revision: u8 = ((cid_combined >> 48) & 0xFF) as u8;
+        let serial_number: u32 = ((cid_combined >> 16) & 0xFFFFFFFF) as u32;
+
+        // The year field is an offset from 1997, devices following eMMC 4.41 and later may
+        // use 2013 as the base instead, which can only be told from EXT_CSD_REV
+        let month: u8 = ((cid_combined >> 12) & 0x0F) as u8;
+        let year: u32 = ((cid_combined >> 8) & 0x0F) as u32 + 1997;
+
+        EmmcCid {
+            manufacturer_id,
+            card_bga,
+            oem_id,
+            product_name,
+            product_revision,
+            serial_number,
+            manufacturing_date: (year, month),
+        }
+    }
+
+    /// From Linux mmc_decode_cid(), devices with EXT_CSD_REV >= 5 count year from 2013
+    /// when the year field is smaller than 13
+    pub(crate) fn adjust_manufacturing_year(&mut self, ext_csd_rev: u8) {
+        if ext_csd_rev >= 5 && self.manufacturing_date.0 < 2010 {
+            self.manufacturing_date.0 += 16;
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub(crate) struct EmmcCsd {
+    csd_structure: u8,
+    /// MMCA system specification version, 4 for eMMC 4.x and later
+    pub(crate) spec_version: u8,
+    /// Zero if the device is larger than 2GB, check SEC_COUNT in EXT_CSD for those
+    card_capacity: u64,
+    max_read_block_len: u16,
+    pub(crate) max_write_block_len: u16,
+    /// Erase group size in number of write blocks
+    pub(crate) erase_group_size: u32,
+    /// Write protect group size in number of erase groups
+    pub(crate) write_protect_group_size: u32,
+    /// Write protect groups are supported
+    pub(crate) write_protect_group_enable: bool,
+    /// The whole device is write protected until the bit is cleared
+    tmp_write_protect: bool,
+    /// The whole device is write protected forever
+    perm_write_protect: bool,
+}
+
+impl EmmcCsd {
+    pub fn new(csd: [u32; 4]) -> EmmcCsd {
+        let csd_combined: u128 = ((csd[0] as u128) << 96)
+            | ((csd[1] as u128) << 64)
+            | ((csd[2] as u128) << 32)
+            | (csd[3] as u128);
+
+        let csd_structure: u8 = ((csd_combined >> 126) & 0x3) as u8; // Bits 126–127
+        let spec_version: u8 = ((csd_combined >> 122) & 0xF) as u8; // Bits 122–125
+
+        let read_bl_len: u16 = ((csd_combined >> 80) & 0xF) as u16; // Bits 80–83
+        let write_bl_len: u16 = ((csd_combined >> 22) & 0xF) as u16; // Bits 22–25
+
+        let c_size: u64 = ((csd_combined >> 62) & 0xFFF) as u64; // Bits 62–73
+        let c_size_mult: u64 = ((csd_combined >> 47) & 0x7) as u64; // Bits 47–49
+
+        // A c_size of 0xFFF means the device is sector addressed and larger than 2GB
+        let card_capacity: u64 = if c_size == 0xFFF {
+            0
+        } else {
+            (c_size + 1) * (1 << (c_size_mult + 2)) * (1 << read_bl_len)
+        };
+
+        let erase_group_size: u32 = ((csd_combined >> 42) & 0x1F) as u32 + 1; // Bits 42–46
+        let erase_group_mult: u32 = ((csd_combined >> 37) & 0x1F) as u32 + 1; // Bits 37–41
+        let write_protect_group_size: u32 = ((csd_combined >> 32) & 0x1F) as u32 + 1; // Bits 32–36
+        let write_protect_group_enable: bool = (csd_combined >> 31) & 0x1 != 0; // Bit 31
+        let perm_write_protect: bool = (csd_combined >> 13) & 0x1 != 0; // Bit 13
+        let tmp_write_protect: bool = (csd_combined >> 12) & 0x1 != 0; // Bit 12
+
+        EmmcCsd {
+            csd_structure,
+            spec_version,
+            card_capacity,
+            max_read_block_len: 1 << read_bl_len,
+            max_write_block_len: 1 << write_bl_len,
+            erase_group_size: erase_group_size * erase_group_mult,
+            write_protect_group_size,
+            write_protect_group_enable,
+            tmp_write_protect,
+            perm_write_protect,
+        }
+    }
+
+    pub(crate) fn write_protected(&self) -> bool {
+        self.tmp_write_protect || self.perm_write_protect
+    }
+}
+
+/// Linux falls back to this value when GENERIC_CMD6_TIME is not provided by the device
+pub(crate) const EMMC_DEFAULT_CMD6_TIMEOUT_MS: u32 = 500;
+
+/// Erase timeout of one erase group used when EXT_CSD does not provide one
+/// The legacy timeout calculated from the CSD is not supported
+const EMMC_DEFAULT_ERASE_TIMEOUT_MS: u32 = 1_000;
+
+/// Typed view of the 512 bytes EXT_CSD register, only the fields used by the driver are extracted
+#[allow(dead_code)]
+#[derive(Debug)]
+pub(crate) struct ExtCsd {
+    /// EXT_CSD_REV, 5 for eMMC 4.41, 6 for eMMC 4.5, 7 for eMMC 5.0 and 8 for eMMC 5.1
+    pub(crate) revision: u8,
+    /// Number of 512 bytes sectors in the user data area
+    pub(crate) sector_count: u32,
+    /// Speed modes supported by the device at 1.8V/3.3V signal voltage
+    pub(crate) device_type: EMmcCapability,
+    /// Size of each boot partition in bytes
+    pub(crate) boot_partition_size: u64,
+    /// Size of the RPMB partition in bytes
+    pub(crate) rpmb_partition_size: u64,
+    /// Size of the four general purpose partitions in bytes
+    pub(crate) gp_partition_size: [u64; 4],
+    /// Volatile cache size in KiB, zero if the device does not have a cache
+    pub(crate) cache_size: u32,
+    /// Whether the high capacity erase group size is in use
+    pub(crate) erase_group_def: bool,
+    /// High capacity erase group size in number of 512 bytes sectors
+    pub(crate) hc_erase_group_size: u32,
+    /// High capacity write protect group size in number of erase groups
+    pub(crate) hc_wp_group_size: u32,
+    /// Timeout of erasing one high capacity erase group
+    pub(crate) erase_timeout_ms: u32,
+    /// Timeout of trimming one erase group
+    pub(crate) trim_timeout_ms: u32,
+    /// Multiplier of the erase timeout for secure erase
+    pub(crate) sec_erase_mult: u8,
+    /// Multiplier of the erase timeout for secure trim
+    pub(crate) sec_trim_mult: u8,
+    pub(crate) sec_feature_support: u8,
+    pub(crate) bkops_support: bool,
+    pub(crate) bkops_en: u8,
+    pub(crate) bkops_status: u8,
+    pub(crate) hpi_features: u8,
+    /// Timeout of the eMMC leaving the operation interrupted by HPI
+    pub(crate) out_of_interrupt_time_ms: u32,
+    /// Number of sectors programmed by the last CMD25 interrupted through HPI
+    pub(crate) correctly_prg_sectors_num: u32,
+    /// Timeout of the eMMC entering or leaving sleep state through CMD5
+    pub(crate) sleep_awake_timeout_ms: u32,
+    /// Timeout of POWER_OFF_LONG notification
+    pub(crate) power_off_long_time_ms: u32,
+    pub(crate) cmdq_support: bool,
+    /// Number of tasks the command queue holds
+    pub(crate) cmdq_depth: u8,
+    /// Reliable write of any size is enabled through WR_REL_PARAM
+    pub(crate) enhanced_reliable_write: bool,
+    /// Reliable write sector count of the legacy reliable write
+    pub(crate) rel_wr_sec_c: u8,
+    /// Maximum number of entries in one packed write, zero if packed commands are not supported
+    pub(crate) max_packed_writes: u8,
+    /// Field firmware update is supported and not disabled by FW_CONFIG
+    pub(crate) ffu_support: bool,
+    /// Address the firmware image is written to in FFU mode
+    pub(crate) ffu_arg: u32,
+    /// The firmware can be installed through MODE_OPERATION_CODES without a reset
+    pub(crate) ffu_install_supported: bool,
+    pub(crate) operation_code_timeout_ms: u32,
+    pub(crate) ffu_status: u8,
+    /// Number of firmware sectors programmed correctly in the last FFU
+    pub(crate) fw_sectors_programmed: u32,
+    /// 512 bytes or 4KiB
+    pub(crate) data_sector_size: u32,
+    pub(crate) partition_config: u8,
+    pub(crate) partition_support: u8,
+    /// The partition layout has been written and can not be changed any more
+    pub(crate) partition_setting_completed: bool,
+    /// Most units the enhanced areas can take, check EmmcPartitionLayout
+    pub(crate) max_enh_size_mult: u32,
+    pub(crate) boot_bus_conditions: u8,
+    pub(crate) boot_wp: u8,
+    /// Protection of the boot partitions, two bits each with boot0 in the lowest bits
+    /// 0 is not protected, 1 is power on protected and 2 is permanently protected
+    pub(crate) boot_wp_status: u8,
+    pub(crate) user_wp: u8,
+    pub(crate) partition_switch_time_ms: u32,
+    pub(crate) generic_cmd6_time_ms: u32,
+    pub(crate) pre_eol_info: u8,
+    pub(crate) life_time_est_typ_a: u8,
+    pub(crate) life_time_est_typ_b: u8,
+    /// Whether the device supports HS400 Enhanced Strobe
+    pub(crate) strobe_support: bool,
+}
+
+impl ExtCsd {
+    /// Timeout of the eMMC leaving the operation interrupted by HPI
+    pub(crate) fn hpi_timeout_ms(&self) -> u32 {
+        if self.out_of_interrupt_time_ms != 0 {
+            self.out_of_interrupt_time_ms
+        } else {
+            self.generic_cmd6_time_ms
+        }
+    }
+
+    /// Compare the read only fields of two EXT_CSD, used to check if the EXT_CSD read
+    /// after changing the bus width is still intact, check mmc_compare_ext_csds() in Linux
+    pub(crate) fn read_only_fields_match(&self, other: &ExtCsd) -> bool {
+        self.revision == other.revision
+            && self.sector_count == other.sector_count
+            && self.device_type.0 == other.device_type.0
+            && self.boot_partition_size == other.boot_partition_size
+            && self.rpmb_partition_size == other.rpmb_partition_size
+            && self.hc_erase_group_size == other.hc_erase_group_size
+            && self.hc_wp_group_size == other.hc_wp_group_size
+    }
+
+    pub fn new(ext_csd: &[u8; EXT_CSD_SIZE]) -> Result<ExtCsd, SdmmcError> {
+        // Value bigger than 2 is reserved, the data we get is likely to be garbage
+        if ext_csd[EXT_CSD_STRUCTURE] > 2 {
+            return Err(SdmmcError::EINVAL);
+        }
+
+        let revision: u8 = ext_csd[EXT_CSD_REV];
+
+        // The sector count is stored in little endian
+        let sector_count: u32 = u32::from_le_bytes([
+            ext_csd[EXT_CSD_SEC_CNT],
+            ext_csd[EXT_CSD_SEC_CNT + 1],
+            ext_csd[EXT_CSD_SEC_CNT + 2],
+            ext_csd[EXT_CSD_SEC_CNT + 3],
+        ]);
+
+        // The 1.2V variants are ignored as none of the host supports 1.2V signal voltage yet
+        let card_type: u8 = ext_csd[EXT_CSD_CARD_TYPE];
+        let mut device_type: EMmcCapability = EMmcCapability(MMC_TIMING_LEGACY);
+        // Hosts run HS timing at 52MHz, devices only supporting 26MHz stay in legacy timing
+        if card_type & EXT_CSD_CARD_TYPE_HS_52 != 0 {
+            device_type.insert(EMmcCapability(MMC_TIMING_MMC_HS));
+        }
+        // DDR_1_8V covers both 1.8V and 3V signal voltage
+        if card_type & EXT_CSD_CARD_TYPE_DDR_1_8V != 0 {
+            device_type.insert(EMmcCapability(MMC_TIMING_MMC_DDR52));
+        }
+        // HS200 is introduced in eMMC 4.5 and HS400 is introduced in eMMC 5.0
+        if revision >= 6 && card_type & EXT_CSD_CARD_TYPE_HS200_1_8V != 0 {
+            device_type.insert(EMmcCapability(MMC_TIMING_MMC_HS200));
+        }
+        if revision >= 7 && card_type & EXT_CSD_CARD_TYPE_HS400_1_8V != 0 {
+            device_type.insert(EMmcCapability(MMC_TIMING_MMC_HS400));
+        }
+
+        // Both boot and RPMB partition size are in multiple of 128KB
+        let boot_partition_size: u64 = ext_csd[EXT_CSD_BOOT_MULT] as u64 * 128 * 1024;
+        let rpmb_partition_size: u64 = ext_csd[EXT_CSD_RPMB_MULT] as u64 * 128 * 1024;
+
+        let erase_group_def: bool = ext_csd[EXT_CSD_ERASE_GROUP_DEF] & 0x1 != 0;
+        // HC_ERASE_GRP_SIZE is in unit of 512KB
+        let hc_erase_group_size: u32 = ext_csd[EXT_CSD_HC_ERASE_GRP_SIZE] as u32 * 1024;
+        let hc_wp_group_size: u32 = ext_csd[EXT_CSD_HC_WP_GRP_SIZE] as u32;
+        // ERASE_TIMEOUT_MULT and TRIM_MULT are in unit of 300ms
+        let erase_timeout_ms: u32 = ext_csd[EXT_CSD_ERASE_TIMEOUT_MULT] as u32 * 300;
+        let trim_timeout_ms: u32 = ext_csd[EXT_CSD_TRIM_MULT] as u32 * 300;
+
+        // GP_SIZE_MULT_X_Y are in unit of write protect group
+        let mut gp_partition_size: [u64; 4] = [0; 4];
+        for (i, size) in gp_partition_size.iter_mut().enumerate() {
+            let index: usize = EXT_CSD_GP_SIZE_MULT + i * 3;
+            let mult: u64 = ((ext_csd[index + 2] as u64) << 16)
+                | ((ext_csd[index + 1] as u64) << 8)
+                | (ext_csd[index] as u64);
+            *size = mult * hc_wp_group_size as u64 * hc_erase_group_size as u64 * 512;
+        }
+
+        // S_A_TIMEOUT is introduced in eMMC 4.3, the timeout is 100ns * 2^S_A_TIMEOUT
+        let mut sleep_awake_timeout_ms: u32 = 0;
+        if revision >= 3 && ext_csd[EXT_CSD_S_A_TIMEOUT] <= 0x17 {
+            sleep_awake_timeout_ms = (1u32 << ext_csd[EXT_CSD_S_A_TIMEOUT]).div_ceil(10_000);
+        }
+
+        // Fields introduced in eMMC 4.41
+        let mut bkops_support: bool = false;
+        let mut bkops_en: u8 = 0;
+        let mut bkops_status: u8 = 0;
+        let mut hpi_features: u8 = 0;
+        let mut out_of_interrupt_time_ms: u32 = 0;
+        let mut correctly_prg_sectors_num: u32 = 0;
+        let mut enhanced_reliable_write: bool = false;
+        if revision >= 5 {
+            enhanced_reliable_write = ext_csd[EXT_CSD_WR_REL_PARAM] & EXT_CSD_EN_REL_WR != 0;
+            bkops_support = ext_csd[EXT_CSD_BKOPS_SUPPORT] & 0x1 != 0;
+            bkops_en = ext_csd[EXT_CSD_BKOPS_EN];
+            bkops_status = ext_csd[EXT_CSD_BKOPS_STATUS];
+            hpi_features = ext_csd[EXT_CSD_HPI_FEATURES];
+            // OUT_OF_INTERRUPT_TIME is in unit of 10ms
+            out_of_interrupt_time_ms = ext_csd[EXT_CSD_OUT_OF_INTERRUPT_TIME] as u32 * 10;
+            correctly_prg_sectors_num = u32::from_le_bytes([
+                ext_csd[EXT_CSD_CORRECTLY_PRG_SECTORS_NUM],
+                ext_csd[EXT_CSD_CORRECTLY_PRG_SECTORS_NUM + 1],
+                ext_csd[EXT_CSD_CORRECTLY_PRG_SECTORS_NUM + 2],
+                ext_csd[EXT_CSD_CORRECTLY_PRG_SECTORS_NUM + 3],
+            ]);
+        }
+
+        // Fields introduced in eMMC 4.5
+        let mut cache_size: u32 = 0;
+        let mut generic_cmd6_time_ms: u32 = 0;
+        let mut power_off_long_time_ms: u32 = 0;
+        let mut max_packed_writes: u8 = 0;
+        if revision >= 6 {
+            max_packed_writes = ext_csd[EXT_CSD_MAX_PACKED_WRITES];
+            cache_size = u32::from_le_bytes([
+                ext_csd[EXT_CSD_CACHE_SIZE],
+                ext_csd[EXT_CSD_CACHE_SIZE + 1],
+                ext_csd[EXT_CSD_CACHE_SIZE + 2],
+                ext_csd[EXT_CSD_CACHE_SIZE + 3],
+            ]);
+            // GENERIC_CMD6_TIME is in unit of 10ms
+            generic_cmd6_time_ms = ext_csd[EXT_CSD_GENERIC_CMD6_TIME] as u32 * 10;
+            // POWER_OFF_LONG_TIME is in unit of 10ms
+            power_off_long_time_ms = ext_csd[EXT_CSD_POWER_OFF_LONG_TIME] as u32 * 10;
+        }
+        if generic_cmd6_time_ms == 0 {
+            generic_cmd6_time_ms = EMMC_DEFAULT_CMD6_TIMEOUT_MS;
+        }
+
+        // Fields introduced in eMMC 5.0
+        let mut pre_eol_info: u8 = 0;
+        let mut life_time_est_typ_a: u8 = 0;
+        let mut life_time_est_typ_b: u8 = 0;
+        if revision >= 7 {
+            pre_eol_info = ext_csd[EXT_CSD_PRE_EOL_INFO];
+            life_time_est_typ_a = ext_csd[EXT_CSD_DEVICE_LIFE_TIME_EST_TYP_A];
+            life_time_est_typ_b = ext_csd[EXT_CSD_DEVICE_LIFE_TIME_EST_TYP_B];
+        }
+
+        // Field firmware update is introduced in eMMC 5.0
+        let mut ffu_support: bool = false;
+        let mut ffu_arg: u32 = 0;
+        let mut ffu_install_supported: bool = false;
+        let mut operation_code_timeout_ms: u32 = 0;
+        let mut ffu_status: u8 = 0;
+        let mut fw_sectors_programmed: u32 = 0;
+        if revision >= 7 {
+            ffu_support = ext_csd[EXT_CSD_SUPPORTED_MODES] & EXT_CSD_SUPPORTED_MODE_FFU != 0
+                && ext_csd[EXT_CSD_FW_CONFIG] & EXT_CSD_FW_UPDATE_DISABLE == 0;
+            ffu_arg = u32::from_le_bytes([
+                ext_csd[EXT_CSD_FFU_ARG],
+                ext_csd[EXT_CSD_FFU_ARG + 1],
+                ext_csd[EXT_CSD_FFU_ARG + 2],
+                ext_csd[EXT_CSD_FFU_ARG + 3],
+            ]);
+            ffu_install_supported =
+                ext_csd[EXT_CSD_FFU_FEATURES] & EXT_CSD_FFU_MODE_OPERATION_CODES != 0;
+            // OPERATION_CODE_TIMEOUT is 100us * 2^OPERATION_CODE_TIMEOUT
+            if ext_csd[EXT_CSD_OPERATION_CODE_TIMEOUT] <= 0x17 {
+                operation_code_timeout_ms =
+                    (1u32 << ext_csd[EXT_CSD_OPERATION_CODE_TIMEOUT]).div_ceil(10);
+            }
+            ffu_status = ext_csd[EXT_CSD_FFU_STATUS];
+            fw_sectors_programmed = u32::from_le_bytes([
+                ext_csd[EXT_CSD_NUM_OF_FW_SEC_PROG],
+                ext_csd[EXT_CSD_NUM_OF_FW_SEC_PROG + 1],
+                ext_csd[EXT_CSD_NUM_OF_FW_SEC_PROG + 2],
+                ext_csd[EXT_CSD_NUM_OF_FW_SEC_PROG + 3],
+            ]);
+        }
+        let data_sector_size: u32 = if ext_csd[EXT_CSD_DATA_SECTOR_SIZE] & 0x1 != 0 {
+            4096
+        } else {
+            512
+        };
+
+        // Enhanced strobe and command queue are introduced in eMMC 5.1
+        let strobe_support: bool = revision >= 8 && ext_csd[EXT_CSD_STROBE_SUPPORT] & 0x1 != 0;
+        let cmdq_support: bool = revision >= 8 && ext_csd[EXT_CSD_CMDQ_SUPPORT] & 0x1 != 0;
+        // CMDQ_DEPTH is the number of tasks minus one
+        let cmdq_depth: u8 = (ext_csd[EXT_CSD_CMDQ_DEPTH] & 0x1F) + 1;
+
+        Ok(ExtCsd {
+            revision,
+            sector_count,
+            device_type,
+            boot_partition_size,
+            rpmb_partition_size,
+            gp_partition_size,
+            cache_size,
+            erase_group_def,
+            hc_erase_group_size,
+            hc_wp_group_size,
+            erase_timeout_ms,
+            trim_timeout_ms,
+            sec_erase_mult: ext_csd[EXT_CSD_SEC_ERASE_MULT],
+            sec_trim_mult: ext_csd[EXT_CSD_SEC_TRIM_MULT],
+            sec_feature_support: ext_csd[EXT_CSD_SEC_FEATURE_SUPPORT],
+            bkops_support,
+            bkops_en,
+            bkops_status,
+            hpi_features,
+            out_of_interrupt_time_ms,
+            correctly_prg_sectors_num,
+            sleep_awake_timeout_ms,
+            power_off_long_time_ms,
+            cmdq_support,
+            cmdq_depth,
+            enhanced_reliable_write,
+            rel_wr_sec_c: ext_csd[EXT_CSD_REL_WR_SEC_C],
+            max_packed_writes,
+            ffu_support,
+            ffu_arg,
+            ffu_install_supported,
+            operation_code_timeout_ms,
+            ffu_status,
+            fw_sectors_programmed,
+            data_sector_size,
+            partition_config: ext_csd[EXT_CSD_PART_CONFIG],
+            partition_support: ext_csd[EXT_CSD_PARTITION_SUPPORT],
+            partition_setting_completed: ext_csd[EXT_CSD_PARTITION_SETTING_COMPLETED]
+                & EXT_CSD_PARTITION_SETTING_COMPLETE
+                != 0,
+            max_enh_size_mult: u32::from_le_bytes([
+                ext_csd[EXT_CSD_MAX_ENH_SIZE_MULT],
+                ext_csd[EXT_CSD_MAX_ENH_SIZE_MULT + 1],
+                ext_csd[EXT_CSD_MAX_ENH_SIZE_MULT + 2],
+                0,
+            ]),
+            boot_bus_conditions: ext_csd[EXT_CSD_BOOT_BUS_CONDITIONS],
+            boot_wp: ext_csd[EXT_CSD_BOOT_WP],
+            boot_wp_status: ext_csd[EXT_CSD_BOOT_WP_STATUS],
+            user_wp: ext_csd[EXT_CSD_USER_WP],
+            // PARTITION_SWITCH_TIME is in unit of 10ms
+            partition_switch_time_ms: ext_csd[EXT_CSD_PART_SWITCH_TIME] as u32 * 10,
+            generic_cmd6_time_ms,
+            pre_eol_info,
+            life_time_est_typ_a,
+            life_time_est_typ_b,
+            strobe_support,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::sdmmc::mmc_struct::MmcTiming;
+
+    fn csd_words(csd: u128) -> [u32; 4] {
+        [
+            (csd >> 96) as u32,
+            (csd >> 64) as u32,
+            (csd >> 32) as u32,
+            csd as u32,
+        ]
+    }
+
+    /// CSD of an eMMC 4.x, erase groups of 32 * 32 write blocks and write protect groups enabled
+    fn csd(c_size: u128, c_size_mult: u128, read_bl_len: u128) -> [u32; 4] {
+        csd_words(
+            (3 << 126)
+                | (4 << 122)
+                | (read_bl_len << 80)
+                | (c_size << 62)
+                | (c_size_mult << 47)
+                | (0x1F << 42)
+                | (0x1F << 37)
+                | (1 << 31)
+                | (9 << 22),
+        )
+    }
+
+    /// EXT_CSD of an 8GB eMMC 5.1, erase groups of 512KB and write protect groups of 4MB
+    pub(crate) fn ext_csd_raw() -> [u8; EXT_CSD_SIZE] {
+        let mut raw: [u8; EXT_CSD_SIZE] = [0; EXT_CSD_SIZE];
+
+        raw[EXT_CSD_STRUCTURE] = 2;
+        raw[EXT_CSD_REV] = 8;
+        raw[EXT_CSD_SEC_CNT..EXT_CSD_SEC_CNT + 4].copy_from_slice(&0x0100_0000u32.to_le_bytes());
+        raw[EXT_CSD_CARD_TYPE] = EXT_CSD_CARD_TYPE_HS_52 | EXT_CSD_CARD_TYPE_HS200_1_8V;
+        raw[EXT_CSD_BOOT_MULT] = 32;
+        raw[EXT_CSD_RPMB_MULT] = 4;
+        raw[EXT_CSD_ERASE_GROUP_DEF] = 1;
+        raw[EXT_CSD_HC_ERASE_GRP_SIZE] = 1;
+        raw[EXT_CSD_HC_WP_GRP_SIZE] = 8;
+        raw[EXT_CSD_ERASE_TIMEOUT_MULT] = 2;
+        raw[EXT_CSD_TRIM_MULT] = 1;
+        raw[EXT_CSD_SEC_ERASE_MULT] = 3;
+        raw[EXT_CSD_SEC_TRIM_MULT] = 4;
+        raw[EXT_CSD_SEC_FEATURE_SUPPORT] = EXT_CSD_SEC_ER_EN | EXT_CSD_SEC_GB_CL_EN;
+        raw[EXT_CSD_MAX_PACKED_WRITES] = 8;
+        raw[EXT_CSD_CMDQ_SUPPORT] = 1;
+        raw[EXT_CSD_CMDQ_DEPTH] = 31;
+        raw[EXT_CSD_PARTITION_SUPPORT] =
+            EXT_CSD_PART_SUPPORT_PART_EN | EXT_CSD_PART_SUPPORT_ENH_ATTRIBUTE_EN;
+        raw[EXT_CSD_MAX_ENH_SIZE_MULT] = 16;
+        raw[EXT_CSD_GP_SIZE_MULT] = 2;
+
+        raw
+    }
+
+    /// A 1GB byte addressed eMMC or an 8GB sector addressed eMMC
+    pub(crate) fn emmc_device(sector_mode: bool, ext_csd: Option<ExtCsd>) -> EMmc {
+        let csd: [u32; 4] = if sector_mode {
+            csd(0xFFF, 7, 9)
+        } else {
+            csd(2047, 7, 10)
+        };
+
+        EMmc {
+            card_id: 0,
+            manufacture_info: EmmcCid::new([0; 4]),
+            card_specific_data: EmmcCsd::new(csd),
+            relative_card_addr: EMMC_DEFAULT_RELATIVE_ADDR,
+            card_state: MmcState {
+                timing: MmcTiming::Legacy,
+                bus_width: MmcBusWidth::Width1,
+            },
+            method: BlockTransmissionMode::SetBlockCount,
+            sector_mode,
+            ext_csd,
+            current_partition: EmmcPartition::UserData,
+            cache_enabled: false,
+            hpi_enabled: false,
+            bkops_running: false,
+            cmdq_enabled: false,
+            power_off_notify: false,
+            sleeping: false,
+        }
+    }
+
+    fn emmc_with_ext_csd(sector_mode: bool) -> EMmc {
+        emmc_device(sector_mode, Some(ExtCsd::new(&ext_csd_raw()).unwrap()))
+    }
+
+    #[test]
+    fn csd_capacity_of_byte_mode_device() {
+        let emmc: EMmc = emmc_device(false, None);
+
+        assert_eq!(emmc.capacity(), 1 << 30);
+        assert_eq!(emmc.erase_group_size(), 1024);
+    }
+
+    #[test]
+    fn ext_csd_fields() {
+        let ext_csd: ExtCsd = ExtCsd::new(&ext_csd_raw()).unwrap();
+
+        assert_eq!(ext_csd.revision, 8);
+        assert_eq!(ext_csd.sector_count, 0x0100_0000);
+        assert!(
+            ext_csd
+                .device_type
+                .contains(EMmcCapability(MMC_TIMING_MMC_HS))
+        );
+        assert!(
+            ext_csd
+                .device_type
+                .contains(EMmcCapability(MMC_TIMING_MMC_HS200))
+        );
+        assert!(
+            !ext_csd
+                .device_type
+                .contains(EMmcCapability(MMC_TIMING_MMC_HS400))
+        );
+        assert_eq!(ext_csd.boot_partition_size, 4 << 20);
+        assert_eq!(ext_csd.rpmb_partition_size, 512 << 10);
+        assert_eq!(ext_csd.hc_erase_group_size, 1024);
+        assert_eq!(ext_csd.hc_wp_group_size, 8);
+        assert_eq!(ext_csd.gp_partition_size, [8 << 20, 0, 0, 0]);
+        assert_eq!(ext_csd.generic_cmd6_time_ms, EMMC_DEFAULT_CMD6_TIMEOUT_MS);
+    }
+
+    #[test]
+    fn ext_csd_ignores_fields_of_newer_revisions() {
+        let mut raw: [u8; EXT_CSD_SIZE] = ext_csd_raw();
+        raw[EXT_CSD_REV] = 5;
+
+        let ext_csd: ExtCsd = ExtCsd::new(&raw).unwrap();
+
+        assert!(
+            !ext_csd
+                .device_type
+                .contains(EMmcCapability(MMC_TIMING_MMC_HS200))
+        );
+        assert_eq!(ext_csd.max_packed_writes, 0);
+        assert!(!ext_csd.cmdq_support);
+    }
+
+    #[test]
+    fn ext_csd_rejects_reserved_structure() {
+        let mut raw: [u8; EXT_CSD_SIZE] = ext_csd_raw();
+        raw[EXT_CSD_STRUCTURE] = 3;
+
+        assert!(matches!(ExtCsd::new(&raw), Err(SdmmcError::EINVAL)));
+    }
+
+    #[test]
+    fn block_range_of_selected_partition() {
+        let mut emmc: EMmc = emmc_with_ext_csd(true);
+
+        assert!(emmc.check_block_range(0x00FF_FFF0, 16).is_ok());
+        assert!(matches!(
+            emmc.check_block_range(0x00FF_FFF0, 17),
+            Err(SdmmcError::EINVAL)
+        ));
+        assert!(matches!(
+            emmc.check_block_range(u64::MAX, 1),
+            Err(SdmmcError::EINVAL)
+        ));
+
+        emmc.current_partition = EmmcPartition::Boot1;
+        assert!(emmc.check_block_range(8191, 1).is_ok());
+        assert!(matches!(
+            emmc.check_block_range(8192, 1),
+            Err(SdmmcError::EINVAL)
+        ));
+
+        emmc.current_partition = EmmcPartition::GeneralPurpose2;
+        assert!(matches!(
+            emmc.check_block_range(0, 1),
+            Err(SdmmcError::EINVAL)
+        ));
+
+        emmc.current_partition = EmmcPartition::Rpmb;
+        assert!(matches!(
+            emmc.check_block_range(0, 1),
+            Err(SdmmcError::EINVAL)
+        ));
+    }
+
+    #[test]
+    fn block_range_without_ext_csd() {
+        let emmc: EMmc = emmc_device(false, None);
+        assert!(emmc.check_block_range((1 << 21) - 1, 1).is_ok());
+        assert!(matches!(
+            emmc.check_block_range(1 << 21, 1),
+            Err(SdmmcError::EINVAL)
+        ));
+
+        let emmc: EMmc = emmc_device(true, None);
+        assert!(matches!(
+            emmc.check_block_range(0, 1),
+            Err(SdmmcError::EUNDEFINED)
+        ));
+    }
+
+    #[test]
+    fn ext_csd_erase_fields() {
+        let ext_csd: ExtCsd = ExtCsd::new(&ext_csd_raw()).unwrap();
+
+        assert_eq!(ext_csd.erase_timeout_ms, 600);
+        assert_eq!(ext_csd.trim_timeout_ms, 300);
+        assert_eq!(ext_csd.sec_erase_mult, 3);
+        assert_eq!(ext_csd.sec_trim_mult, 4);
+    }
+
+    #[test]
+    fn erase_timeout() {
+        let emmc: EMmc = emmc_with_ext_csd(true);
+
+        assert_eq!(
+            emmc.erase_timeout_ms(0, 2047, EmmcEraseType::Erase)
+                .unwrap(),
+            1200
+        );
+        assert_eq!(
+            emmc.erase_timeout_ms(1024, 2047, EmmcEraseType::SecureErase)
+                .unwrap(),
+            1800
+        );
+        // Trim works on write blocks, the erase groups touched decide the timeout
+        assert_eq!(
+            emmc.erase_timeout_ms(1000, 1030, EmmcEraseType::SecureTrim)
+                .unwrap(),
+            2400
+        );
+        assert_eq!(
+            emmc.erase_timeout_ms(5, 10, EmmcEraseType::Trim).unwrap(),
+            300
+        );
+    }
+
+    #[test]
+    fn erase_timeout_rejects_invalid_ranges() {
+        let emmc: EMmc = emmc_with_ext_csd(true);
+
+        assert!(matches!(
+            emmc.erase_timeout_ms(1, 1024, EmmcEraseType::Erase),
+            Err(SdmmcError::EINVAL)
+        ));
+        assert!(matches!(
+            emmc.erase_timeout_ms(10, 5, EmmcEraseType::Trim),
+            Err(SdmmcError::EINVAL)
+        ));
+        assert!(matches!(
+            emmc.erase_timeout_ms(0x0100_0000, 0x0100_0000, EmmcEraseType::Trim),
+            Err(SdmmcError::EINVAL)
+        ));
+
+        let mut raw: [u8; EXT_CSD_SIZE] = ext_csd_raw();
+        raw[EXT_CSD_SEC_FEATURE_SUPPORT] = 0;
+        let emmc: EMmc = emmc_device(true, Some(ExtCsd::new(&raw).unwrap()));
+        assert!(matches!(
+            emmc.erase_timeout_ms(5, 10, EmmcEraseType::Trim),
+            Err(SdmmcError::EUNSUPPORTEDCARD)
+        ));
+    }
+
+    #[test]
+    fn ext_csd_cmdq_fields() {
+        let ext_csd: ExtCsd = ExtCsd::new(&ext_csd_raw()).unwrap();
+
+        assert!(ext_csd.cmdq_support);
+        assert_eq!(ext_csd.cmdq_depth, 32);
+    }
+
+    #[test]
+    fn card_address_of_sector_mode_device() {
+        let emmc: EMmc = emmc_with_ext_csd(true);
+
+        assert_eq!(emmc.card_address(5).unwrap(), 5);
+        assert_eq!(emmc.card_address(u32::MAX as u64).unwrap(), u32::MAX);
+        assert!(matches!(
+            emmc.card_address(1 << 32),
+            Err(SdmmcError::EINVAL)
+        ));
+    }
+
+    #[test]
+    fn card_address_of_byte_mode_device() {
+        let emmc: EMmc = emmc_device(false, None);
+
+        assert_eq!(emmc.card_address(5).unwrap(), 5 * 512);
+        assert_eq!(
+            emmc.card_address((1 << 23) - 1).unwrap(),
+            ((1 << 23) - 1) * 512
+        );
+        assert!(matches!(
+            emmc.card_address(1 << 23),
+            Err(SdmmcError::EINVAL)
+        ));
+        assert!(matches!(
+            emmc.card_address(u64::MAX),
+            Err(SdmmcError::EINVAL)
+        ));
+    }
+
+    #[test]
+    fn ext_csd_packed_write_fields() {
+        let ext_csd: ExtCsd = ExtCsd::new(&ext_csd_raw()).unwrap();
+
+        assert_eq!(ext_csd.max_packed_writes, 8);
+    }
+
+    #[test]
+    fn packed_write_header_layout() {
+        let emmc: EMmc = emmc_with_ext_csd(true);
+        let entries: [PackedWriteEntry; 2] = [
+            PackedWriteEntry {
+                start_idx: 10,
+                blockcnt: 4,
+                reliable_write: false,
+            },
+            PackedWriteEntry {
+                start_idx: 100,
+                blockcnt: 2,
+                reliable_write: false,
+            },
+        ];
+        let mut header: [u8; PACKED_HEADER_SIZE] = [0xFF; PACKED_HEADER_SIZE];
+
+        assert_eq!(emmc.packed_write_header(&entries, &mut header).unwrap(), 7);
+
+        assert_eq!(
+            header[..8],
+            [PACKED_CMD_VERSION, PACKED_CMD_WRITE, 2, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(header[8..12], 4u32.to_le_bytes());
+        assert_eq!(header[12..16], 10u32.to_le_bytes());
+        assert_eq!(header[16..20], 2u32.to_le_bytes());
+        assert_eq!(header[20..24], 100u32.to_le_bytes());
+        assert!(header[24..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn packed_write_header_uses_byte_address_on_byte_mode_device() {
+        let emmc: EMmc = emmc_device(false, Some(ExtCsd::new(&ext_csd_raw()).unwrap()));
+        let entries: [PackedWriteEntry; 1] = [PackedWriteEntry {
+            start_idx: 10,
+            blockcnt: 1,
+            reliable_write: false,
+        }];
+        let mut header: [u8; PACKED_HEADER_SIZE] = [0; PACKED_HEADER_SIZE];
+
+        emmc.packed_write_header(&entries, &mut header).unwrap();
+
+        assert_eq!(header[12..16], (10u32 * 512).to_le_bytes());
+    }
+
+    #[test]
+    fn packed_write_header_rejects_bad_entries() {
+        let emmc: EMmc = emmc_with_ext_csd(true);
+        let mut header: [u8; PACKED_HEADER_SIZE] = [0; PACKED_HEADER_SIZE];
+
+        let empty: [PackedWriteEntry; 1] = [PackedWriteEntry {
+            start_idx: 0,
+            blockcnt: 0,
+            reliable_write: false,
+        }];
+        assert!(matches!(
+            emmc.packed_write_header(&empty, &mut header),
+            Err(SdmmcError::EINVAL)
+        ));
+
+        // CMD23 only carries 16 bits of block count
+        let oversized: [PackedWriteEntry; 2] = [
+            PackedWriteEntry {
+                start_idx: 0,
+                blockcnt: 0x8000,
+                reliable_write: false,
+            },
+            PackedWriteEntry {
+                start_idx: 0x8000,
+                blockcnt: 0x8000,
+                reliable_write: false,
+            },
+        ];
+        assert!(matches!(
+            emmc.packed_write_header(&oversized, &mut header),
+            Err(SdmmcError::EINVAL)
+        ));
+
+        assert!(matches!(
+            emmc.packed_write_header(&[], &mut header),
+            Err(SdmmcError::EINVAL)
+        ));
+    }
+
+    #[test]
+    fn csd_write_protect_group() {
+        let emmc: EMmc = emmc_device(false, None);
+
+        assert_eq!(emmc.card_specific_data.write_protect_group_size, 1);
+        assert!(!emmc.card_specific_data.write_protected());
+    }
+
+    #[test]
+    fn partition_layout_plan() {
+        let emmc: EMmc = emmc_with_ext_csd(true);
+        let layout: EmmcPartitionLayout = EmmcPartitionLayout {
+            gp_size_mult: [2, 0, 0, 0],
+            gp_enhanced: [true, false, false, false],
+            enh_start_sector: 8192,
+            enh_size_mult: 4,
+        };
+
+        assert_eq!(
+            emmc.check_partition_layout(&layout).unwrap(),
+            EmmcPartitionPlan {
+                unit_size: 4 << 20,
+                enhanced_units: 6,
+                max_enhanced_units: 16,
+                committed: false,
+            }
+        );
+    }
+
+    #[test]
+    fn partition_layout_rejects_invalid_layouts() {
+        let emmc: EMmc = emmc_with_ext_csd(true);
+        let layout: EmmcPartitionLayout = EmmcPartitionLayout {
+            enh_start_sector: 8192,
+            enh_size_mult: 17,
+            ..Default::default()
+        };
+        assert!(matches!(
+            emmc.check_partition_layout(&layout),
+            Err(SdmmcError::EINVAL)
+        ));
+
+        // The enhanced area must be aligned to the partition unit
+        let layout: EmmcPartitionLayout = EmmcPartitionLayout {
+            enh_start_sector: 4096,
+            enh_size_mult: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            emmc.check_partition_layout(&layout),
+            Err(SdmmcError::EINVAL)
+        ));
+
+        let mut raw: [u8; EXT_CSD_SIZE] = ext_csd_raw();
+        raw[EXT_CSD_PARTITION_SETTING_COMPLETED] = EXT_CSD_PARTITION_SETTING_COMPLETE;
+        let emmc: EMmc = emmc_device(true, Some(ExtCsd::new(&raw).unwrap()));
+        let layout: EmmcPartitionLayout = EmmcPartitionLayout {
+            gp_size_mult: [1, 0, 0, 0],
+            ..Default::default()
+        };
+        assert!(matches!(
+            emmc.check_partition_layout(&layout),
+            Err(SdmmcError::EINVAL)
+        ));
+    }
+
+    #[test]
+    fn partition_layout_registers_of_byte_mode_device() {
+        let emmc: EMmc = emmc_device(false, Some(ExtCsd::new(&ext_csd_raw()).unwrap()));
+        let layout: EmmcPartitionLayout = EmmcPartitionLayout {
+            gp_size_mult: [0, 0x01_0203, 0, 0],
+            gp_enhanced: [false, true, false, false],
+            enh_start_sector: 8192,
+            enh_size_mult: 1,
+        };
+
+        let registers: [(usize, u8); 20] = emmc.partition_layout_registers(&layout).unwrap();
+
+        // ENH_START_ADDR is in bytes
+        let enh_start_addr: [u8; 4] = (8192u32 * 512).to_le_bytes();
+        for i in 0..4 {
+            assert_eq!(
+                registers[i],
+                (EXT_CSD_ENH_START_ADDR + i, enh_start_addr[i])
+            );
+        }
+        assert_eq!(registers[4], (EXT_CSD_ENH_SIZE_MULT, 1));
+        assert_eq!(registers[10], (EXT_CSD_GP_SIZE_MULT + 3, 0x03));
+        assert_eq!(registers[11], (EXT_CSD_GP_SIZE_MULT + 4, 0x02));
+        assert_eq!(registers[12], (EXT_CSD_GP_SIZE_MULT + 5, 0x01));
+        assert_eq!(
+            registers[19],
+            (
+                EXT_CSD_PARTITIONS_ATTRIBUTE,
+                EXT_CSD_PART_ATTR_ENH_USR | (1 << 2)
+            )
+        );
+    }
+
+    #[test]
+    fn partition_layout_rejects_enh_start_overflowing_byte_address() {
+        let emmc: EMmc = emmc_device(false, Some(ExtCsd::new(&ext_csd_raw()).unwrap()));
+        let layout: EmmcPartitionLayout = EmmcPartitionLayout {
+            enh_start_sector: 1 << 23,
+            enh_size_mult: 1,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            emmc.check_partition_layout(&layout),
+            Err(SdmmcError::EINVAL)
+        ));
+        assert!(matches!(
+            emmc.partition_layout_registers(&layout),
+            Err(SdmmcError::EINVAL)
+        ));
+    }
+
+    #[test]
+    fn ext_csd_correctly_programmed_sectors() {
+        let mut raw: [u8; EXT_CSD_SIZE] = ext_csd_raw();
+        raw[EXT_CSD_CORRECTLY_PRG_SECTORS_NUM..EXT_CSD_CORRECTLY_PRG_SECTORS_NUM + 4]
+            .copy_from_slice(&300u32.to_le_bytes());
+
+        let ext_csd: ExtCsd = ExtCsd::new(&raw).unwrap();
+
+        assert_eq!(ext_csd.correctly_prg_sectors_num, 300);
+    }
+}
@@ -1,7 +1,19 @@
 // Copyright 2025, UNSW
 // SPDX-License-Identifier: BSD-2-Clause
 
-use super::sd::{EMmc, Sdcard};
+use core::sync::atomic::Ordering;
+
+use crate::{sdmmc_os::Sleep, sdmmc_traits::SdmmcHardware};
+
+use super::{
+    MMC_RSP_R1, MmcData, MmcDataFlag, SDCARD_DEFAULT_SECTOR_SIZE, SdmmcCmd, SdmmcError,
+    constant::{
+        MMC_CMD_SEND_TUNING_BLOCK, MMC_CMD_SEND_TUNING_BLOCK_HS200, TUNING_BLK_PATTERN_4BIT,
+        TUNING_BLK_PATTERN_8BIT,
+    },
+    emmc::EMmc,
+    sd::{SdCapacityClass, Sdcard},
+};
 
 // Enums for bus_width
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -58,6 +70,17 @@ pub struct MmcState {
     pub bus_width: MmcBusWidth,
 }
 
+/// Extra semantics of a write request, used by write_block_with_flags
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteFlags {
+    /// The data must reach the non-volatile storage before the write completes,
+    /// only makes a difference when the volatile cache of the eMMC is enabled
+    pub force_unit_access: bool,
+    /// The old data is kept if the write is interrupted by a power loss, only supported by eMMC
+    /// Without enhanced reliable write, the request must be one block or REL_WR_SEC_C aligned blocks
+    pub reliable_write: bool,
+}
+
 /// Some of the MmcDevice is reserved for future use
 #[allow(dead_code)]
 pub(crate) enum MmcDevice {
@@ -66,6 +89,37 @@ pub(crate) enum MmcDevice {
     Unknown,
 }
 
+impl MmcDevice {
+    /// Address argument of the read, write and erase cmds for block `start_idx`,
+    /// together with the argument of CMD22 which must be sent right before them for SDUC cards
+    /// Standard capacity sdcards and byte mode eMMC take the address in bytes,
+    /// the block length is set to 512 bytes through CMD16 for sdcards.
+    /// Addresses that do not fit into the argument are rejected instead of being truncated
+    pub(crate) fn card_address(&self, start_idx: u64) -> Result<(u32, Option<u32>), SdmmcError> {
+        let address: u64 = match self {
+            MmcDevice::Sdcard(sdcard) => match sdcard.capacity_class {
+                SdCapacityClass::Standard => start_idx
+                    .checked_mul(SDCARD_DEFAULT_SECTOR_SIZE as u64)
+                    .ok_or(SdmmcError::EINVAL)?,
+                SdCapacityClass::High => start_idx,
+                SdCapacityClass::Ultra => {
+                    // EXT_ADR of CMD22 carries the bits 32-37 of the address
+                    if start_idx >> 38 != 0 {
+                        return Err(SdmmcError::EINVAL);
+                    }
+                    return Ok((start_idx as u32, Some((start_idx >> 32) as u32)));
+                }
+            },
+            MmcDevice::EMmc(emmc) => return Ok((emmc.card_address(start_idx)?, None)),
+            _ => start_idx,
+        };
+
+        let address: u32 = u32::try_from(address).map_err(|_| SdmmcError::EINVAL)?;
+
+        Ok((address, None))
+    }
+}
+
 /// Represents the different states of an SD or eMMC card.
 /// Not used yet
 #[allow(dead_code)]
@@ -99,3 +153,172 @@ pub struct CardInfo {
     pub card_capacity: u64,
     pub card_state: MmcState,
 }
+
+/// Describes how one tuning attempt is checked
+///
+/// The protocol layer builds this struct and passes it to `sdmmc_execute_tuning`.
+/// The host only needs to adjust its sampling point and call `send_tuning_block`,
+/// so the tuning block is checked the same way regardless of the host.
+#[derive(Debug, Clone, Copy)]
+pub struct MmcTuning {
+    cmdidx: u32,
+    cmdarg: u32,
+    blocksize: u32,
+    /// The expected content of the tuning block, None if the data is not defined
+    pattern: Option<&'static [u8]>,
+    physical_memory: u64,
+    raw_memory: *const u8,
+    invalidate_cache_fn: fn(),
+}
+
+impl MmcTuning {
+    /// Tuning for UHS-I SD cards through CMD19, the card returns the 64 bytes 4 bit tuning block
+    /// so the data is compared with the pattern as well as checked by the CRC
+    /// Unsafe because the raw memory is dereferenced when checking the tuning block
+    pub(crate) unsafe fn sdcard(
+        physical_memory: u64,
+        raw_memory: *const u8,
+        invalidate_cache_fn: fn(),
+    ) -> MmcTuning {
+        MmcTuning {
+            cmdidx: MMC_CMD_SEND_TUNING_BLOCK,
+            cmdarg: 0,
+            blocksize: TUNING_BLK_PATTERN_4BIT.len() as u32,
+            pattern: Some(&TUNING_BLK_PATTERN_4BIT),
+            physical_memory,
+            raw_memory,
+            invalidate_cache_fn,
+        }
+    }
+
+    /// Tuning for eMMC HS200 through CMD21, the tuning block depends on the bus width
+    /// The memory must be able to hold 128 bytes
+    /// Unsafe because the raw memory is dereferenced when checking the tuning block
+    pub(crate) unsafe fn emmc_hs200(
+        bus_width: MmcBusWidth,
+        physical_memory: u64,
+        raw_memory: *const u8,
+        invalidate_cache_fn: fn(),
+    ) -> Result<MmcTuning, SdmmcError> {
+        let pattern: &'static [u8] = match bus_width {
+            MmcBusWidth::Width8 => &TUNING_BLK_PATTERN_8BIT,
+            MmcBusWidth::Width4 => &TUNING_BLK_PATTERN_4BIT,
+            MmcBusWidth::Width1 => return Err(SdmmcError::EINVAL),
+        };
+
+        Ok(MmcTuning {
+            cmdidx: MMC_CMD_SEND_TUNING_BLOCK_HS200,
+            cmdarg: 0,
+            blocksize: pattern.len() as u32,
+            pattern: Some(pattern),
+            physical_memory,
+            raw_memory,
+            invalidate_cache_fn,
+        })
+    }
+
+    /// Send the tuning command and check the tuning block returned by the card
+    /// Returns EIO if the tuning block does not match, so the host could try the next sampling point
+    pub fn send_tuning_block<T: SdmmcHardware>(
+        &self,
+        hardware: &mut T,
+        sleep: &mut dyn Sleep,
+    ) -> Result<(), SdmmcError> {
+        let mut resp: [u32; 4] = [0; 4];
+
+        let cmd: SdmmcCmd = SdmmcCmd {
+            cmdidx: self.cmdidx,
+            resp_type: MMC_RSP_R1,
+            cmdarg: self.cmdarg,
+        };
+
+        let data: MmcData = MmcData {
+            blocksize: self.blocksize,
+            blockcnt: 1,
+            flags: MmcDataFlag::SdmmcDataRead,
+            addr: self.physical_memory,
+        };
+
+        hardware.sdmmc_do_request(sleep, &cmd, Some(&data), &mut resp, 1)?;
+
+        if let Some(pattern) = self.pattern {
+            core::sync::atomic::fence(Ordering::Acquire);
+
+            (self.invalidate_cache_fn)();
+
+            let received: &[u8] =
+                unsafe { core::slice::from_raw_parts(self.raw_memory, pattern.len()) };
+
+            if received != pattern {
+                return Err(SdmmcError::EIO);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdmmc::{emmc::tests::emmc_device, sd::tests::sdcard_device};
+
+    #[test]
+    fn card_address_of_sdsc() {
+        let device: MmcDevice = MmcDevice::Sdcard(sdcard_device(SdCapacityClass::Standard));
+
+        assert_eq!(device.card_address(3).unwrap(), (3 * 512, None));
+        assert!(matches!(
+            device.card_address(1 << 23),
+            Err(SdmmcError::EINVAL)
+        ));
+    }
+
+    #[test]
+    fn card_address_of_sdhc() {
+        let device: MmcDevice = MmcDevice::Sdcard(sdcard_device(SdCapacityClass::High));
+
+        assert_eq!(device.card_address(3).unwrap(), (3, None));
+        assert_eq!(
+            device.card_address(u32::MAX as u64).unwrap(),
+            (u32::MAX, None)
+        );
+        assert!(matches!(
+            device.card_address(1 << 32),
+            Err(SdmmcError::EINVAL)
+        ));
+    }
+
+    #[test]
+    fn card_address_of_sduc() {
+        let device: MmcDevice = MmcDevice::Sdcard(sdcard_device(SdCapacityClass::Ultra));
+
+        assert_eq!(device.card_address(3).unwrap(), (3, Some(0)));
+        assert_eq!(device.card_address((1 << 32) | 5).unwrap(), (5, Some(1)));
+        assert_eq!(
+            device.card_address((1 << 38) - 1).unwrap(),
+            (u32::MAX, Some(0x3F))
+        );
+        assert!(matches!(
+            device.card_address(1 << 38),
+            Err(SdmmcError::EINVAL)
+        ));
+    }
+
+    #[test]
+    fn card_address_of_emmc() {
+        let device: MmcDevice = MmcDevice::EMmc(emmc_device(false, None));
+        assert_eq!(device.card_address(3).unwrap(), (3 * 512, None));
+        assert!(matches!(
+            device.card_address(1 << 23),
+            Err(SdmmcError::EINVAL)
+        ));
+
+        let device: MmcDevice = MmcDevice::EMmc(emmc_device(true, None));
+        assert_eq!(device.card_address(3).unwrap(), (3, None));
+        assert!(matches!(
+            device.card_address(1 << 32),
+            Err(SdmmcError::EINVAL)
+        ));
+    }
+}
@@ -0,0 +1,367 @@
+// Copyright 2025, UNSW
+// SPDX-License-Identifier: BSD-2-Clause
+
+use core::sync::atomic::Ordering;
+
+use crate::sdmmc_os::RpmbAuthenticator;
+
+use super::SdmmcError;
+
+/// Every RPMB request and response is exactly one 512 bytes frame
+pub const RPMB_FRAME_SIZE: usize = 512;
+/// Size of the data carried by one RPMB frame
+pub const RPMB_DATA_SIZE: usize = 256;
+pub const RPMB_KEY_MAC_SIZE: usize = 32;
+pub const RPMB_NONCE_SIZE: usize = 16;
+
+// Byte offset of the fields inside the RPMB frame, check JESD84-B51 section 6.6.22.
+// All the fields are stored in big endian
+const RPMB_KEY_MAC_OFFSET: usize = 196;
+const RPMB_DATA_OFFSET: usize = 228;
+const RPMB_NONCE_OFFSET: usize = 484;
+const RPMB_WRITE_COUNTER_OFFSET: usize = 500;
+const RPMB_ADDRESS_OFFSET: usize = 504;
+const RPMB_BLOCK_COUNT_OFFSET: usize = 506;
+const RPMB_RESULT_OFFSET: usize = 508;
+const RPMB_REQ_RESP_OFFSET: usize = 510;
+
+// Request types
+pub(crate) const RPMB_REQ_KEY_PROGRAM: u16 = 0x0001;
+pub(crate) const RPMB_REQ_READ_COUNTER: u16 = 0x0002;
+pub(crate) const RPMB_REQ_AUTH_WRITE: u16 = 0x0003;
+pub(crate) const RPMB_REQ_AUTH_READ: u16 = 0x0004;
+pub(crate) const RPMB_REQ_RESULT_READ: u16 = 0x0005;
+
+// Response types are the request types shifted into the upper byte
+pub(crate) const RPMB_RESP_KEY_PROGRAM: u16 = 0x0100;
+pub(crate) const RPMB_RESP_READ_COUNTER: u16 = 0x0200;
+pub(crate) const RPMB_RESP_AUTH_WRITE: u16 = 0x0300;
+pub(crate) const RPMB_RESP_AUTH_READ: u16 = 0x0400;
+
+// Operation results reported by the eMMC
+pub const RPMB_RESULT_OK: u16 = 0x0000;
+pub const RPMB_RESULT_GENERAL_FAILURE: u16 = 0x0001;
+pub const RPMB_RESULT_AUTH_FAILURE: u16 = 0x0002;
+pub const RPMB_RESULT_COUNTER_FAILURE: u16 = 0x0003;
+pub const RPMB_RESULT_ADDRESS_FAILURE: u16 = 0x0004;
+pub const RPMB_RESULT_WRITE_FAILURE: u16 = 0x0005;
+pub const RPMB_RESULT_READ_FAILURE: u16 = 0x0006;
+pub const RPMB_RESULT_KEY_NOT_PROGRAMMED: u16 = 0x0007;
+const RPMB_RESULT_MASK: u16 = 0x007F;
+/// Set together with the result once the write counter has reached its maximum value
+pub const RPMB_RESULT_COUNTER_EXPIRED: u16 = 0x0080;
+
+/// DMA buffer holding the RPMB frame being sent or received
+pub struct RpmbFrameBuffer {
+    raw_memory: *mut [u8; RPMB_FRAME_SIZE],
+    physical_memory: u64,
+    cache_invalidate_fn: fn(),
+}
+
+impl RpmbFrameBuffer {
+    /// `cache_invalidate_fn` is called after the driver writes a frame and before the driver reads a frame,
+    /// so it should clean and invalidate the cache for the region if the memory is cached.
+    ///
+    /// # Safety
+    /// `raw_memory` and `physical_memory` must point to the same 512 bytes region that the host can DMA into/from,
+    /// the raw memory is dereferenced by the driver whenever a frame is sent or received.
+    pub unsafe fn new(
+        raw_memory: *mut [u8; RPMB_FRAME_SIZE],
+        physical_memory: u64,
+        cache_invalidate_fn: fn(),
+    ) -> RpmbFrameBuffer {
+        RpmbFrameBuffer {
+            raw_memory,
+            physical_memory,
+            cache_invalidate_fn,
+        }
+    }
+
+    pub(crate) fn physical_memory(&self) -> u64 {
+        self.physical_memory
+    }
+
+    /// Encode the frame into the buffer, the MAC is calculated over the encoded frame when `authenticator` is provided
+    pub(crate) fn write_frame(
+        &self,
+        frame: &RpmbFrame,
+        authenticator: Option<&mut dyn RpmbAuthenticator>,
+    ) -> Result<(), SdmmcError> {
+        let raw: &mut [u8; RPMB_FRAME_SIZE] = unsafe { &mut *self.raw_memory };
+
+        frame.encode(raw);
+
+        if let Some(authenticator) = authenticator {
+            let mac: [u8; RPMB_KEY_MAC_SIZE] =
+                authenticator.rpmb_hmac_sha256(&raw[RPMB_DATA_OFFSET..])?;
+            raw[RPMB_KEY_MAC_OFFSET..RPMB_DATA_OFFSET].copy_from_slice(&mac);
+        }
+
+        core::sync::atomic::fence(Ordering::Release);
+
+        (self.cache_invalidate_fn)();
+
+        Ok(())
+    }
+
+    /// Decode the frame in the buffer and check it is the `expected_resp` response with a successful result,
+    /// the MAC is checked afterwards when `authenticator` is provided
+    /// A failed operation is reported with its result, as the eMMC may not be able to sign the response,
+    /// e.g. before the key is programmed
+    pub(crate) fn read_frame(
+        &self,
+        expected_resp: u16,
+        authenticator: Option<&mut dyn RpmbAuthenticator>,
+    ) -> Result<RpmbFrame, SdmmcError> {
+        core::sync::atomic::fence(Ordering::Acquire);
+
+        (self.cache_invalidate_fn)();
+
+        let raw: &[u8; RPMB_FRAME_SIZE] = unsafe { &*self.raw_memory };
+
+        let frame: RpmbFrame = RpmbFrame::decode(raw);
+
+        frame.check_response(expected_resp)?;
+
+        if let Some(authenticator) = authenticator {
+            let mac: [u8; RPMB_KEY_MAC_SIZE] =
+                authenticator.rpmb_hmac_sha256(&raw[RPMB_DATA_OFFSET..])?;
+            if mac != frame.key_mac {
+                // The response does not come from a device holding the same key
+                return Err(SdmmcError::ERPMB(RPMB_RESULT_AUTH_FAILURE));
+            }
+        }
+
+        Ok(frame)
+    }
+}
+
+/// Decoded RPMB frame, the stuff bytes at the beginning of the frame are always zero
+pub(crate) struct RpmbFrame {
+    pub(crate) key_mac: [u8; RPMB_KEY_MAC_SIZE],
+    pub(crate) data: [u8; RPMB_DATA_SIZE],
+    pub(crate) nonce: [u8; RPMB_NONCE_SIZE],
+    pub(crate) write_counter: u32,
+    pub(crate) address: u16,
+    pub(crate) block_count: u16,
+    pub(crate) result: u16,
+    pub(crate) req_resp: u16,
+}
+
+impl RpmbFrame {
+    pub(crate) fn request(req_resp: u16) -> RpmbFrame {
+        RpmbFrame {
+            key_mac: [0; RPMB_KEY_MAC_SIZE],
+            data: [0; RPMB_DATA_SIZE],
+            nonce: [0; RPMB_NONCE_SIZE],
+            write_counter: 0,
+            address: 0,
+            block_count: 0,
+            result: 0,
+            req_resp,
+        }
+    }
+
+    fn encode(&self, raw: &mut [u8; RPMB_FRAME_SIZE]) {
+        raw[..RPMB_KEY_MAC_OFFSET].fill(0);
+        raw[RPMB_KEY_MAC_OFFSET..RPMB_DATA_OFFSET].copy_from_slice(&self.key_mac);
+        raw[RPMB_DATA_OFFSET..RPMB_NONCE_OFFSET].copy_from_slice(&self.data);
+        raw[RPMB_NONCE_OFFSET..RPMB_WRITE_COUNTER_OFFSET].copy_from_slice(&self.nonce);
+        raw[RPMB_WRITE_COUNTER_OFFSET..RPMB_ADDRESS_OFFSET]
+            .copy_from_slice(&self.write_counter.to_be_bytes());
+        raw[RPMB_ADDRESS_OFFSET..RPMB_BLOCK_COUNT_OFFSET]
+            .copy_from_slice(&self.address.to_be_bytes());
+        raw[RPMB_BLOCK_COUNT_OFFSET..RPMB_RESULT_OFFSET]
+            .copy_from_slice(&self.block_count.to_be_bytes());
+        raw[RPMB_RESULT_OFFSET..RPMB_REQ_RESP_OFFSET].copy_from_slice(&self.result.to_be_bytes());
+        raw[RPMB_REQ_RESP_OFFSET..].copy_from_slice(&self.req_resp.to_be_bytes());
+    }
+
+    fn decode(raw: &[u8; RPMB_FRAME_SIZE]) -> RpmbFrame {
+        let mut frame: RpmbFrame = RpmbFrame::request(0);
+
+        frame
+            .key_mac
+            .copy_from_slice(&raw[RPMB_KEY_MAC_OFFSET..RPMB_DATA_OFFSET]);
+        frame
+            .data
+            .copy_from_slice(&raw[RPMB_DATA_OFFSET..RPMB_NONCE_OFFSET]);
+        frame
+            .nonce
+            .copy_from_slice(&raw[RPMB_NONCE_OFFSET..RPMB_WRITE_COUNTER_OFFSET]);
+        frame.write_counter = u32::from_be_bytes([
+            raw[RPMB_WRITE_COUNTER_OFFSET],
+            raw[RPMB_WRITE_COUNTER_OFFSET + 1],
+            raw[RPMB_WRITE_COUNTER_OFFSET + 2],
+            raw[RPMB_WRITE_COUNTER_OFFSET + 3],
+        ]);
+        frame.address =
+            u16::from_be_bytes([raw[RPMB_ADDRESS_OFFSET], raw[RPMB_ADDRESS_OFFSET + 1]]);
+        frame.block_count = u16::from_be_bytes([
+            raw[RPMB_BLOCK_COUNT_OFFSET],
+            raw[RPMB_BLOCK_COUNT_OFFSET + 1],
+        ]);
+        frame.result = u16::from_be_bytes([raw[RPMB_RESULT_OFFSET], raw[RPMB_RESULT_OFFSET + 1]]);
+        frame.req_resp =
+            u16::from_be_bytes([raw[RPMB_REQ_RESP_OFFSET], raw[RPMB_REQ_RESP_OFFSET + 1]]);
+
+        frame
+    }
+
+    /// Check the response type and the operation result reported by the eMMC
+    pub(crate) fn check_response(&self, expected_resp: u16) -> Result<(), SdmmcError> {
+        if self.req_resp != expected_resp {
+            return Err(SdmmcError::EIO);
+        }
+
+        if self.result & RPMB_RESULT_MASK != RPMB_RESULT_OK {
+            return Err(SdmmcError::ERPMB(self.result));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_cache_maintenance() {}
+
+    /// Stands in for HMAC-SHA256, the MAC records the length and a checksum of the message
+    struct ChecksumAuthenticator;
+
+    impl RpmbAuthenticator for ChecksumAuthenticator {
+        fn rpmb_hmac_sha256(&mut self, message: &[u8]) -> Result<[u8; 32], SdmmcError> {
+            let mut mac: [u8; RPMB_KEY_MAC_SIZE] = [0; RPMB_KEY_MAC_SIZE];
+            mac[..2].copy_from_slice(&(message.len() as u16).to_be_bytes());
+            mac[2] = message
+                .iter()
+                .fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+            Ok(mac)
+        }
+
+        fn rpmb_nonce(&mut self) -> [u8; 16] {
+            [0x5A; RPMB_NONCE_SIZE]
+        }
+    }
+
+    fn auth_write_request() -> RpmbFrame {
+        let mut frame: RpmbFrame = RpmbFrame::request(RPMB_REQ_AUTH_WRITE);
+        frame.data = [0xA5; RPMB_DATA_SIZE];
+        frame.nonce = [0x11; RPMB_NONCE_SIZE];
+        frame.write_counter = 0x0102_0304;
+        frame.address = 0x0506;
+        frame.block_count = 1;
+        frame
+    }
+
+    #[test]
+    fn frame_layout() {
+        let mut raw: [u8; RPMB_FRAME_SIZE] = [0xFF; RPMB_FRAME_SIZE];
+        let buffer: RpmbFrameBuffer =
+            unsafe { RpmbFrameBuffer::new(&mut raw, 0, no_cache_maintenance) };
+
+        buffer.write_frame(&auth_write_request(), None).unwrap();
+
+        assert!(raw[..RPMB_DATA_OFFSET].iter().all(|byte| *byte == 0));
+        assert!(
+            raw[RPMB_DATA_OFFSET..RPMB_NONCE_OFFSET]
+                .iter()
+                .all(|byte| *byte == 0xA5)
+        );
+        assert!(
+            raw[RPMB_NONCE_OFFSET..RPMB_WRITE_COUNTER_OFFSET]
+                .iter()
+                .all(|byte| *byte == 0x11)
+        );
+        assert_eq!(raw[500..504], [0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(raw[504..506], [0x05, 0x06]);
+        assert_eq!(raw[506..508], [0x00, 0x01]);
+        assert_eq!(raw[508..510], [0x00, 0x00]);
+        assert_eq!(raw[510..512], [0x00, 0x03]);
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let mut raw: [u8; RPMB_FRAME_SIZE] = [0; RPMB_FRAME_SIZE];
+        let buffer: RpmbFrameBuffer =
+            unsafe { RpmbFrameBuffer::new(&mut raw, 0, no_cache_maintenance) };
+
+        buffer.write_frame(&auth_write_request(), None).unwrap();
+        let frame: RpmbFrame = buffer.read_frame(RPMB_REQ_AUTH_WRITE, None).unwrap();
+
+        assert_eq!(frame.data, [0xA5; RPMB_DATA_SIZE]);
+        assert_eq!(frame.nonce, [0x11; RPMB_NONCE_SIZE]);
+        assert_eq!(frame.write_counter, 0x0102_0304);
+        assert_eq!(frame.address, 0x0506);
+        assert_eq!(frame.block_count, 1);
+        assert_eq!(frame.req_resp, RPMB_REQ_AUTH_WRITE);
+    }
+
+    #[test]
+    fn mac_covers_data_to_end_of_frame() {
+        let mut raw: [u8; RPMB_FRAME_SIZE] = [0; RPMB_FRAME_SIZE];
+        let buffer: RpmbFrameBuffer =
+            unsafe { RpmbFrameBuffer::new(&mut raw, 0, no_cache_maintenance) };
+        let mut authenticator: ChecksumAuthenticator = ChecksumAuthenticator;
+
+        buffer
+            .write_frame(&auth_write_request(), Some(&mut authenticator))
+            .unwrap();
+
+        let frame: RpmbFrame = buffer
+            .read_frame(RPMB_REQ_AUTH_WRITE, Some(&mut authenticator))
+            .unwrap();
+        assert_eq!(frame.key_mac[..2], 284u16.to_be_bytes());
+
+        // Any change after the MAC field breaks the authentication
+        unsafe { (*buffer.raw_memory)[RPMB_WRITE_COUNTER_OFFSET] ^= 1 };
+        assert!(matches!(
+            buffer.read_frame(RPMB_REQ_AUTH_WRITE, Some(&mut authenticator)),
+            Err(SdmmcError::ERPMB(RPMB_RESULT_AUTH_FAILURE))
+        ));
+    }
+
+    #[test]
+    fn failed_result_is_reported_before_mac() {
+        let mut raw: [u8; RPMB_FRAME_SIZE] = [0; RPMB_FRAME_SIZE];
+        let buffer: RpmbFrameBuffer =
+            unsafe { RpmbFrameBuffer::new(&mut raw, 0, no_cache_maintenance) };
+        let mut authenticator: ChecksumAuthenticator = ChecksumAuthenticator;
+
+        // An eMMC without a key cannot sign the response
+        let mut frame: RpmbFrame = RpmbFrame::request(RPMB_RESP_READ_COUNTER);
+        frame.result = RPMB_RESULT_KEY_NOT_PROGRAMMED;
+        buffer.write_frame(&frame, None).unwrap();
+
+        assert!(matches!(
+            buffer.read_frame(RPMB_RESP_READ_COUNTER, Some(&mut authenticator)),
+            Err(SdmmcError::ERPMB(RPMB_RESULT_KEY_NOT_PROGRAMMED))
+        ));
+        assert!(matches!(
+            buffer.read_frame(RPMB_RESP_AUTH_READ, Some(&mut authenticator)),
+            Err(SdmmcError::EIO)
+        ));
+    }
+
+    #[test]
+    fn response_check() {
+        let mut frame: RpmbFrame = RpmbFrame::request(RPMB_RESP_AUTH_WRITE);
+        assert!(frame.check_response(RPMB_RESP_AUTH_WRITE).is_ok());
+        assert!(matches!(
+            frame.check_response(RPMB_RESP_AUTH_READ),
+            Err(SdmmcError::EIO)
+        ));
+
+        // The expired counter is reported on top of the result
+        frame.result = RPMB_RESULT_OK | RPMB_RESULT_COUNTER_EXPIRED;
+        assert!(frame.check_response(RPMB_RESP_AUTH_WRITE).is_ok());
+
+        frame.result = RPMB_RESULT_WRITE_FAILURE | RPMB_RESULT_COUNTER_EXPIRED;
+        assert!(matches!(
+            frame.check_response(RPMB_RESP_AUTH_WRITE),
+            Err(SdmmcError::ERPMB(result)) if result == RPMB_RESULT_WRITE_FAILURE | RPMB_RESULT_COUNTER_EXPIRED
+        ));
+    }
+}
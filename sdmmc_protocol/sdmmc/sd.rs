@@ -7,8 +7,9 @@ use crate::{
     dev_log, info,
     sdmmc::{
         MMC_RSP_R1, MmcData, MmcDataFlag, SdmmcCmd,
-        constant::{MMC_CMD_APP_CMD, SD_CMD_APP_SEND_SCR, SD_CMD_SWITCH_FUNC},
+        constant::{MMC_CMD_APP_CMD, SD_CMD_APP_SEND_SCR},
         mmc_struct::CardInfo,
+        print_card_capacity,
     },
     sdmmc_os::Sleep,
     sdmmc_traits::SdmmcHardware,
@@ -26,6 +27,7 @@ pub struct Sdcard {
     pub(crate) manufacture_info: Cid,
     pub(crate) card_specific_data: Csd,
     pub(crate) card_version: SdVersion,
+    pub(crate) capacity_class: SdCapacityClass,
     pub(crate) relative_card_addr: u16,
     pub(crate) card_state: MmcState,
     pub(crate) card_cap: SdcardCapability,
@@ -88,39 +90,11 @@ impl Sdcard {
         Ok(scr)
     }
 
-    pub fn sdcard_test_tuning<T: SdmmcHardware>(
-        hardware: &mut T,
-        sleep: &mut dyn Sleep,
-        memory: *mut [u8; 64],
-    ) -> Result<(), SdmmcError> {
-        let mut resp: [u32; 4] = [0; 4];
-
-        let data = MmcData {
-            blocksize: 64,
-            blockcnt: 1,
-            flags: MmcDataFlag::SdmmcDataRead,
-            addr: memory as u64,
-        };
-
-        let cmd = SdmmcCmd {
-            cmdidx: SD_CMD_SWITCH_FUNC,
-            resp_type: MMC_RSP_R1,
-            cmdarg: 0x00FFFFFF,
-        };
-
-        hardware.sdmmc_do_request(sleep, &cmd, Some(&data), &mut resp, 1)
-    }
-
     pub fn print_info(&self) {
         const LABEL_WIDTH: usize = 20;
         const DATA_WIDTH: usize = 25;
         let capacity_bytes = self.card_specific_data.card_capacity;
 
-        const KB: u64 = 1024;
-        const MB: u64 = 1024 * KB;
-        const GB: u64 = 1024 * MB;
-        const TB: u64 = 1024 * GB;
-
         info!("\n\n╔═════════════════════════════════════════════════╗");
         info!("║ SDCARD INFORMATION                              ║");
         info!("╠═════════════════════════════════════════════════╣");
@@ -173,37 +147,7 @@ impl Sdcard {
             padding = DATA_WIDTH - 7
         );
 
-        let (val, fraction, unit) = match capacity_bytes {
-            c if c >= TB => (c / TB, (c % TB) / GB, "TB"),
-            c if c >= GB => (c / GB, (c % GB) / MB, "GB"),
-            c if c >= MB => (c / MB, (c % MB) / KB, "MB"),
-            c if c >= KB => (c / KB, c % KB, "KB"),
-            c => (c, 0, "Bytes"),
-        };
-        if fraction == 0 {
-            info!(
-                "║ {:<label_width$}: {:<data_width$} ║",
-                "Card Capacity",
-                &format_args!(
-                    "{} {}{:padding$}",
-                    val,
-                    unit,
-                    "",
-                    padding = DATA_WIDTH - 4 - val.ilog10() as usize
-                ),
-                label_width = LABEL_WIDTH,
-                data_width = DATA_WIDTH
-            );
-        } else {
-            info!(
-                "║ {:<label_width$}: {}{:padding$} ║",
-                "Card Capacity",
-                &format_args!("{}.{:0<3} {}", val, (fraction * 1000) / 1024, unit),
-                "",
-                label_width = LABEL_WIDTH,
-                padding = DATA_WIDTH - val.ilog10() as usize - 8
-            );
-        }
+        print_card_capacity(capacity_bytes, LABEL_WIDTH, DATA_WIDTH);
 
         info!("╚═════════════════════════════════════════════════╝\n");
     }
@@ -217,10 +161,15 @@ impl Sdcard {
     }
 }
 
-/// Placeholder eMMC struct that is not implemented
-pub struct EMmc {
-    pub card_id: u128,
-    pub method: BlockTransmissionMode,
+/// Capacity class reported by CCS and CO2T in the OCR, which decides how the blocks are addressed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum SdCapacityClass {
+    /// SDSC, addressed by byte instead of 512 bytes block
+    Standard,
+    /// SDHC/SDXC, addressed by 512 bytes block with a 32 bits address
+    High,
+    /// SDUC, the upper 6 bits of the 38 bits block address are sent through CMD22 first
+    Ultra,
 }
 
 // Beware this struct is meant to track the cmd set that the sdcard should support
@@ -296,6 +245,8 @@ pub(crate) struct Csd {
     max_write_block_len: u16,
     erase_sector_size: u32,
     supports_partial_write: bool,
+    tmp_write_protect: bool,
+    perm_write_protect: bool,
 }
 
 impl Csd {
@@ -311,9 +262,9 @@ impl Csd {
         let sd_version: SdVersion = match csd_structure {
             0 => SdVersion::V1_0, // CSD Version 1.0
             1 => SdVersion::V2_0, // CSD Version 2.0
+            2 => SdVersion::V3_0, // CSD Version 3.0, only used by SDUC
             // Even if the parsing csd fails, it should not crash the driver completely
-            _ => return Err(SdmmcError::EUNSUPPORTEDCARD), // CSD structures beyond 2.0 are not supported here
-                                                           // Actually SDUC card are using CSD 3.0 so maybe add something here later.
+            _ => return Err(SdmmcError::EUNSUPPORTEDCARD), // CSD structures beyond 3.0 are not supported here
         };
 
         // Parse fields based on CSD version
@@ -340,7 +291,15 @@ impl Csd {
 
                 (card_capacity, sector_size)
             }
-            SdVersion::V3_0 => return Err(SdmmcError::EINVAL),
+            SdVersion::V3_0 => {
+                // CSD Version 3.0 capacity calculation for SDUC, C_SIZE is extended to 28 bits
+                let c_size: u64 = ((csd_combined >> 48) & 0xFFF_FFFF) as u64; // Bits 48–75
+                let card_capacity: u64 = (c_size + 1) * 512 * 1024;
+
+                let sector_size: u32 = (((csd_combined >> 39) & 0x7F) as u32 + 1) * 512; // Bits 39–45
+
+                (card_capacity, sector_size)
+            }
             SdVersion::V4_0 => return Err(SdmmcError::EINVAL),
         };
 
@@ -354,6 +313,10 @@ impl Csd {
         // Partial write support (same for both versions)
         let supports_partial_write: bool = ((csd_combined >> 21) & 0x1) != 0; // Bit 21
 
+        // Write protection of the whole card (same for both versions)
+        let perm_write_protect: bool = ((csd_combined >> 13) & 0x1) != 0; // Bit 13
+        let tmp_write_protect: bool = ((csd_combined >> 12) & 0x1) != 0; // Bit 12
+
         // Return the constructed CSD struct along with the SD version
         Ok((
             Csd {
@@ -363,10 +326,17 @@ impl Csd {
                 max_write_block_len,
                 erase_sector_size,
                 supports_partial_write,
+                tmp_write_protect,
+                perm_write_protect,
             },
             sd_version,
         ))
     }
+
+    /// The whole card is write protected through TMP_WRITE_PROTECT or PERM_WRITE_PROTECT
+    pub(crate) fn write_protected(&self) -> bool {
+        self.tmp_write_protect || self.perm_write_protect
+    }
 }
 
 #[allow(dead_code)]
@@ -429,3 +399,92 @@ impl Scr {
         })
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::sdmmc::mmc_struct::MmcTiming;
+
+    fn csd_words(csd: u128) -> [u32; 4] {
+        [
+            (csd >> 96) as u32,
+            (csd >> 64) as u32,
+            (csd >> 32) as u32,
+            csd as u32,
+        ]
+    }
+
+    /// CSD version 1.0 of a 1GB SDSC card, 1024 bytes blocks
+    const CSD_V1_SDSC: u128 = (10 << 80) | (2047 << 62) | (7 << 47) | (0x7F << 39) | (9 << 22);
+    /// CSD version 2.0 of an 8GB SDHC card
+    const CSD_V2_SDHC: u128 = (1 << 126) | (9 << 80) | (15159 << 48) | (9 << 22);
+    /// CSD version 3.0 of a 32TB SDUC card, C_SIZE uses more than the 22 bits of CSD version 2.0
+    const CSD_V3_SDUC: u128 = (2 << 126) | (9 << 80) | (0x3FF_FFFF << 48) | (9 << 22);
+
+    pub(crate) fn sdcard_device(capacity_class: SdCapacityClass) -> Sdcard {
+        let (csd, card_version) = Csd::new(csd_words(CSD_V2_SDHC)).unwrap();
+
+        Sdcard {
+            card_id: 0,
+            manufacture_info: Cid::new([0; 4]),
+            card_specific_data: csd,
+            card_version,
+            capacity_class,
+            relative_card_addr: 0,
+            card_state: MmcState {
+                timing: MmcTiming::Legacy,
+                bus_width: MmcBusWidth::Width1,
+            },
+            card_cap: SdcardCapability(0),
+            method: BlockTransmissionMode::SetBlockCount,
+            card_config: None,
+        }
+    }
+
+    #[test]
+    fn csd_v1_capacity() {
+        let (csd, version) = Csd::new(csd_words(CSD_V1_SDSC)).unwrap();
+
+        assert_eq!(version, SdVersion::V1_0);
+        assert_eq!(csd.card_capacity, 1 << 30);
+        assert_eq!(csd.max_read_block_len, 1024);
+        assert_eq!(csd.erase_sector_size, 128);
+    }
+
+    #[test]
+    fn csd_v2_capacity() {
+        let (csd, version) = Csd::new(csd_words(CSD_V2_SDHC)).unwrap();
+
+        assert_eq!(version, SdVersion::V2_0);
+        assert_eq!(csd.card_capacity, 15160 * 512 * 1024);
+        assert_eq!(csd.max_read_block_len, 512);
+    }
+
+    #[test]
+    fn csd_v3_capacity() {
+        let (csd, version) = Csd::new(csd_words(CSD_V3_SDUC)).unwrap();
+
+        assert_eq!(version, SdVersion::V3_0);
+        assert_eq!(csd.card_capacity, 0x400_0000 * 512 * 1024);
+    }
+
+    #[test]
+    fn csd_rejects_reserved_structure() {
+        assert!(matches!(
+            Csd::new(csd_words(3 << 126)),
+            Err(SdmmcError::EUNSUPPORTEDCARD)
+        ));
+    }
+
+    #[test]
+    fn csd_write_protect() {
+        let (csd, _) = Csd::new(csd_words(CSD_V2_SDHC)).unwrap();
+        assert!(!csd.write_protected());
+
+        let (csd, _) = Csd::new(csd_words(CSD_V2_SDHC | (1 << 12))).unwrap();
+        assert!(csd.write_protected());
+
+        let (csd, _) = Csd::new(csd_words(CSD_V2_SDHC | (1 << 13))).unwrap();
+        assert!(csd.write_protected());
+    }
+}
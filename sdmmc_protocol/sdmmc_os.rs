@@ -3,6 +3,7 @@
 
 use crate::sdmmc::MmcSignalVoltage;
 use crate::sdmmc::SdmmcError;
+use crate::sdmmc::emmc::BkopsStatus;
 use core::sync::atomic::AtomicU8;
 use core::sync::atomic::Ordering;
 
@@ -16,6 +17,13 @@ pub trait Sleep {
             core::hint::spin_loop(); // Use spin loop hint to reduce contention during the wait
         }
     }
+
+    /// Monotonic time in microseconds, used to time out the busy waits of the async requests
+    /// without blocking the executor. Without a clock, the async busy waits sleep through usleep
+    /// between polls like the sync ones do
+    fn time_us(&mut self) -> Option<u64> {
+        None
+    }
 }
 
 #[allow(unused_variables)]
@@ -29,6 +37,25 @@ pub trait VoltageOps {
     }
 }
 
+/// Authentication of the eMMC RPMB partition, provided by the integrator
+/// so the RPMB authentication key never enters the driver
+pub trait RpmbAuthenticator {
+    /// HMAC-SHA256 keyed with the RPMB authentication key
+    /// `message` is the 284 bytes of the RPMB frame from the data field to the end of the frame
+    fn rpmb_hmac_sha256(&mut self, message: &[u8]) -> Result<[u8; 32], SdmmcError>;
+
+    /// A random nonce, used to make sure the response read from the RPMB partition is fresh
+    fn rpmb_nonce(&mut self) -> [u8; 16];
+}
+
+/// Decides when the driver starts the manual background operations of the eMMC, provided by the integrator
+/// The driver only asks the policy when the host reports it is idle through emmc_bkops_idle
+pub trait BkopsPolicy {
+    /// Return true to start the background operations, `status` is the level the eMMC reports
+    /// Linux starts them once the level reaches PerformanceImpacted
+    fn bkops_should_start(&mut self, status: BkopsStatus) -> bool;
+}
+
 pub fn process_wait_unreliable(time_ns: u64) {
     for _ in 0..time_ns {
         core::hint::spin_loop(); // Use spin loop hint to reduce contention during the wait
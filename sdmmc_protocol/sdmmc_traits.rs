@@ -5,7 +5,7 @@ use crate::{
     dev_log,
     sdmmc::{
         HostInfo, MmcData, MmcIos, MmcSignalVoltage, SdmmcCmd, SdmmcError,
-        mmc_struct::{MmcBusWidth, MmcTiming},
+        mmc_struct::{MmcBusWidth, MmcTiming, MmcTuning},
     },
     sdmmc_os::Sleep,
 };
@@ -36,6 +36,25 @@ pub trait SdmmcOps {
         Err(SdmmcError::ENOTIMPLEMENTED)
     }
 
+    /// Enable or disable latching the data with the data strobe signal driven by the eMMC
+    /// Used by HS400 and HS400 Enhanced Strobe, where the data is transferred on both clock edges.
+    /// For HS400 Enhanced Strobe, the response is latched with the data strobe signal as well.
+    /// The host should only change the sampling logic here, the clock is changed by `sdmmc_config_timing`
+    fn sdmmc_config_strobe(&mut self, enable: bool) -> Result<(), SdmmcError> {
+        Err(SdmmcError::ENOTIMPLEMENTED)
+    }
+
+    /// Put the host into or out of the eMMC boot operation mode, used by `emmc_boot_read`
+    /// Boot mode is enabled before the protocol layer puts the eMMC into pre-idle state, so a host without
+    /// boot support fails here without side effects. The CMD0 with the boot initiation argument sent while
+    /// enabled carries the boot data.
+    /// The eMMC answers with the boot data instead of a response, so the host should not wait for one.
+    /// If `boot_ack` is true, the host should expect the boot acknowledge pattern on DAT0 before the data
+    /// and report `ETIMEDOUT` if it does not arrive within 50ms.
+    fn sdmmc_config_boot(&mut self, enable: bool, boot_ack: bool) -> Result<(), SdmmcError> {
+        Err(SdmmcError::ENOTIMPLEMENTED)
+    }
+
     /// Reads the current state of the SD card data lanes.
     ///
     /// This function is specifically used during voltage switching to check if the SD card
@@ -105,6 +124,15 @@ pub trait SdmmcOps {
         Err(SdmmcError::ENOTIMPLEMENTED)
     }
 
+    /// Stop the data transfer of the request in flight, DMA included, without waiting for it to finish
+    /// Used when the protocol layer gives up on a request halfway, like a write interrupted through HPI.
+    /// Once it returns, the host must not touch the data buffer any more and must be ready for the next command.
+    /// The card is not told about it, the protocol layer stops the card with CMD12 or HPI afterwards.
+    /// Without it, a write is never interrupted and runs to the end instead.
+    fn sdmmc_abort_data(&mut self) -> Result<(), SdmmcError> {
+        Err(SdmmcError::ENOTIMPLEMENTED)
+    }
+
     /// At higher clock frequencies, timing mismatches can occur between the host's sampling point and the valid data window
     /// from the SD card during read operations. This can lead to CRC errors, as the host may sample incoming data outside the
     /// stable data window, even when the SD card’s response appears normal.
@@ -116,9 +144,13 @@ pub trait SdmmcOps {
     /// signals sent from the host to the SD card. This would ensure that the SD card reliably receives data, especially
     /// at high frequencies. However, output timing tends to be more stable, and a specific function for tuning host-to-card
     /// data timing is often not implemented or needed, as seen in the Linux driver.
+    ///
+    /// For each sampling point, the host should call `tuning.send_tuning_block()`, which sends the tuning
+    /// command chosen by the protocol layer and compares the returned tuning block with the expected pattern.
+    /// An `EIO` error means the sampling point is not usable and the next one should be tried.
     fn sdmmc_execute_tuning(
         &mut self,
-        memory: *mut [u8; 64],
+        tuning: &MmcTuning,
         sleep: &mut dyn Sleep,
     ) -> Result<(), SdmmcError> {
         Err(SdmmcError::ENOTIMPLEMENTED)
@@ -51,7 +51,7 @@ fn init() -> impl Handler {
 
     // This line of code actually is very unsafe!
     // Considering the memory is stolen from the memory that has sdcard registers mapped in
-    let unsafe_stolen_memory: *mut [u8; 64] = 0xf5500000 as *mut [u8; 64];
+    let unsafe_stolen_memory: *mut [u8; 512] = 0xf5500000 as *mut [u8; 512];
     let physical_memory_addr: u64 = 0xf5500000;
 
     assert!((physical_memory_addr as usize).is_multiple_of(8));